@@ -1,33 +1,513 @@
 //! Defines the core backend API
 
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    sync::Arc,
+};
+
 use async_trait::async_trait;
+use futures::{
+    future::BoxFuture,
+    stream::{self, BoxStream},
+    StreamExt, TryStreamExt,
+};
+
+use time::{Date, OffsetDateTime};
 
 use crate::{
     error::{Error, Result},
-    public::{account::Account, transaction::Transaction},
+    public::{
+        account::{Account, AccountQuery, AccountSortField},
+        amount::Amount,
+        transaction::{Transaction, TransactionGroupBy, TransactionQuery, TransactionSortField},
+    },
 };
 
+pub mod audit;
 pub mod collection;
+pub mod export;
 pub mod id;
+#[cfg(any(test, feature = "test-util"))]
+pub mod memory;
+pub mod patch;
+pub mod query;
+pub mod store;
+#[cfg(any(test, feature = "test-util"))]
+pub mod testsuite;
 pub mod user;
 pub mod version;
 
-use collection::Collection;
+use audit::{AuditAction, AuditEntry, AuditLog, Audited, ResourceType};
+use collection::{ChangeEvent, Collection, CollectionStats};
+use export::ExportFormat;
 use id::Id;
-use user::{AccessLevel, ChangeGroup, Group, User, WithGroup};
-use version::Versioned;
+use patch::{Patch, Patchable};
+use query::{
+    boolean::BooleanExpr, GroupKey, Pagination, Query, QueryOptions, QueryValidationError,
+    Queryable, SimpleQuery, Sort, WithGroupQuery,
+};
+use store::{Collections, Store};
+use user::{AccessLevel, Capability, ChangeGroup, Group, Permissions, Role, User, WithGroup};
+use version::{Version, Versioned};
+
+/// An entry in the ledger of an account, as returned by [`Backend::account_ledger`].
+#[derive(Clone, Debug)]
+pub enum LedgerEntry {
+    /// The opening balance of the account.
+    Opening { date: Date, amount: Amount },
+    /// A transaction involving the account.
+    Transaction(Versioned<Transaction>),
+}
 
 pub struct Backend {
-    current_user: Id<user::User>,
+    current_user: Id<User>,
+    store: Arc<dyn Store>,
     users: Box<dyn Collection<User> + Send + Sync>,
     groups: Box<dyn Collection<Group> + Send + Sync>,
     accounts: Box<dyn Collection<Account> + Send + Sync>,
     transactions: Box<dyn Collection<Transaction> + Send + Sync>,
+    audit: Box<dyn AuditLog + Send + Sync>,
 }
 
 impl Backend {
-    async fn get_group_permsissions(&self, group: Id<Group>) -> Result<AccessLevel> {
+    /// Create a backend acting as `current_user`, with the collections of `store`.
+    pub fn new(store: Arc<dyn Store>, current_user: Id<User>) -> Self {
+        let collections = store.collections();
+        Self::with_collections(store, collections, current_user)
+    }
+
+    /// Create a backend acting as a different user, with the same store.
+    ///
+    /// The new backend's operations take effect immediately, even if this backend is within a
+    /// unit of work.
+    pub fn as_user(&self, user: Id<User>) -> Self {
+        Self::new(self.store.clone(), user)
+    }
+
+    /// The user the backend acts as.
+    pub fn current_user(&self) -> Id<User> {
+        self.current_user
+    }
+
+    fn with_collections(
+        store: Arc<dyn Store>,
+        collections: Collections,
+        current_user: Id<User>,
+    ) -> Self {
+        let Collections {
+            users,
+            groups,
+            accounts,
+            transactions,
+            audit,
+        } = collections;
+        Self {
+            current_user,
+            store,
+            users,
+            groups,
+            accounts,
+            transactions,
+            audit,
+        }
+    }
+
+    /// Run `f` as a single unit of work, so that either all of the operations it performs on the
+    /// backend take effect, or, if it returns an error, none of them do.
+    ///
+    /// The backend passed to `f` acts as the current user, with all operations taking place within
+    /// the unit of work. Units of work started from within `f` are independent of this one.
+    pub async fn transaction<R, F>(&self, f: F) -> Result<R>
+    where
+        F: for<'a> FnOnce(&'a mut Backend) -> BoxFuture<'a, Result<R>> + Send,
+        R: Send,
+    {
+        let unit = self.store.begin().await?;
+        let mut backend =
+            Self::with_collections(self.store.clone(), unit.collections(), self.current_user);
+        let result = f(&mut backend).await;
+        drop(backend);
+        match result {
+            Ok(value) => {
+                unit.commit().await?;
+                Ok(value)
+            }
+            Err(err) => {
+                if let Err(abort_err) = unit.abort().await {
+                    log::error!("Unable to abort unit of work: {abort_err}");
+                }
+                Err(err)
+            }
+        }
+    }
+
+    /// Run `f` against a consistent snapshot of the backend, so that all of the reads it performs
+    /// observe the same state, regardless of any concurrent writes.
+    ///
+    /// The backend passed to `f` acts as the current user, and can only be read from.
+    pub async fn with_snapshot<R, F>(&self, f: F) -> Result<R>
+    where
+        F: for<'a> FnOnce(&'a Backend) -> BoxFuture<'a, Result<R>> + Send,
+        R: Send,
+    {
+        let unit = self.store.snapshot().await?;
+        let backend =
+            Self::with_collections(self.store.clone(), unit.collections(), self.current_user);
+        let result = f(&backend).await;
+        drop(backend);
+        if let Err(abort_err) = unit.abort().await {
+            log::error!("Unable to end snapshot: {abort_err}");
+        }
+        result
+    }
+
+    /// Apply `f` to the object with id and update it, retrying up to `max_attempts` times if
+    /// there are conflicting edits.
+    ///
+    /// See [`collection::update_with`] for details.
+    pub async fn update_with<T, F>(
+        &mut self,
+        id: Id<T>,
+        max_attempts: usize,
+        f: F,
+    ) -> Result<Versioned<T>>
+    where
+        Self: Collection<T>,
+        T: Clone,
+        F: FnMut(&mut T),
+    {
+        collection::update_with(self, id, max_attempts, f).await
+    }
+
+    /// Get the balance of an account as of the end of `as_of`, which is its opening balance plus
+    /// the transactions dated from its opening date up to and including `as_of`
+    ///
+    /// Transactions dated before the opening date are already accounted for by the opening
+    /// balance, so they are excluded.
+    pub async fn account_balance(&self, account: Id<Account>, as_of: Date) -> Result<Amount> {
+        let WithGroup {
+            group,
+            object: account_object,
+        } = self.accounts.get(account).await?.ok_or(Error::NotFound)?;
+        if self.get_group_permsissions(group).await? < AccessLevel::Read {
+            return Err(Error::Unauthorized);
+        }
+        let account_object = account_object.object;
+        let opening_balance = account_object.opening_balance_as_of(as_of);
+        let dates = match account_object.opening_date {
+            // no transactions are counted, and the range would be empty
+            Some(opening) if opening > as_of => return Ok(opening_balance),
+            Some(opening) => SimpleQuery::range(opening..=as_of),
+            None => SimpleQuery::range(..=as_of),
+        };
+        // all transactions involving the account are in the same group as the account
+        let query = [
+            WithGroupQuery::Group(vec![group]),
+            WithGroupQuery::Other(TransactionQuery::Account(SimpleQuery::eq(account))),
+            WithGroupQuery::Other(TransactionQuery::Date(dates)),
+        ];
+        let balance = self
+            .transactions
+            .sum_amounts(&query, TransactionGroupBy::Account, QueryOptions::default())
+            .await?
+            .into_iter()
+            .find(|&(id, _)| id == account)
+            .map_or(Amount::ZERO, |(_, balance)| balance);
+        Ok(opening_balance + balance)
+    }
+
+    /// Get the ledger of an account: its opening balance followed by the transactions involving
+    /// it, ordered by date, each with the balance of the account after the entry
+    ///
+    /// The opening balance is dated at the account's opening date, or if it has none, at the date
+    /// of its earliest transaction, and is omitted if the account has neither. Transactions dated
+    /// before the opening date are already accounted for by the opening balance, so they are
+    /// excluded. Transactions on the same date are ordered by id, so the running balances are
+    /// consistent between calls.
+    pub async fn account_ledger(&self, account: Id<Account>) -> Result<Vec<(LedgerEntry, Amount)>> {
+        let WithGroup {
+            group,
+            object: account_object,
+        } = self.accounts.get(account).await?.ok_or(Error::NotFound)?;
+        let account_object = account_object.object;
+        if self.get_group_permsissions(group).await? < AccessLevel::Read {
+            return Err(Error::Unauthorized);
+        }
+        // all transactions involving the account are in the same group as the account
+        let mut query = vec![
+            WithGroupQuery::Group(vec![group]),
+            WithGroupQuery::Other(TransactionQuery::Account(SimpleQuery::eq(account))),
+        ];
+        if let Some(opening) = account_object.opening_date {
+            query.push(WithGroupQuery::Other(TransactionQuery::Date(
+                SimpleQuery::range(opening..),
+            )));
+        }
+        let sort = [Sort {
+            field: TransactionSortField::Date,
+            ascending: true,
+        }];
+        let transactions = self
+            .transactions
+            .query_sorted(&query, &sort, QueryOptions::default())
+            .await?;
+        let opening_date = account_object.opening_date.or_else(|| {
+            transactions
+                .first()
+                .map(|transaction| transaction.object.object.date)
+        });
+        let mut balance = account_object.opening_balance;
+        let opening = opening_date.map(|date| {
+            let entry = LedgerEntry::Opening {
+                date,
+                amount: account_object.opening_balance,
+            };
+            (entry, balance)
+        });
+        Ok(opening
+            .into_iter()
+            .chain(transactions.into_iter().map(|transaction| {
+                let amount = transaction.object.object.amounts.get(&account).copied();
+                balance = balance + amount.unwrap_or(Amount::ZERO);
+                (LedgerEntry::Transaction(transaction.object), balance)
+            }))
+            .collect())
+    }
+
+    /// Get all of the accounts in a group, ordered by name, with their balances as of the end of
+    /// `as_of`, as given by [`account_balance`](Self::account_balance)
+    pub async fn accounts_with_balances(
+        &self,
+        group: Id<Group>,
+        as_of: Date,
+    ) -> Result<Vec<(Versioned<Account>, Amount)>> {
+        if self.get_group_permsissions(group).await? < AccessLevel::Read {
+            return Err(Error::Unauthorized);
+        }
+        let sort = [Sort {
+            field: AccountSortField::Name,
+            ascending: true,
+        }];
+        let accounts = self
+            .accounts
+            .query_sorted(
+                &[WithGroupQuery::Group(vec![group])],
+                &sort,
+                QueryOptions::default(),
+            )
+            .await?;
+        // the transactions of all of the accounts are summed together, and then the ones dated
+        // before the opening dates of their accounts are taken back out, so the number of queries
+        // doesn't grow with the number of accounts
+        let query = [
+            WithGroupQuery::Group(vec![group]),
+            WithGroupQuery::Other(TransactionQuery::Date(SimpleQuery::range(..=as_of))),
+        ];
+        let mut balances = self
+            .transactions
+            .sum_amounts(&query, TransactionGroupBy::Account, QueryOptions::default())
+            .await?
+            .into_iter()
+            .collect::<BTreeMap<_, _>>();
+        let opening_dates = accounts
+            .iter()
+            .filter_map(|account| {
+                let Versioned { id, object, .. } = &account.object;
+                object.opening_date.map(|opening| (*id, opening))
+            })
+            .collect::<BTreeMap<_, _>>();
+        if let Some(&latest) = opening_dates.values().max() {
+            let query = [
+                WithGroupQuery::Group(vec![group]),
+                WithGroupQuery::Other(TransactionQuery::Account(SimpleQuery::in_(
+                    opening_dates.keys().copied().collect(),
+                ))),
+                WithGroupQuery::Other(TransactionQuery::Date(
+                    SimpleQuery::range(..latest).and_le(as_of),
+                )),
+            ];
+            for transaction in self
+                .transactions
+                .query(&query, QueryOptions::default())
+                .await?
+            {
+                let transaction = &transaction.object.object;
+                for (account, amount) in transaction.amounts.iter() {
+                    if opening_dates
+                        .get(account)
+                        .is_some_and(|&opening| transaction.date < opening)
+                    {
+                        let balance = balances.entry(*account).or_insert(Amount::ZERO);
+                        *balance = *balance - *amount;
+                    }
+                }
+            }
+        }
+        Ok(accounts
+            .into_iter()
+            .map(|account| {
+                let Versioned { id, object, .. } = &account.object;
+                let balance = balances.get(id).copied().unwrap_or(Amount::ZERO);
+                let balance = object.opening_balance_as_of(as_of) + balance;
+                (account.object, balance)
+            })
+            .collect())
+    }
+
+    /// Find the account in a group with the name, ignoring case, as when checking whether a name
+    /// is already taken.
+    ///
+    /// Names aren't required to be unique, so if several accounts have the name, the one with the
+    /// lowest id is returned.
+    pub async fn find_account_by_name(
+        &self,
+        group: Id<Group>,
+        name: &str,
+    ) -> Result<Option<Id<Account>>> {
+        if self.get_group_permsissions(group).await? < AccessLevel::Read {
+            return Err(Error::Unauthorized);
+        }
+        let query = [
+            WithGroupQuery::Group(vec![group]),
+            WithGroupQuery::Other(AccountQuery::NameExact(name.into())),
+        ];
+        Ok(self
+            .accounts
+            .query_ids(&query, QueryOptions::default())
+            .await?
+            .into_iter()
+            .min())
+    }
+
+    /// Export all of the accounts and transactions in a group, in the format.
+    ///
+    /// Transactions are written as they are read from the collection, rather than all being
+    /// collected first.
+    pub async fn export_group(&self, group: Id<Group>, format: ExportFormat) -> Result<String> {
+        if self.get_group_permsissions(group).await? < AccessLevel::Read {
+            return Err(Error::Unauthorized);
+        }
+        let mut exporter = format.exporter();
+        let accounts = self
+            .accounts
+            .query(
+                &[WithGroupQuery::Group(vec![group])],
+                QueryOptions::default(),
+            )
+            .await?;
+        for account in accounts {
+            exporter.account(&account.object)?;
+        }
+        let query = [WithGroupQuery::Group(vec![group])];
+        let mut transactions = self
+            .transactions
+            .query_stream(&query, QueryOptions::default());
+        while let Some(transaction) = transactions.try_next().await? {
+            exporter.transaction(&transaction.object)?;
+        }
+        exporter.finish()
+    }
+
+    /// Make `new_owner` an owner of the group, with write access, returning the group's new
+    /// version.
+    ///
+    /// If `demote` is set, the current user is demoted to a member, so they can no longer manage
+    /// the group's permissions. This fails with `Error::Unauthorized` unless the current user can
+    /// manage the group's permissions, with `Error::NotFound` if `new_owner` doesn't exist, and
+    /// with `Error::ConflictingEdit` if the group is modified concurrently.
+    pub async fn transfer_ownership(
+        &mut self,
+        group: Id<Group>,
+        new_owner: Id<User>,
+        demote: bool,
+    ) -> Result<Version> {
+        if !self
+            .has_capability(group, Capability::ManagePermissions)
+            .await?
+        {
+            return Err(Error::Unauthorized);
+        }
+        if !self.users.exists(new_owner).await? {
+            return Err(Error::NotFound);
+        }
+        let mut object = self.groups.get(group).await?.ok_or(Error::NotFound)?.object;
+        let permissions = &mut object.object.permissions;
+        permissions.roles.insert(new_owner, Role::Owner);
+        permissions.users.insert(new_owner, AccessLevel::Write);
+        if demote && new_owner != self.current_user {
+            // explicit write access would still grant every capability
+            permissions.roles.insert(self.current_user, Role::Member);
+            permissions.users.remove(&self.current_user);
+        }
+        // the update only succeeds if the group is still at the version that was read
+        self.update(object).await
+    }
+
+    /// Get all of the changes made to the object with id, in the order they were made.
+    ///
+    /// The current user must be able to read from the object's group, or be a superuser if the
+    /// object has been purged.
+    pub async fn audit_trail<T: Audited>(&self, id: Id<T>) -> Result<Vec<AuditEntry>> {
+        let group = match T::RESOURCE_TYPE {
+            ResourceType::User => {
+                self.get_group_including_deleted::<User>(id.transmute())
+                    .await?
+            }
+            ResourceType::Group => {
+                self.get_group_including_deleted::<Group>(id.transmute())
+                    .await?
+            }
+            ResourceType::Account => {
+                self.get_group_including_deleted::<Account>(id.transmute())
+                    .await?
+            }
+            ResourceType::Transaction => {
+                self.get_group_including_deleted::<Transaction>(id.transmute())
+                    .await?
+            }
+        };
+        let authorized = match group {
+            Some(group) => self.get_group_permsissions(group).await? >= AccessLevel::Read,
+            None => self.is_superuser().await?,
+        };
+        if !authorized {
+            return Err(Error::Unauthorized);
+        }
+        self.audit.trail(T::RESOURCE_TYPE, id.transmute()).await
+    }
+
+    /// Record a change to the object with id, made by the current user, in the audit log
+    async fn record_change<T: Audited>(
+        &mut self,
+        action: AuditAction,
+        id: Id<T>,
+        old_version: Option<Version>,
+        new_version: Option<Version>,
+    ) -> Result<()> {
+        let entry = AuditEntry {
+            actor: self.current_user,
+            action,
+            resource_type: T::RESOURCE_TYPE,
+            resource_id: id.transmute(),
+            timestamp: OffsetDateTime::now_utc(),
+            old_version,
+            new_version,
+        };
+        self.audit.record(entry).await
+    }
+
+    /// Returns whether the current user is a superuser
+    async fn is_superuser(&self) -> Result<bool> {
         Ok(self
+            .users
+            .get(self.current_user)
+            .await?
+            .is_some_and(|user| user.object.object.is_superuser))
+    }
+
+    /// Get the permissions of a group and each of its ancestors, starting with the group itself
+    async fn get_permissions_chain(&self, group: Id<Group>) -> Result<Vec<Permissions>> {
+        let mut current = self
             .groups
             .get(group)
             .await
@@ -38,9 +518,104 @@ impl Backend {
                 Error::Unauthorized
             })?
             .object
-            .object
-            .permissions
-            .get(self.current_user))
+            .object;
+        let mut chain = Vec::new();
+        for _ in 0..=MAX_GROUP_DEPTH {
+            let parent = current.parent;
+            chain.push(current.permissions);
+            let Some(parent) = parent else {
+                return Ok(chain);
+            };
+            // a parent which has been deleted no longer grants access
+            let Some(parent) = self.groups.get(parent).await? else {
+                return Ok(chain);
+            };
+            current = parent.object.object;
+        }
+        Err(Error::backend(GroupTooDeep(group)))
+    }
+
+    /// Get the current user's access level to a group, which is the highest level granted by the
+    /// group or any of its ancestors
+    async fn get_group_permsissions(&self, group: Id<Group>) -> Result<AccessLevel> {
+        Ok(self
+            .get_permissions_chain(group)
+            .await?
+            .iter()
+            .map(|permissions| permissions.get(self.current_user))
+            .max()
+            .unwrap_or_default())
+    }
+
+    /// Returns whether the current user has a capability in a group, granted by the group or any
+    /// of its ancestors
+    async fn has_capability(&self, group: Id<Group>, capability: Capability) -> Result<bool> {
+        Ok(self
+            .get_permissions_chain(group)
+            .await?
+            .iter()
+            .any(|permissions| permissions.allows(self.current_user, capability)))
+    }
+
+    /// Get the ids of all groups the current user can read from
+    async fn readable_groups(&self) -> Result<Vec<Id<Group>>> {
+        let groups = self
+            .groups
+            .query(&[], QueryOptions::default())
+            .await?
+            .into_iter()
+            .map(|group| (group.object.id, group.object.object))
+            .collect::<BTreeMap<_, _>>();
+        let mut readable = Vec::new();
+        for &id in groups.keys() {
+            // a cycle in one part of the hierarchy shouldn't prevent reading from any other group
+            match inherited_access(&groups, id, self.current_user) {
+                Ok(access) if access >= AccessLevel::Read => readable.push(id),
+                Ok(_) => {}
+                Err(err) => log::error!("Unable to determine access to {id:?}: {err}"),
+            }
+        }
+        Ok(readable)
+    }
+
+    /// Restrict a query to objects in groups the current user can read from
+    async fn restrict_to_readable<T: Queryable>(
+        &self,
+        query: &[WithGroupQuery<T>],
+    ) -> Result<Vec<WithGroupQuery<T>>> {
+        for clause in query {
+            clause.validate().map_err(rejected_query)?;
+        }
+        let mut query = query.to_vec();
+        query.push(WithGroupQuery::Group(self.readable_groups().await?));
+        Ok(query)
+    }
+
+    /// Get the group of the object with id, whether or not it has been deleted
+    async fn get_group_including_deleted<T>(&self, id: Id<T>) -> Result<Option<Id<Group>>>
+    where
+        Self: HasCollection<T>,
+    {
+        let collection = self.get_collection();
+        Ok(match collection.get_group(id).await? {
+            Some(group) => Some(group),
+            None => collection.get_deleted(id).await?.map(|object| object.group),
+        })
+    }
+
+    /// Get the version of the object with id, whether or not it has been deleted
+    async fn get_version_including_deleted<T>(&self, id: Id<T>) -> Result<Option<Version>>
+    where
+        Self: HasCollection<T>,
+    {
+        let collection = self.get_collection();
+        Ok(match collection.get_version(id).await? {
+            Some(version) => Some(version),
+            None => collection
+                .get_deleted(id)
+                .await?
+                .map(|object| object.object.version),
+        })
     }
 
     async fn get_group_of<T>(&self, id: Id<T>) -> Result<Id<Group>>
@@ -48,13 +623,49 @@ impl Backend {
         Self: HasCollection<T>,
     {
         self.get_collection()
-            .get(id)
+            .get_group(id)
             .await?
             .ok_or(Error::NotFound)
-            .map(|result| result.group)
     }
 }
 
+/// The maximum depth of nesting of groups, beyond which a group's ancestors are assumed to form a
+/// cycle
+const MAX_GROUP_DEPTH: usize = 32;
+
+/// The error when the ancestors of a group are nested too deeply, or form a cycle.
+#[derive(Debug, thiserror::Error)]
+#[error("The ancestors of {0:?} are nested more than {MAX_GROUP_DEPTH} deep, or form a cycle")]
+struct GroupTooDeep(Id<Group>);
+
+/// Get the access level of `user` to the group with id, which is the highest level granted by the
+/// group or any of its ancestors in `groups`
+fn inherited_access(
+    groups: &BTreeMap<Id<Group>, Group>,
+    id: Id<Group>,
+    user: Id<User>,
+) -> Result<AccessLevel> {
+    let mut access = AccessLevel::None;
+    let mut next = Some(id);
+    for _ in 0..=MAX_GROUP_DEPTH {
+        // a parent which has been deleted no longer grants access
+        let Some(group) = next.and_then(|next| groups.get(&next)) else {
+            return Ok(access);
+        };
+        access = access.max(group.permissions.get(user));
+        next = group.parent;
+    }
+    Err(Error::backend(GroupTooDeep(id)))
+}
+
+/// The error for a query which failed validation.
+///
+/// Such queries are usually bugs in the caller, so they are logged as well as being rejected.
+fn rejected_query(err: QueryValidationError) -> Error {
+    log::warn!("Rejected invalid query: {err}");
+    Error::InvalidQuery(err)
+}
+
 trait HasCollection<T> {
     fn get_collection(&self) -> &(dyn Collection<T> + Send + Sync);
     fn get_mut_collection(&mut self) -> &mut (dyn Collection<T> + Send + Sync);
@@ -82,19 +693,227 @@ impl_has_collection! {
     transactions: Transaction,
 }
 
+/// Validation of objects before they are written to the backend.
+#[async_trait]
+trait Validate<T> {
+    /// Check that the object is valid as a member of `group`.
+    async fn validate(&self, group: Id<Group>, object: &T) -> Result<()>;
+}
+
+#[async_trait]
+impl Validate<User> for Backend {
+    async fn validate(&self, _group: Id<Group>, _user: &User) -> Result<()> {
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl Validate<Group> for Backend {
+    async fn validate(&self, _group: Id<Group>, _object: &Group) -> Result<()> {
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl Validate<Account> for Backend {
+    async fn validate(&self, _group: Id<Group>, _account: &Account) -> Result<()> {
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl Validate<Transaction> for Backend {
+    async fn validate(&self, group: Id<Group>, transaction: &Transaction) -> Result<()> {
+        let mut currencies = BTreeMap::new();
+        for &account in transaction.amounts.keys() {
+            let account_object = self.accounts.get(account).await?.ok_or(Error::NotFound)?;
+            if account_object.group != group {
+                return Err(Error::TransactionGroup);
+            }
+            let currency = account_object.object.object.currency;
+            if currency.is_some_and(|currency| {
+                transaction.amounts[&account].places() > currency.exponent()
+            }) {
+                return Err(Error::AmountTooPrecise);
+            }
+            currencies.insert(account, currency);
+        }
+        if !transaction.is_balanced_by_currency(|account| currencies[&account]) {
+            return Err(Error::UnbalancedTransaction);
+        }
+        // a balanced transaction's credits total the same as its debits, so are also in range
+        if transaction.total_debit().is_none() {
+            return Err(Error::AmountOutOfRange);
+        }
+        Ok(())
+    }
+}
+
+/// Checks of the objects referring to an object, before it is moved out of its group or deleted.
+///
+/// Both checks fail with `Error::StillReferenced` if objects which would be left invalid still
+/// refer to the object.
+#[async_trait]
+trait CheckReferences<T> {
+    /// Check that the object with id can be moved out of `group`, which it is currently in.
+    async fn check_move(&self, group: Id<Group>, id: Id<T>) -> Result<()>;
+
+    /// Check that the object with id, in `group`, can be deleted.
+    async fn check_delete(&self, group: Id<Group>, id: Id<T>) -> Result<()>;
+}
+
+#[async_trait]
+impl CheckReferences<User> for Backend {
+    async fn check_move(&self, _group: Id<Group>, _id: Id<User>) -> Result<()> {
+        Ok(())
+    }
+
+    async fn check_delete(&self, _group: Id<Group>, _id: Id<User>) -> Result<()> {
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl CheckReferences<Group> for Backend {
+    async fn check_move(&self, _group: Id<Group>, _id: Id<Group>) -> Result<()> {
+        Ok(())
+    }
+
+    async fn check_delete(&self, _group: Id<Group>, _id: Id<Group>) -> Result<()> {
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl CheckReferences<Account> for Backend {
+    async fn check_move(&self, group: Id<Group>, id: Id<Account>) -> Result<()> {
+        // transactions can only involve accounts in their own group, so moving an account out
+        // from under its transactions would leave them invalid
+        self.check_account_unused(group, id).await
+    }
+
+    async fn check_delete(&self, group: Id<Group>, id: Id<Account>) -> Result<()> {
+        self.check_account_unused(group, id).await
+    }
+}
+
+impl Backend {
+    /// Check that no transactions in `group` involve the account.
+    ///
+    /// Deleted transactions aren't checked, since restoring them checks that their accounts
+    /// still exist in their group.
+    async fn check_account_unused(&self, group: Id<Group>, id: Id<Account>) -> Result<()> {
+        let query = [
+            WithGroupQuery::Group(vec![group]),
+            WithGroupQuery::Other(TransactionQuery::Account(SimpleQuery::eq(id))),
+        ];
+        let transactions = self
+            .transactions
+            .query_ids(&query, QueryOptions::default())
+            .await?;
+        if !transactions.is_empty() {
+            let ids = transactions.into_iter().map(Id::transmute).collect();
+            return Err(Error::StillReferenced(ids));
+        }
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl CheckReferences<Transaction> for Backend {
+    async fn check_move(&self, _group: Id<Group>, _id: Id<Transaction>) -> Result<()> {
+        Ok(())
+    }
+
+    async fn check_delete(&self, _group: Id<Group>, _id: Id<Transaction>) -> Result<()> {
+        Ok(())
+    }
+}
+
+/// Authorization of changes to objects which need more than write access.
+#[async_trait]
+trait AuthorizeChange<T> {
+    /// Check that the current user can replace the object with id, if it exists, with `object`, as
+    /// a member of `group`.
+    async fn authorize_change(&self, group: Id<Group>, id: Id<T>, object: &T) -> Result<()>;
+}
+
+#[async_trait]
+impl AuthorizeChange<User> for Backend {
+    async fn authorize_change(&self, _group: Id<Group>, _id: Id<User>, _user: &User) -> Result<()> {
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl AuthorizeChange<Group> for Backend {
+    async fn authorize_change(
+        &self,
+        group: Id<Group>,
+        id: Id<Group>,
+        object: &Group,
+    ) -> Result<()> {
+        let current = match self.groups.get(id).await? {
+            Some(current) => Some(current),
+            None => self.groups.get_deleted(id).await?,
+        };
+        // the parent of a group grants permissions on it too
+        let changes_permissions = current.is_some_and(|current| {
+            let current = current.object.object;
+            current.permissions != object.permissions || current.parent != object.parent
+        });
+        if changes_permissions
+            && !self
+                .has_capability(group, Capability::ManagePermissions)
+                .await?
+        {
+            return Err(Error::Unauthorized);
+        }
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl AuthorizeChange<Account> for Backend {
+    async fn authorize_change(
+        &self,
+        _group: Id<Group>,
+        _id: Id<Account>,
+        _account: &Account,
+    ) -> Result<()> {
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl AuthorizeChange<Transaction> for Backend {
+    async fn authorize_change(
+        &self,
+        _group: Id<Group>,
+        _id: Id<Transaction>,
+        _transaction: &Transaction,
+    ) -> Result<()> {
+        Ok(())
+    }
+}
+
 #[async_trait]
 impl<T> Collection<T> for Backend
 where
-    Backend: HasCollection<T>,
-    T: Send + 'static,
+    Backend: HasCollection<T> + Validate<T> + AuthorizeChange<T> + CheckReferences<T>,
+    T: Audited + Send + Sync + 'static,
 {
     /// Create a new object
     async fn create(&mut self, object: WithGroup<T>) -> Result<Id<T>> {
         if self.get_group_permsissions(object.group).await? < AccessLevel::Write {
             Err(Error::Unauthorized)
         } else {
-            // TODO: validation
-            self.get_mut_collection().create(object).await
+            self.validate(object.group, &object.object).await?;
+            let id = self.get_mut_collection().create(object).await?;
+            let version = self.get_collection().get_version(id).await?;
+            self.record_change(AuditAction::Create, id, None, version)
+                .await?;
+            Ok(id)
         }
     }
 
@@ -112,27 +931,295 @@ where
         }
     }
 
+    /// Check whether an object with the id exists
+    async fn exists(&self, id: Id<T>) -> Result<bool> {
+        Ok(self.get_group(id).await?.is_some())
+    }
+
+    /// Get the group of the object with id
+    async fn get_group(&self, id: Id<T>) -> Result<Option<Id<Group>>> {
+        let maybe_group = self.get_collection().get_group(id).await?;
+        if let Some(group) = maybe_group {
+            if self.get_group_permsissions(group).await? < AccessLevel::Read {
+                return Err(Error::Unauthorized);
+            }
+        }
+        Ok(maybe_group)
+    }
+
+    /// Get the current version of the object with id
+    async fn get_version(&self, id: Id<T>) -> Result<Option<Version>> {
+        if let Some(group) = self.get_collection().get_group(id).await? {
+            if self.get_group_permsissions(group).await? < AccessLevel::Read {
+                return Err(Error::Unauthorized);
+            }
+        }
+        self.get_collection().get_version(id).await
+    }
+
+    /// Get all objects with any of the ids.
+    ///
+    /// Ids which don't exist are omitted from the result.
+    async fn get_many(&self, ids: &[Id<T>]) -> Result<Vec<WithGroup<Versioned<T>>>> {
+        let objects = self.get_collection().get_many(ids).await?;
+        let groups = objects
+            .iter()
+            .map(|object| object.group)
+            .collect::<BTreeSet<_>>();
+        for group in groups {
+            if self.get_group_permsissions(group).await? < AccessLevel::Read {
+                return Err(Error::Unauthorized);
+            }
+        }
+        Ok(objects)
+    }
+
     /// Attempt to apply an update to the object.
     ///
     /// If there are conflicting edits, this will fail with `Error::ConflictingEdit`
-    async fn update(&mut self, object: Versioned<T>) -> Result<()> {
+    async fn update(&mut self, object: Versioned<T>) -> Result<Version> {
         let group = self.get_group_of(object.id).await?;
         if self.get_group_permsissions(group).await? < AccessLevel::Write {
             Err(Error::Unauthorized)
         } else {
-            // TODO: validation
-            self.get_mut_collection().update(object).await
+            self.authorize_change(group, object.id, &object.object)
+                .await?;
+            self.validate(group, &object.object).await?;
+            let (id, old_version) = (object.id, object.version);
+            let version = self.get_mut_collection().update(object).await?;
+            self.record_change(AuditAction::Update, id, Some(old_version), Some(version))
+                .await?;
+            Ok(version)
+        }
+    }
+
+    /// Attempt to apply a partial update to the object.
+    ///
+    /// If there are conflicting edits, this will fail with `Error::ConflictingEdit`
+    async fn patch(&mut self, id: Id<T>, version: Version, patch: T::Patch) -> Result<Version>
+    where
+        T: Patchable,
+    {
+        let mut object = self
+            .get_collection()
+            .get(id)
+            .await?
+            .ok_or(Error::NotFound)?;
+        if self.get_group_permsissions(object.group).await? < AccessLevel::Write {
+            return Err(Error::Unauthorized);
+        }
+        // the patch is validated against the current version, so if that has already changed,
+        // the patched object might not be what the caller expects
+        if object.object.version != version {
+            return Err(Error::ConflictingEdit);
+        }
+        patch.apply(&mut object.object.object);
+        self.authorize_change(object.group, id, &object.object.object)
+            .await?;
+        self.validate(object.group, &object.object.object).await?;
+        let new_version = self.get_mut_collection().patch(id, version, patch).await?;
+        self.record_change(AuditAction::Update, id, Some(version), Some(new_version))
+            .await?;
+        Ok(new_version)
+    }
+
+    /// Create the object with id if it doesn't exist, or replace it if it does, returning its new
+    /// version
+    async fn upsert(&mut self, id: Id<T>, object: WithGroup<T>) -> Result<Version> {
+        // replacing a deleted object also restores it, so it must be writable too
+        if let Some(old_group) = self.get_group_including_deleted(id).await? {
+            if self.get_group_permsissions(old_group).await? < AccessLevel::Write {
+                return Err(Error::Unauthorized);
+            }
+        }
+        if self.get_group_permsissions(object.group).await? < AccessLevel::Write {
+            Err(Error::Unauthorized)
+        } else {
+            self.authorize_change(object.group, id, &object.object)
+                .await?;
+            self.validate(object.group, &object.object).await?;
+            let old_version = self.get_version_including_deleted(id).await?;
+            let version = self.get_mut_collection().upsert(id, object).await?;
+            let action = if old_version.is_some() {
+                AuditAction::Update
+            } else {
+                AuditAction::Create
+            };
+            self.record_change(action, id, old_version, Some(version))
+                .await?;
+            Ok(version)
+        }
+    }
+
+    /// Get the previous versions of the object with id, oldest first
+    async fn history(&self, id: Id<T>) -> Result<Vec<WithGroup<Versioned<T>>>> {
+        let history = self.get_collection().history(id).await?;
+        let groups = history
+            .iter()
+            .map(|object| object.group)
+            .collect::<BTreeSet<_>>();
+        for group in groups {
+            if self.get_group_permsissions(group).await? < AccessLevel::Read {
+                return Err(Error::Unauthorized);
+            }
+        }
+        Ok(history)
+    }
+
+    /// Restore a previous version of the object with id, as a new edit, returning its new version
+    async fn revert(&mut self, id: Id<T>, version: Version) -> Result<Version> {
+        let previous = self
+            .get_collection()
+            .history(id)
+            .await?
+            .into_iter()
+            .find(|object| object.object.version == version)
+            .ok_or(Error::NotFound)?;
+        // a deleted object is recreated in the group it was deleted from
+        let group = self
+            .get_group_including_deleted(id)
+            .await?
+            .ok_or(Error::NotFound)?;
+        if self.get_group_permsissions(group).await? < AccessLevel::Write {
+            Err(Error::Unauthorized)
+        } else {
+            self.authorize_change(group, id, &previous.object.object)
+                .await?;
+            self.validate(group, &previous.object.object).await?;
+            let old_version = self.get_version_including_deleted(id).await?;
+            let new_version = self.get_mut_collection().revert(id, version).await?;
+            self.record_change(AuditAction::Update, id, old_version, Some(new_version))
+                .await?;
+            Ok(new_version)
         }
     }
 
     /// Delete object with id
     async fn delete(&mut self, id: Id<T>) -> Result<()> {
         let group = self.get_group_of(id).await?;
-        if self.get_group_permsissions(group).await? < AccessLevel::Write {
+        if !self.has_capability(group, Capability::Delete).await? {
+            Err(Error::Unauthorized)
+        } else {
+            self.check_delete(group, id).await?;
+            let version = self.get_collection().get_version(id).await?;
+            self.get_mut_collection().delete(id).await?;
+            self.record_change(AuditAction::Delete, id, version, version)
+                .await
+        }
+    }
+
+    /// Delete all objects matching all of the queries, returning the number of objects deleted.
+    ///
+    /// This fails with `Error::Unauthorized` unless the current user can write to every group
+    /// containing a matching object.
+    async fn delete_many(&mut self, query: &[WithGroupQuery<T>]) -> Result<usize>
+    where
+        T: Queryable,
+    {
+        for clause in query {
+            clause.validate().map_err(rejected_query)?;
+        }
+        let objects = self
+            .get_collection()
+            .query(query, QueryOptions::default())
+            .await?;
+        if objects.is_empty() {
+            return Ok(0);
+        }
+        let groups = objects
+            .iter()
+            .map(|object| object.group)
+            .collect::<BTreeSet<_>>();
+        for &group in &groups {
+            if !self.has_capability(group, Capability::Delete).await? {
+                return Err(Error::Unauthorized);
+            }
+        }
+        // only delete the objects that were checked, and only while they are still in the groups
+        // that were checked, rather than whatever matches the query by now
+        let query = [
+            WithGroupQuery::Ids(objects.iter().map(|object| object.object.id).collect()),
+            WithGroupQuery::Group(groups.into_iter().collect()),
+        ];
+        for object in &objects {
+            self.check_delete(object.group, object.object.id).await?;
+        }
+        let count = self.get_mut_collection().delete_many(&query).await?;
+        // objects which were deleted by someone else in the meantime are still logged as deleted
+        // here, which is the best that can be done without fetching them again
+        for object in objects {
+            let WithGroup {
+                object: Versioned { id, version, .. },
+                ..
+            } = object;
+            self.record_change(AuditAction::Delete, id, Some(version), Some(version))
+                .await?;
+        }
+        Ok(count)
+    }
+
+    /// Get the deleted object with id
+    async fn get_deleted(&self, id: Id<T>) -> Result<Option<WithGroup<Versioned<T>>>> {
+        let maybe_object = self.get_collection().get_deleted(id).await?;
+        if let Some(object) = &maybe_object {
+            if self.get_group_permsissions(object.group).await? < AccessLevel::Read {
+                return Err(Error::Unauthorized);
+            }
+        }
+        Ok(maybe_object)
+    }
+
+    /// Get all deleted objects matching all of the queries, in groups the current user can read
+    /// from
+    async fn query_deleted(
+        &self,
+        query: &[WithGroupQuery<T>],
+    ) -> Result<Vec<WithGroup<Versioned<T>>>>
+    where
+        T: Queryable,
+    {
+        let query = self.restrict_to_readable(query).await?;
+        self.get_collection().query_deleted(&query).await
+    }
+
+    /// Restore the deleted object with id
+    async fn restore(&mut self, id: Id<T>) -> Result<()> {
+        let object = self
+            .get_collection()
+            .get_deleted(id)
+            .await?
+            .ok_or(Error::NotFound)?;
+        if !self
+            .has_capability(object.group, Capability::Delete)
+            .await?
+        {
+            Err(Error::Unauthorized)
+        } else {
+            // anything the object refers to may have been deleted in the meantime
+            self.validate(object.group, &object.object.object).await?;
+            self.get_mut_collection().restore(id).await?;
+            let version = Some(object.object.version);
+            self.record_change(AuditAction::Restore, id, version, version)
+                .await
+        }
+    }
+
+    /// Permanently remove the deleted object with id
+    async fn purge(&mut self, id: Id<T>) -> Result<()> {
+        let object = self
+            .get_collection()
+            .get_deleted(id)
+            .await?
+            .ok_or(Error::NotFound)?;
+        if !self
+            .has_capability(object.group, Capability::Delete)
+            .await?
+        {
             Err(Error::Unauthorized)
         } else {
-            // TODO: validation of back-references
-            self.get_mut_collection().delete(id).await
+            self.get_mut_collection().purge(id).await?;
+            self.record_change(AuditAction::Purge, id, Some(object.object.version), None)
+                .await
         }
     }
 
@@ -141,13 +1228,698 @@ where
     where
         T: ChangeGroup,
     {
-        let old_group = self.get_group_of(id).await?;
-        if self.get_group_permsissions(old_group).await? < AccessLevel::Write
-            || self.get_group_permsissions(new_group).await? < AccessLevel::Write
+        let object = self
+            .get_collection()
+            .get(id)
+            .await?
+            .ok_or(Error::NotFound)?;
+        if !self
+            .has_capability(object.group, Capability::ChangeGroup)
+            .await?
+            || !self
+                .has_capability(new_group, Capability::ChangeGroup)
+                .await?
         {
             Err(Error::Unauthorized)
         } else {
-            self.get_mut_collection().change_group(id, new_group).await
+            // the object must also be valid in its new group, such as a transaction only
+            // involving accounts in the same group as it
+            self.validate(new_group, &object.object.object).await?;
+            if object.group != new_group {
+                self.check_move(object.group, id).await?;
+            }
+            let old_version = Some(object.object.version);
+            self.get_mut_collection()
+                .change_group(id, new_group)
+                .await?;
+            let new_version = self.get_collection().get_version(id).await?;
+            self.record_change(AuditAction::ChangeGroup, id, old_version, new_version)
+                .await
+        }
+    }
+
+    /// Move all objects with any of the ids to a different group.
+    ///
+    /// The current user must be able to change the groups of objects in the new group and in
+    /// every group the objects are currently in. Fails with `Error::NotFound` if any of the ids
+    /// don't exist, or with a validation error if any of the objects would be invalid in the new
+    /// group.
+    async fn change_group_many(&mut self, ids: &[Id<T>], new_group: Id<Group>) -> Result<()>
+    where
+        T: ChangeGroup,
+    {
+        let objects = self.get_collection().get_many(ids).await?;
+        if objects.len() < ids.iter().collect::<BTreeSet<_>>().len() {
+            return Err(Error::NotFound);
+        }
+        let mut groups = objects
+            .iter()
+            .map(|object| object.group)
+            .collect::<BTreeSet<_>>();
+        groups.insert(new_group);
+        for group in groups {
+            if !self.has_capability(group, Capability::ChangeGroup).await? {
+                return Err(Error::Unauthorized);
+            }
+        }
+        for object in &objects {
+            self.validate(new_group, &object.object.object).await?;
+            if object.group != new_group {
+                self.check_move(object.group, object.object.id).await?;
+            }
+        }
+        self.get_mut_collection()
+            .change_group_many(ids, new_group)
+            .await?;
+        let new_versions = self
+            .get_collection()
+            .get_many(ids)
+            .await?
+            .into_iter()
+            .map(|object| (object.object.id, object.object.version))
+            .collect::<BTreeMap<_, _>>();
+        for object in objects {
+            let id = object.object.id;
+            let new_version = new_versions.get(&id).copied();
+            self.record_change(
+                AuditAction::ChangeGroup,
+                id,
+                Some(object.object.version),
+                new_version,
+            )
+            .await?;
+        }
+        Ok(())
+    }
+
+    /// Get all objects matching all of the queries, in groups the current user can read from
+    async fn query(
+        &self,
+        query: &[WithGroupQuery<T>],
+        options: QueryOptions,
+    ) -> Result<Vec<WithGroup<Versioned<T>>>>
+    where
+        T: Queryable,
+    {
+        let query = self.restrict_to_readable(query).await?;
+        self.get_collection().query(&query, options).await
+    }
+
+    /// Get all objects matching the expression, in groups the current user can read from
+    async fn query_expr(
+        &self,
+        query: &BooleanExpr<WithGroupQuery<T>>,
+        options: QueryOptions,
+    ) -> Result<Vec<WithGroup<Versioned<T>>>>
+    where
+        T: Queryable,
+    {
+        query.validate().map_err(rejected_query)?;
+        let query = BooleanExpr::All(vec![
+            query.clone(),
+            BooleanExpr::Query(WithGroupQuery::Group(self.readable_groups().await?)),
+        ]);
+        self.get_collection().query_expr(&query, options).await
+    }
+
+    /// Get the ids of all objects matching all of the queries, in groups the current user can read
+    /// from
+    async fn query_ids(
+        &self,
+        query: &[WithGroupQuery<T>],
+        options: QueryOptions,
+    ) -> Result<Vec<Id<T>>>
+    where
+        T: Queryable,
+    {
+        let query = self.restrict_to_readable(query).await?;
+        self.get_collection().query_ids(&query, options).await
+    }
+
+    /// Stream all objects matching all of the queries, in groups the current user can read from
+    fn query_stream(
+        &self,
+        query: &[WithGroupQuery<T>],
+        options: QueryOptions,
+    ) -> BoxStream<'_, Result<WithGroup<Versioned<T>>>>
+    where
+        T: Queryable,
+    {
+        let query = query.to_vec();
+        stream::once(async move {
+            let query = self.restrict_to_readable(&query).await?;
+            Ok::<_, Error>(self.get_collection().query_stream(&query, options))
+        })
+        .try_flatten()
+        .boxed()
+    }
+
+    /// Watch for changes to objects matching all of the queries, in groups the current user can
+    /// read from.
+    ///
+    /// The readable groups are determined when the stream is created.
+    async fn watch(
+        &self,
+        query: &[WithGroupQuery<T>],
+    ) -> Result<BoxStream<'static, Result<ChangeEvent<T>>>>
+    where
+        T: Queryable,
+    {
+        let query = self.restrict_to_readable(query).await?;
+        self.get_collection().watch(&query).await
+    }
+
+    /// Get all objects matching all of the queries, in groups the current user can read from, in
+    /// sorted order
+    async fn query_sorted(
+        &self,
+        query: &[WithGroupQuery<T>],
+        sort: &[Sort<T>],
+        options: QueryOptions,
+    ) -> Result<Vec<WithGroup<Versioned<T>>>>
+    where
+        T: Queryable,
+    {
+        let query = self.restrict_to_readable(query).await?;
+        self.get_collection()
+            .query_sorted(&query, sort, options)
+            .await
+    }
+
+    /// Get a page of the objects matching all of the queries, in groups the current user can read
+    /// from
+    async fn query_paginated(
+        &self,
+        query: &[WithGroupQuery<T>],
+        sort: &[Sort<T>],
+        pagination: Pagination,
+        options: QueryOptions,
+    ) -> Result<Vec<WithGroup<Versioned<T>>>>
+    where
+        T: Queryable,
+    {
+        let query = self.restrict_to_readable(query).await?;
+        self.get_collection()
+            .query_paginated(&query, sort, pagination, options)
+            .await
+    }
+
+    /// Get the distinct values of a string field among the objects matching all of the queries,
+    /// in groups the current user can read from
+    async fn distinct_values(
+        &self,
+        field: T::TextField,
+        query: &[WithGroupQuery<T>],
+        options: QueryOptions,
+    ) -> Result<Vec<String>>
+    where
+        T: Queryable,
+    {
+        let query = self.restrict_to_readable(query).await?;
+        self.get_collection()
+            .distinct_values(field, &query, options)
+            .await
+    }
+
+    /// Count the objects matching all of the queries, in groups the current user can read from
+    async fn query_count(&self, query: &[WithGroupQuery<T>], options: QueryOptions) -> Result<usize>
+    where
+        T: Queryable,
+    {
+        let query = self.restrict_to_readable(query).await?;
+        self.get_collection().query_count(&query, options).await
+    }
+
+    /// Count the objects matching all of the queries, in groups the current user can read from,
+    /// grouped by the value of a field
+    async fn query_count_by(
+        &self,
+        query: &[WithGroupQuery<T>],
+        group_by: T::GroupBy,
+        options: QueryOptions,
+    ) -> Result<Vec<(GroupKey<T>, usize)>>
+    where
+        T: Queryable,
+    {
+        let query = self.restrict_to_readable(query).await?;
+        self.get_collection()
+            .query_count_by(&query, group_by, options)
+            .await
+    }
+
+    /// Sum the amounts of the objects matching all of the queries, in groups the current user can
+    /// read from, grouped by the value of a field
+    async fn sum_amounts(
+        &self,
+        query: &[WithGroupQuery<T>],
+        group_by: T::GroupBy,
+        options: QueryOptions,
+    ) -> Result<Vec<(GroupKey<T>, Amount)>>
+    where
+        T: Queryable,
+    {
+        let query = self.restrict_to_readable(query).await?;
+        self.get_collection()
+            .sum_amounts(&query, group_by, options)
+            .await
+    }
+
+    /// Get statistics about the collection, if the current user is a superuser
+    async fn stats(&self) -> Result<CollectionStats> {
+        if self.is_superuser().await? {
+            self.get_collection().stats().await
+        } else {
+            Err(Error::Unauthorized)
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use futures::executor::block_on;
+    use rust_decimal::Decimal;
+    use time::{macros::date, Date};
+
+    use super::{
+        collection::Collection,
+        id::Id,
+        memory::InMemoryStore,
+        query::{boolean::BooleanExpr, QueryOptions, SimpleQuery, WithGroupQuery},
+        store::Store,
+        user::{AccessLevel, Group, Permissions, WithGroup},
+        Backend, LedgerEntry,
+    };
+    use crate::{
+        error::Error,
+        map::Map,
+        public::{
+            account::{Account, AccountPatch, AccountType},
+            amount::{Amount, Currency},
+            transaction::{Transaction, TransactionQuery},
+        },
+    };
+
+    fn amount(value: &str) -> Amount {
+        value.parse::<Decimal>().unwrap().try_into().unwrap()
+    }
+
+    /// A backend with an empty in-memory store, acting as a user with write access to the
+    /// returned group.
+    async fn backend_with_group() -> (Backend, Id<Group>) {
+        let store = Arc::new(InMemoryStore::new());
+        let user = Id::new_random();
+        let mut users = Map::default();
+        users.insert(user, AccessLevel::Write);
+        let group = Group {
+            name: "Books".into(),
+            permissions: Permissions {
+                users,
+                roles: Map::default(),
+                default: AccessLevel::None,
+            },
+            parent: None,
+        };
+        let group = store
+            .collections()
+            .groups
+            .create(WithGroup {
+                group: Id::new_random(),
+                object: group,
+            })
+            .await
+            .unwrap();
+        (Backend::new(store, user), group)
+    }
+
+    async fn create_account(
+        backend: &mut Backend,
+        group: Id<Group>,
+        customize: impl FnOnce(&mut Account),
+    ) -> Id<Account> {
+        let mut account = Account {
+            name: "Account".into(),
+            description: String::new(),
+            account_type: AccountType::Asset,
+            opening_balance: Amount::ZERO,
+            opening_date: None,
+            currency: None,
+        };
+        customize(&mut account);
+        backend
+            .create(WithGroup {
+                group,
+                object: account,
+            })
+            .await
+            .unwrap()
+    }
+
+    async fn create_transaction(
+        backend: &mut Backend,
+        group: Id<Group>,
+        date: Date,
+        amounts: &[(Id<Account>, &str)],
+    ) -> Result<Id<Transaction>, Error> {
+        let mut map = Map::default();
+        for &(account, value) in amounts {
+            map.insert(account, amount(value));
+        }
+        let transaction = Transaction {
+            date,
+            description: String::new(),
+            amounts: map,
+        };
+        backend
+            .create(WithGroup {
+                group,
+                object: transaction,
+            })
+            .await
+    }
+
+    #[test]
+    fn transaction_amounts_fit_currency() {
+        block_on(async {
+            let (mut backend, group) = backend_with_group().await;
+            let jpy = Some("JPY".parse::<Currency>().unwrap());
+            let a = create_account(&mut backend, group, |account| account.currency = jpy).await;
+            let b = create_account(&mut backend, group, |account| account.currency = jpy).await;
+            let date = date!(2024 - 01 - 01);
+            let amounts = [(a, "0.5"), (b, "-0.5")];
+            let result = create_transaction(&mut backend, group, date, &amounts).await;
+            assert!(matches!(result, Err(Error::AmountTooPrecise)));
+            let amounts = [(a, "500"), (b, "-500")];
+            let result = create_transaction(&mut backend, group, date, &amounts).await;
+            assert!(result.is_ok());
+        });
+    }
+
+    #[test]
+    fn opening_balance() {
+        block_on(async {
+            let (mut backend, group) = backend_with_group().await;
+            let bank = create_account(&mut backend, group, |account| {
+                account.opening_balance = amount("100");
+                account.opening_date = Some(date!(2024 - 02 - 01));
+            })
+            .await;
+            let other = create_account(&mut backend, group, |_| {}).await;
+            for (date, bank_amount, other_amount) in [
+                // already included in the opening balance
+                (date!(2024 - 01 - 15), "20", "-20"),
+                (date!(2024 - 02 - 01), "5", "-5"),
+                (date!(2024 - 03 - 01), "-30", "30"),
+            ] {
+                let amounts = [(bank, bank_amount), (other, other_amount)];
+                create_transaction(&mut backend, group, date, &amounts)
+                    .await
+                    .unwrap();
+            }
+
+            let balance = |date| backend.account_balance(bank, date);
+            assert_eq!(balance(date!(2024 - 01 - 31)).await.unwrap(), Amount::ZERO);
+            assert_eq!(balance(date!(2024 - 02 - 01)).await.unwrap(), amount("105"));
+            assert_eq!(balance(date!(2024 - 03 - 01)).await.unwrap(), amount("75"));
+            // the other account has no opening date, so includes all of its transactions
+            let other_balance = backend.account_balance(other, date!(2024 - 03 - 01));
+            assert_eq!(other_balance.await.unwrap(), amount("5"));
+
+            let balances = backend
+                .accounts_with_balances(group, date!(2024 - 03 - 01))
+                .await
+                .unwrap()
+                .into_iter()
+                .map(|(account, balance)| (account.id, balance))
+                .collect::<Vec<_>>();
+            assert!(balances.contains(&(bank, amount("75"))));
+            assert!(balances.contains(&(other, amount("5"))));
+
+            let ledger = backend.account_ledger(bank).await.unwrap();
+            assert_eq!(ledger.len(), 3, "the earliest transaction is excluded");
+            assert!(matches!(
+                ledger[0],
+                (LedgerEntry::Opening { date, amount: opening }, balance)
+                    if date == date!(2024 - 02 - 01)
+                        && opening == amount("100")
+                        && balance == amount("100")
+            ));
+            let balances = ledger
+                .iter()
+                .map(|(_, balance)| *balance)
+                .collect::<Vec<_>>();
+            assert_eq!(balances, ["100", "105", "75"].map(amount));
+        });
+    }
+
+    #[test]
+    fn balances_with_opening_dates() {
+        block_on(async {
+            let (mut backend, group) = backend_with_group().await;
+            let bank = create_account(&mut backend, group, |account| {
+                account.opening_balance = amount("100");
+                account.opening_date = Some(date!(2024 - 02 - 01));
+            })
+            .await;
+            let card = create_account(&mut backend, group, |account| {
+                account.opening_balance = amount("-50");
+                account.opening_date = Some(date!(2024 - 03 - 15));
+            })
+            .await;
+            let other = create_account(&mut backend, group, |_| {}).await;
+            for (date, amounts) in [
+                (date!(2024 - 01 - 15), [(bank, "20"), (card, "-20")]),
+                (date!(2024 - 02 - 01), [(bank, "5"), (other, "-5")]),
+                (date!(2024 - 03 - 01), [(card, "-30"), (other, "30")]),
+                (date!(2024 - 03 - 15), [(bank, "-10"), (card, "10")]),
+            ] {
+                create_transaction(&mut backend, group, date, &amounts)
+                    .await
+                    .unwrap();
+            }
+
+            for as_of in [
+                date!(2024 - 01 - 15),
+                date!(2024 - 02 - 01),
+                date!(2024 - 03 - 01),
+                date!(2024 - 03 - 15),
+            ] {
+                let balances = backend.accounts_with_balances(group, as_of).await.unwrap();
+                assert_eq!(balances.len(), 3);
+                for (account, balance) in balances {
+                    let expected = backend.account_balance(account.id, as_of).await.unwrap();
+                    assert_eq!(balance, expected, "{} as of {as_of}", account.object.name);
+                }
+            }
+            let balance = |account, as_of| backend.account_balance(account, as_of);
+            let as_of = date!(2024 - 03 - 15);
+            assert_eq!(balance(bank, as_of).await.unwrap(), amount("95"));
+            assert_eq!(balance(card, as_of).await.unwrap(), amount("-40"));
+            assert_eq!(balance(other, as_of).await.unwrap(), amount("25"));
+        });
+    }
+
+    #[test]
+    fn clear_account_opening_date_and_currency() {
+        block_on(async {
+            let (mut backend, group) = backend_with_group().await;
+            let account = create_account(&mut backend, group, |account| {
+                account.opening_date = Some(date!(2024 - 02 - 01));
+                account.currency = Some("JPY".parse().unwrap());
+            })
+            .await;
+            let patch: AccountPatch =
+                serde_json::from_str(r#"{"opening_date": null, "currency": null}"#).unwrap();
+            assert_eq!(patch.opening_date, Some(None));
+            assert_eq!(patch.currency, Some(None));
+            assert_eq!(
+                serde_json::to_string(&patch).unwrap(),
+                r#"{"opening_date":null,"currency":null}"#
+            );
+            let version = Collection::<Account>::get(&backend, account)
+                .await
+                .unwrap()
+                .unwrap()
+                .object
+                .version;
+            Collection::<Account>::patch(&mut backend, account, version, patch)
+                .await
+                .unwrap();
+            let object = Collection::<Account>::get(&backend, account)
+                .await
+                .unwrap()
+                .unwrap()
+                .object
+                .object;
+            assert_eq!(object.opening_date, None);
+            assert_eq!(object.currency, None);
+
+            // a missing field leaves the value unchanged
+            let patch: AccountPatch =
+                serde_json::from_str(r#"{"opening_date": "2024-03-01"}"#).unwrap();
+            assert_eq!(patch.opening_date, Some(Some(date!(2024 - 03 - 01))));
+            assert_eq!(patch.currency, None);
+        });
+    }
+
+    #[test]
+    fn delete_many() {
+        block_on(async {
+            let (mut backend, group) = backend_with_group().await;
+            let a = create_account(&mut backend, group, |_| {}).await;
+            let b = create_account(&mut backend, group, |_| {}).await;
+            let amounts = [(a, "1"), (b, "-1")];
+            for date in [date!(2024 - 01 - 01), date!(2024 - 02 - 01)] {
+                create_transaction(&mut backend, group, date, &amounts)
+                    .await
+                    .unwrap();
+            }
+            let dated = |query| {
+                [
+                    WithGroupQuery::Group(vec![group]),
+                    WithGroupQuery::Other(TransactionQuery::Date(query)),
+                ]
+            };
+
+            let invalid = dated(SimpleQuery::default());
+            let result = Collection::<Transaction>::delete_many(&mut backend, &invalid).await;
+            assert!(matches!(result, Err(Error::InvalidQuery(_))));
+
+            let query = dated(SimpleQuery::month_of(date!(2024 - 01 - 01)));
+            let deleted = Collection::<Transaction>::delete_many(&mut backend, &query).await;
+            assert_eq!(deleted.unwrap(), 1);
+            let deleted = Collection::<Transaction>::delete_many(&mut backend, &query).await;
+            assert_eq!(deleted.unwrap(), 0, "nothing is left to delete");
+            let options = QueryOptions::default();
+            let all = [WithGroupQuery::Group(vec![group])];
+            let count = Collection::<Transaction>::query_count(&backend, &all, options).await;
+            assert_eq!(count.unwrap(), 1);
+        });
+    }
+
+    #[test]
+    fn find_account_by_name() {
+        block_on(async {
+            let (mut backend, group) = backend_with_group().await;
+            let cafe = create_account(&mut backend, group, |account| {
+                account.name = "Cafe\u{301} Noir".into();
+            })
+            .await;
+            create_account(&mut backend, group, |account| account.name = "Cafe".into()).await;
+            for name in ["Caf\u{e9} Noir", "CAF\u{c9} NOIR", "cafe\u{301} noir"] {
+                let found = backend.find_account_by_name(group, name).await.unwrap();
+                assert_eq!(found, Some(cafe), "{name:?}");
+            }
+            let found = backend
+                .find_account_by_name(group, "Caf\u{e9}")
+                .await
+                .unwrap();
+            assert_ne!(found, Some(cafe));
+            let found = backend.find_account_by_name(group, "Tea").await.unwrap();
+            assert_eq!(found, None);
+        });
+    }
+
+    #[test]
+    fn opening_balance_without_date() {
+        block_on(async {
+            let (mut backend, group) = backend_with_group().await;
+            let bank = create_account(&mut backend, group, |account| {
+                account.opening_balance = amount("10");
+            })
+            .await;
+            let other = create_account(&mut backend, group, |_| {}).await;
+            let ledger = backend.account_ledger(bank).await.unwrap();
+            assert!(
+                ledger.is_empty(),
+                "there is no date for the opening balance"
+            );
+
+            for date in [date!(2024 - 03 - 01), date!(2024 - 01 - 01)] {
+                let amounts = [(bank, "1"), (other, "-1")];
+                create_transaction(&mut backend, group, date, &amounts)
+                    .await
+                    .unwrap();
+            }
+            let ledger = backend.account_ledger(bank).await.unwrap();
+            assert!(matches!(
+                ledger[0],
+                (LedgerEntry::Opening { date, .. }, _) if date == date!(2024 - 01 - 01)
+            ));
+            let balances = ledger
+                .iter()
+                .map(|(_, balance)| *balance)
+                .collect::<Vec<_>>();
+            assert_eq!(balances, ["10", "11", "12"].map(amount));
+            let balance = backend.account_balance(bank, date!(2024 - 01 - 31));
+            assert_eq!(balance.await.unwrap(), amount("11"));
+        });
+    }
+
+    #[test]
+    fn invalid_query() {
+        block_on(async {
+            let (backend, group) = backend_with_group().await;
+            let query = [
+                WithGroupQuery::Group(vec![group]),
+                !WithGroupQuery::Other(TransactionQuery::Date(SimpleQuery::default())),
+            ];
+            let options = QueryOptions::default();
+            let result = Collection::<Transaction>::query(&backend, &query, options).await;
+            assert!(matches!(
+                result,
+                Err(Error::InvalidQuery(err)) if err.clause == ["not", "date"]
+            ));
+            let result = Collection::<Transaction>::query_count(&backend, &query, options).await;
+            assert!(matches!(result, Err(Error::InvalidQuery(_))));
+            let expr = BooleanExpr::Any(query.into_iter().map(BooleanExpr::Query).collect());
+            let result = backend.query_expr(&expr, options).await;
+            assert!(matches!(
+                result,
+                Err(Error::InvalidQuery(err)) if err.clause == ["any[1]", "not", "date"]
+            ));
+
+            let query = [WithGroupQuery::<Transaction>::Group(vec![group])];
+            assert_eq!(backend.query_count(&query, options).await.unwrap(), 0);
+        });
+    }
+
+    #[test]
+    fn transaction_amounts_in_range() {
+        block_on(async {
+            let (mut backend, group) = backend_with_group().await;
+            let accounts = [
+                create_account(&mut backend, group, |_| {}).await,
+                create_account(&mut backend, group, |_| {}).await,
+                create_account(&mut backend, group, |_| {}).await,
+                create_account(&mut backend, group, |_| {}).await,
+            ];
+            let max = Amount::from_units(i64::MAX);
+            let create = |amounts: &[Amount]| {
+                let transaction = Transaction {
+                    date: date!(2024 - 01 - 01),
+                    description: String::new(),
+                    amounts: Map(accounts
+                        .iter()
+                        .copied()
+                        .zip(amounts.iter().copied())
+                        .collect()),
+                };
+                WithGroup {
+                    group,
+                    object: transaction,
+                }
+            };
+            // these sum to zero when wrapped around in 64 bits
+            let result = backend
+                .create(create(&[max, max, Amount::from_units(2)]))
+                .await;
+            assert!(matches!(result, Err(Error::UnbalancedTransaction)));
+            let result = backend.create(create(&[max, max, -max, -max])).await;
+            assert!(matches!(result, Err(Error::AmountOutOfRange)));
+            let result = backend.create(create(&[max, -max])).await;
+            assert!(result.is_ok());
+        });
+    }
+}
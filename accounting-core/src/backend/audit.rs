@@ -0,0 +1,96 @@
+//! A log of the changes made to resources, and who made them.
+
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+use time::OffsetDateTime;
+
+use crate::{
+    backend::{
+        id::Id,
+        user::{Group, User},
+        version::Version,
+    },
+    error::Result,
+    public::{account::Account, transaction::Transaction},
+};
+
+/// A record of a single change to a resource.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct AuditEntry {
+    /// The user who made the change.
+    pub actor: Id<User>,
+    pub action: AuditAction,
+    pub resource_type: ResourceType,
+    pub resource_id: Id<AnyResource>,
+    /// When the change was made.
+    #[serde(with = "crate::serde::datetime")]
+    pub timestamp: OffsetDateTime,
+    /// The version of the resource before the change, or `None` if it didn't exist.
+    pub old_version: Option<Version>,
+    /// The version of the resource after the change, or `None` if it was purged.
+    pub new_version: Option<Version>,
+}
+
+/// The kind of change made to a resource.
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Deserialize, Serialize)]
+pub enum AuditAction {
+    /// The resource was created.
+    Create,
+    /// The resource was replaced, patched, or reverted to a previous version.
+    Update,
+    /// The resource was deleted.
+    Delete,
+    /// The resource was restored after being deleted.
+    Restore,
+    /// The resource was permanently removed after being deleted.
+    Purge,
+    /// The resource was moved to a different group.
+    ChangeGroup,
+}
+
+/// The kinds of resources whose changes are recorded.
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Deserialize, Serialize)]
+pub enum ResourceType {
+    User,
+    Group,
+    Account,
+    Transaction,
+}
+
+/// Marker type for the ids of resources of any type.
+pub enum AnyResource {}
+
+/// A type whose changes are recorded in the audit log.
+pub trait Audited {
+    const RESOURCE_TYPE: ResourceType;
+}
+
+impl Audited for User {
+    const RESOURCE_TYPE: ResourceType = ResourceType::User;
+}
+
+impl Audited for Group {
+    const RESOURCE_TYPE: ResourceType = ResourceType::Group;
+}
+
+impl Audited for Account {
+    const RESOURCE_TYPE: ResourceType = ResourceType::Account;
+}
+
+impl Audited for Transaction {
+    const RESOURCE_TYPE: ResourceType = ResourceType::Transaction;
+}
+
+/// Storage for audit entries.
+#[async_trait]
+pub trait AuditLog {
+    /// Append an entry to the log.
+    async fn record(&mut self, entry: AuditEntry) -> Result<()>;
+
+    /// Get all of the entries for the resource with id, in the order they were recorded.
+    async fn trail(
+        &self,
+        resource_type: ResourceType,
+        resource_id: Id<AnyResource>,
+    ) -> Result<Vec<AuditEntry>>;
+}
@@ -1,15 +1,60 @@
+use std::collections::BTreeMap;
+
 use async_trait::async_trait;
+use derivative::Derivative;
+use futures::stream::BoxStream;
+use time::OffsetDateTime;
 
 use crate::{
     backend::{
         id::Id,
+        patch::Patchable,
+        query::{
+            boolean::BooleanExpr, GroupKey, Pagination, QueryOptions, Queryable, Sort,
+            WithGroupQuery,
+        },
         user::{ChangeGroup, Group, WithGroup},
-        version::Versioned,
+        version::{Version, Versioned},
     },
-    error::Result,
+    error::{Error, Result},
+    public::amount::Amount,
 };
 
+/// The maximum number of values returned by [`Collection::distinct_values`].
+pub const MAX_DISTINCT_VALUES: usize = 100;
+
+/// A change to an object in a collection.
+#[derive(Derivative)]
+#[derivative(Clone(bound = ""), Copy(bound = ""), Debug(bound = ""))]
+pub enum ChangeEvent<T> {
+    /// The object was created, with the given version.
+    Created { id: Id<T>, version: Version },
+    /// The object was updated, and now has the given version.
+    Updated { id: Id<T>, version: Version },
+    /// The object was deleted.
+    Deleted { id: Id<T> },
+}
+
+/// Statistics about the contents of a collection.
+#[derive(Clone, Debug)]
+pub struct CollectionStats {
+    /// The number of objects, excluding deleted objects.
+    pub count: usize,
+    /// The number of deleted objects which have not been purged.
+    pub deleted_count: usize,
+    /// The total size of the stored objects in bytes, including deleted objects, if the backend
+    /// stores them in a serialized form.
+    pub storage_size: Option<u64>,
+    /// The size of each of the collection's indexes in bytes, by name.
+    pub index_sizes: BTreeMap<String, u64>,
+    /// When an object in the collection was last modified, or `None` if it is empty.
+    pub last_modified: Option<OffsetDateTime>,
+}
+
 /// A collection of resources
+///
+/// Every method which queries the collection takes [`QueryOptions`], whose limits apply to the
+/// whole of the query, however its results are returned.
 #[async_trait]
 pub trait Collection<T> {
     /// Create a new object
@@ -18,16 +63,354 @@ pub trait Collection<T> {
     /// Get object with id
     async fn get(&self, id: Id<T>) -> Result<Option<WithGroup<Versioned<T>>>>;
 
-    /// Attempt to apply an update to the object.
+    /// Check whether an object with the id exists, without fetching the object
+    async fn exists(&self, id: Id<T>) -> Result<bool>;
+
+    /// Get the group of the object with id, without fetching the object
+    async fn get_group(&self, id: Id<T>) -> Result<Option<Id<Group>>>;
+
+    /// Get the current version of the object with id, without fetching the object
+    async fn get_version(&self, id: Id<T>) -> Result<Option<Version>>;
+
+    /// Get all objects with any of the ids.
+    ///
+    /// Ids which don't exist are omitted from the result, and the results are in no particular
+    /// order, so callers which need to know which ids are missing should compare the ids of the
+    /// results against the requested ids.
+    async fn get_many(&self, ids: &[Id<T>]) -> Result<Vec<WithGroup<Versioned<T>>>>;
+
+    /// Attempt to apply an update to the object, returning its new version.
     ///
     /// If there are conflicting edits, this will fail with `Error::ConflictingEdit`
-    async fn update(&mut self, object: Versioned<T>) -> Result<()>;
+    async fn update(&mut self, object: Versioned<T>) -> Result<Version>;
+
+    /// Attempt to apply a partial update to the object, returning its new version.
+    ///
+    /// As with [`update`](Self::update), if the object is no longer at `version`, this will fail
+    /// with `Error::ConflictingEdit`
+    async fn patch(&mut self, id: Id<T>, version: Version, patch: T::Patch) -> Result<Version>
+    where
+        T: Patchable;
+
+    /// Create the object with id if it doesn't exist, or replace it if it does, returning its new
+    /// version
+    ///
+    /// As with [`update`](Self::update), the version which is replaced, if any, is recorded in the
+    /// collection's history.
+    async fn upsert(&mut self, id: Id<T>, object: WithGroup<T>) -> Result<Version>;
+
+    /// Get the previous versions of the object with id, oldest first.
+    ///
+    /// If the collection does not record history, or the object has been purged, this is always
+    /// empty.
+    async fn history(&self, id: Id<T>) -> Result<Vec<WithGroup<Versioned<T>>>>;
+
+    /// Restore a previous version of the object with id, as a new edit, returning its new version.
+    ///
+    /// If the object has been deleted, it is recreated.
+    async fn revert(&mut self, id: Id<T>, version: Version) -> Result<Version>;
 
-    /// Delete object with id
+    /// Delete object with id.
+    ///
+    /// Deleted objects are kept, but are excluded from all other methods until they are restored,
+    /// except for those which explicitly operate on deleted objects. If there is no object with
+    /// id, this does nothing.
     async fn delete(&mut self, id: Id<T>) -> Result<()>;
 
+    /// Delete all objects matching all of the queries, returning the number of objects deleted
+    async fn delete_many(&mut self, query: &[WithGroupQuery<T>]) -> Result<usize>
+    where
+        T: Queryable;
+
+    /// Get the deleted object with id
+    async fn get_deleted(&self, id: Id<T>) -> Result<Option<WithGroup<Versioned<T>>>>;
+
+    /// Get all deleted objects matching all of the queries
+    async fn query_deleted(
+        &self,
+        query: &[WithGroupQuery<T>],
+    ) -> Result<Vec<WithGroup<Versioned<T>>>>
+    where
+        T: Queryable;
+
+    /// Restore the deleted object with id.
+    ///
+    /// If there is no deleted object with id, this will fail with `Error::NotFound`
+    async fn restore(&mut self, id: Id<T>) -> Result<()>;
+
+    /// Permanently remove the deleted object with id, along with its history, so that it can't be
+    /// reverted.
+    ///
+    /// If there is no deleted object with id, this will fail with `Error::NotFound`
+    async fn purge(&mut self, id: Id<T>) -> Result<()>;
+
     /// Move an object to a different group.
+    ///
+    /// If there is no object with id, this does nothing.
     async fn change_group(&mut self, id: Id<T>, new_group: Id<Group>) -> Result<()>
     where
         T: ChangeGroup;
+
+    /// Move all objects with any of the ids to a different group.
+    ///
+    /// Ids which don't exist are ignored.
+    async fn change_group_many(&mut self, ids: &[Id<T>], new_group: Id<Group>) -> Result<()>
+    where
+        T: ChangeGroup;
+
+    /// Get all objects matching all of the queries.
+    ///
+    /// The objects are returned with their current version, so they can be modified and passed
+    /// directly to [`update`](Self::update).
+    async fn query(
+        &self,
+        query: &[WithGroupQuery<T>],
+        options: QueryOptions,
+    ) -> Result<Vec<WithGroup<Versioned<T>>>>
+    where
+        T: Queryable;
+
+    /// Get all objects matching the expression.
+    ///
+    /// This generalizes [`query`](Self::query), which is equivalent to passing an
+    /// [`All`](BooleanExpr::All) expression.
+    async fn query_expr(
+        &self,
+        query: &BooleanExpr<WithGroupQuery<T>>,
+        options: QueryOptions,
+    ) -> Result<Vec<WithGroup<Versioned<T>>>>
+    where
+        T: Queryable;
+
+    /// Get the ids of all objects matching all of the queries
+    async fn query_ids(
+        &self,
+        query: &[WithGroupQuery<T>],
+        options: QueryOptions,
+    ) -> Result<Vec<Id<T>>>
+    where
+        T: Queryable;
+
+    /// Stream all objects matching all of the queries.
+    ///
+    /// Backend errors are surfaced as items of the stream. Dropping the stream early releases any
+    /// resources held by the backend.
+    fn query_stream(
+        &self,
+        query: &[WithGroupQuery<T>],
+        options: QueryOptions,
+    ) -> BoxStream<'_, Result<WithGroup<Versioned<T>>>>
+    where
+        T: Queryable;
+
+    /// Watch for changes to objects matching all of the queries.
+    ///
+    /// The stream continues until it is dropped or the backend fails.
+    async fn watch(
+        &self,
+        query: &[WithGroupQuery<T>],
+    ) -> Result<BoxStream<'static, Result<ChangeEvent<T>>>>
+    where
+        T: Queryable;
+
+    /// Get all objects matching all of the queries, ordered by the first sort, then by the second
+    /// sort among objects which compare equal by the first, and so on.
+    ///
+    /// Objects which compare equal by all of the sorts are ordered by id.
+    async fn query_sorted(
+        &self,
+        query: &[WithGroupQuery<T>],
+        sort: &[Sort<T>],
+        options: QueryOptions,
+    ) -> Result<Vec<WithGroup<Versioned<T>>>>
+    where
+        T: Queryable;
+
+    /// Get a page of the objects matching all of the queries, ordered as in
+    /// [`query_sorted`](Self::query_sorted).
+    ///
+    /// If the offset is past the end of the results, this returns an empty list.
+    async fn query_paginated(
+        &self,
+        query: &[WithGroupQuery<T>],
+        sort: &[Sort<T>],
+        pagination: Pagination,
+        options: QueryOptions,
+    ) -> Result<Vec<WithGroup<Versioned<T>>>>
+    where
+        T: Queryable;
+
+    /// Count the objects matching all of the queries
+    async fn query_count(
+        &self,
+        query: &[WithGroupQuery<T>],
+        options: QueryOptions,
+    ) -> Result<usize>
+    where
+        T: Queryable;
+
+    /// Get the distinct values of a string field among the objects matching all of the queries,
+    /// in ascending order.
+    ///
+    /// At most [`MAX_DISTINCT_VALUES`] values are returned.
+    async fn distinct_values(
+        &self,
+        field: T::TextField,
+        query: &[WithGroupQuery<T>],
+        options: QueryOptions,
+    ) -> Result<Vec<String>>
+    where
+        T: Queryable;
+
+    /// Count the objects matching all of the queries, grouped by the value of a field
+    async fn query_count_by(
+        &self,
+        query: &[WithGroupQuery<T>],
+        group_by: T::GroupBy,
+        options: QueryOptions,
+    ) -> Result<Vec<(GroupKey<T>, usize)>>
+    where
+        T: Queryable;
+
+    /// Sum the amounts of the objects matching all of the queries, grouped by the value of a
+    /// field
+    async fn sum_amounts(
+        &self,
+        query: &[WithGroupQuery<T>],
+        group_by: T::GroupBy,
+        options: QueryOptions,
+    ) -> Result<Vec<(GroupKey<T>, Amount)>>
+    where
+        T: Queryable;
+
+    /// Get statistics about the collection
+    async fn stats(&self) -> Result<CollectionStats>;
+}
+
+/// Apply `f` to the object with id and update it, retrying with a freshly fetched copy of the
+/// object whenever there is a conflicting edit.
+///
+/// Returns the object as updated. If all of `max_attempts` attempts result in conflicting edits,
+/// this will fail with `Error::RetriesExhausted`.
+pub async fn update_with<T, C, F>(
+    collection: &mut C,
+    id: Id<T>,
+    max_attempts: usize,
+    mut f: F,
+) -> Result<Versioned<T>>
+where
+    T: Clone,
+    C: Collection<T> + ?Sized,
+    F: FnMut(&mut T),
+{
+    for _ in 0..max_attempts {
+        let mut object = collection.get(id).await?.ok_or(Error::NotFound)?.object;
+        f(&mut object.object);
+        match collection.update(object.clone()).await {
+            Ok(version) => return Ok(Versioned { version, ..object }),
+            Err(Error::ConflictingEdit) => continue,
+            Err(err) => return Err(err),
+        }
+    }
+    Err(Error::RetriesExhausted(max_attempts))
+}
+
+#[cfg(test)]
+mod tests {
+    use futures::{executor::block_on, FutureExt};
+
+    use super::{update_with, Collection};
+    use crate::{
+        backend::{
+            id::Id,
+            memory::InMemoryCollection,
+            user::{User, WithGroup},
+        },
+        error::Error,
+    };
+
+    /// A collection containing a single user, and the user's id.
+    async fn collection_with_user() -> (InMemoryCollection<User>, Id<User>) {
+        let mut collection = InMemoryCollection::new();
+        let id = collection
+            .create(WithGroup {
+                group: Id::new_random(),
+                object: User {
+                    name: "Alice".into(),
+                    is_superuser: false,
+                },
+            })
+            .await
+            .unwrap();
+        (collection, id)
+    }
+
+    /// Rename the user in a separate handle to the collection, as another client would.
+    fn conflicting_write(collection: &InMemoryCollection<User>, id: Id<User>, name: &str) {
+        let mut other = collection.clone();
+        let mut object = other
+            .get(id)
+            .now_or_never()
+            .unwrap()
+            .unwrap()
+            .unwrap()
+            .object;
+        object.object.name = name.into();
+        other.update(object).now_or_never().unwrap().unwrap();
+    }
+
+    #[test]
+    fn update_with_retries() {
+        block_on(async {
+            let (mut collection, id) = collection_with_user().await;
+            let other = collection.clone();
+            let mut attempts = 0;
+            let updated = update_with(&mut collection, id, 3, |user| {
+                attempts += 1;
+                // conflict with the first two attempts, between their `get` and `update`
+                if attempts < 3 {
+                    conflicting_write(&other, id, &format!("Alice {attempts}"));
+                }
+                user.is_superuser = true;
+            })
+            .await
+            .expect("update_with should succeed once there is no conflict");
+            assert_eq!(attempts, 3);
+            assert_eq!(updated.object.name, "Alice 2");
+            assert!(updated.object.is_superuser);
+
+            let stored = collection.get(id).await.unwrap().unwrap().object;
+            assert_eq!(stored.version, updated.version);
+            assert_eq!(stored.object.name, "Alice 2");
+            assert!(stored.object.is_superuser);
+        });
+    }
+
+    #[test]
+    fn update_with_retries_exhausted() {
+        block_on(async {
+            let (mut collection, id) = collection_with_user().await;
+            let other = collection.clone();
+            let mut attempts = 0;
+            let result = update_with(&mut collection, id, 3, |user| {
+                attempts += 1;
+                conflicting_write(&other, id, "Bob");
+                user.is_superuser = true;
+            })
+            .await;
+            assert!(matches!(result, Err(Error::RetriesExhausted(3))));
+            assert_eq!(attempts, 3);
+            let stored = collection.get(id).await.unwrap().unwrap().object;
+            assert!(!stored.object.is_superuser);
+        });
+    }
+
+    #[test]
+    fn update_with_missing() {
+        block_on(async {
+            let (mut collection, _) = collection_with_user().await;
+            let result = update_with(&mut collection, Id::new_random(), 3, |_| {}).await;
+            assert!(matches!(result, Err(Error::NotFound)));
+        });
+    }
 }
@@ -0,0 +1,242 @@
+//! Exporting the contents of a group, for backups and spreadsheets.
+
+use std::collections::BTreeMap;
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    backend::{id::Id, version::Versioned},
+    error::{Error, Result},
+    public::{account::Account, transaction::Transaction},
+};
+
+/// The format of an export.
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Deserialize, Serialize)]
+pub enum ExportFormat {
+    /// An object with the `accounts` and `transactions` of the group, each in the same form as
+    /// they are serialized elsewhere.
+    Json,
+    /// One row per account involved in each transaction, with the transaction's id, date and
+    /// description, and the account's id, name, and amount. Accounts which aren't involved in
+    /// any transactions don't appear.
+    Csv,
+}
+
+/// Builds an export, one object at a time.
+///
+/// All of the accounts are added before any of the transactions.
+pub(crate) trait Exporter: Send {
+    fn account(&mut self, account: &Versioned<Account>) -> Result<()>;
+
+    fn transaction(&mut self, transaction: &Versioned<Transaction>) -> Result<()>;
+
+    fn finish(self: Box<Self>) -> Result<String>;
+}
+
+impl ExportFormat {
+    pub(crate) fn exporter(self) -> Box<dyn Exporter> {
+        match self {
+            Self::Json => Box::new(JsonExporter {
+                out: String::from(r#"{"accounts":["#),
+                in_transactions: false,
+                is_first: true,
+            }),
+            Self::Csv => Box::new(CsvExporter {
+                out: String::new(),
+                account_names: BTreeMap::new(),
+            }),
+        }
+    }
+}
+
+/// Writes the JSON array elements as they are added, rather than collecting the objects first.
+struct JsonExporter {
+    out: String,
+    /// Whether the accounts array has been closed and the transactions array opened.
+    in_transactions: bool,
+    /// Whether the next element is the first in its array.
+    is_first: bool,
+}
+
+impl JsonExporter {
+    fn push<T: Serialize>(&mut self, value: &T) -> Result<()> {
+        if !self.is_first {
+            self.out.push(',');
+        }
+        self.is_first = false;
+        self.out
+            .push_str(&serde_json::to_string(value).map_err(Error::backend)?);
+        Ok(())
+    }
+
+    fn start_transactions(&mut self) {
+        if !self.in_transactions {
+            self.out.push_str(r#"],"transactions":["#);
+            self.in_transactions = true;
+            self.is_first = true;
+        }
+    }
+}
+
+impl Exporter for JsonExporter {
+    fn account(&mut self, account: &Versioned<Account>) -> Result<()> {
+        self.push(account)
+    }
+
+    fn transaction(&mut self, transaction: &Versioned<Transaction>) -> Result<()> {
+        self.start_transactions();
+        self.push(transaction)
+    }
+
+    fn finish(mut self: Box<Self>) -> Result<String> {
+        self.start_transactions();
+        self.out.push_str("]}");
+        Ok(self.out)
+    }
+}
+
+/// Writes the rows of a CSV export as they are added.
+///
+/// The columns are the transaction's id, date and description, and the account's id, name (or
+/// empty if it isn't in the group), and amount, with two decimal places, or more if it is more
+/// precise. The header row is written before the first row, so an export with no rows is empty.
+struct CsvExporter {
+    out: String,
+    account_names: BTreeMap<Id<Account>, String>,
+}
+
+const CSV_HEADER: [&str; 6] = [
+    "transaction",
+    "date",
+    "description",
+    "account",
+    "account_name",
+    "amount",
+];
+
+impl CsvExporter {
+    fn push_row(&mut self, fields: &[&str]) {
+        for (i, field) in fields.iter().enumerate() {
+            if i > 0 {
+                self.out.push(',');
+            }
+            push_csv_field(&mut self.out, field);
+        }
+        self.out.push('\n');
+    }
+}
+
+/// Append a field of a CSV row, quoting it if it contains a delimiter, quote, or line break, as
+/// described in RFC 4180.
+fn push_csv_field(out: &mut String, field: &str) {
+    if field.contains([',', '"', '\n', '\r']) {
+        out.push('"');
+        out.push_str(&field.replace('"', "\"\""));
+        out.push('"');
+    } else {
+        out.push_str(field);
+    }
+}
+
+impl Exporter for CsvExporter {
+    fn account(&mut self, account: &Versioned<Account>) -> Result<()> {
+        self.account_names
+            .insert(account.id, account.object.name.clone());
+        Ok(())
+    }
+
+    fn transaction(&mut self, transaction: &Versioned<Transaction>) -> Result<()> {
+        for (&account, &amount) in transaction.object.amounts.iter() {
+            if self.out.is_empty() {
+                self.push_row(&CSV_HEADER);
+            }
+            let account_name = self.account_names.get(&account).cloned();
+            self.push_row(&[
+                &transaction.id.to_string(),
+                &transaction.object.date.to_string(),
+                &transaction.object.description,
+                &account.to_string(),
+                account_name.as_deref().unwrap_or(""),
+                &amount.to_string(),
+            ]);
+        }
+        Ok(())
+    }
+
+    fn finish(self: Box<Self>) -> Result<String> {
+        Ok(self.out)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use time::macros::{date, datetime};
+
+    use super::ExportFormat;
+    use crate::{
+        backend::{
+            id::Id,
+            version::{Version, Versioned},
+        },
+        map::Map,
+        public::{
+            account::{Account, AccountType},
+            amount::Amount,
+            transaction::Transaction,
+        },
+    };
+
+    fn versioned<T>(object: T) -> Versioned<T> {
+        Versioned {
+            id: Id::new_random(),
+            version: Version::new_random(),
+            created_at: datetime!(2024-01-01 00:00 UTC),
+            modified_at: datetime!(2024-01-01 00:00 UTC),
+            object,
+        }
+    }
+
+    #[test]
+    fn csv() {
+        let account = versioned(Account {
+            name: "Food, drink".into(),
+            description: String::new(),
+            account_type: AccountType::Expense,
+            opening_balance: Amount::ZERO,
+            opening_date: None,
+            currency: None,
+        });
+        let other = Id::new_random();
+        let mut amounts = Map::default();
+        amounts.insert(account.id, Amount::from_units(12_500));
+        amounts.insert(other, Amount::from_units(-12_500));
+        let transaction = versioned(Transaction {
+            date: date!(2024 - 01 - 15),
+            description: "Lunch at \"Joe's\"".into(),
+            amounts,
+        });
+
+        let mut exporter = ExportFormat::Csv.exporter();
+        exporter.account(&account).unwrap();
+        exporter.transaction(&transaction).unwrap();
+        let rows = exporter.finish().unwrap();
+        let mut expected = vec![
+            "transaction,date,description,account,account_name,amount".to_owned(),
+            format!(
+                "{},2024-01-15,\"Lunch at \"\"Joe's\"\"\",{},\"Food, drink\",1.25",
+                transaction.id, account.id
+            ),
+            format!(
+                "{},2024-01-15,\"Lunch at \"\"Joe's\"\"\",{other},,-1.25",
+                transaction.id
+            ),
+        ];
+        // rows are in the order of the account ids
+        if other < account.id {
+            expected.swap(1, 2);
+        }
+        assert_eq!(rows.lines().collect::<Vec<_>>(), expected);
+
+        assert_eq!(ExportFormat::Csv.exporter().finish().unwrap(), "");
+    }
+}
@@ -1,16 +1,84 @@
-//! A typed 64-bit identifier for a resource.
+//! Typed identifiers for resources.
 #![allow(
-    clippy::incorrect_clone_impl_on_copy_type,
-    clippy::incorrect_partial_ord_impl_on_ord_type
+    clippy::non_canonical_clone_impl,
+    clippy::non_canonical_partial_ord_impl
 )]
 
-use std::{fmt, marker::PhantomData};
+use std::{fmt, hash::Hash, marker::PhantomData, str::FromStr};
 
 use derivative::Derivative;
-use rand::distributions::{Distribution, Standard};
-use serde::{Deserialize, Serialize};
+use rand::{
+    distributions::{Distribution, Standard},
+    Rng,
+};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
 
-/// A typed 64-bit identifier for a resource.
+/// The representation of an [`Id`].
+pub trait RawId:
+    Copy
+    + Eq
+    + Hash
+    + Ord
+    + fmt::Debug
+    + fmt::Display
+    + FromStr
+    + Serialize
+    + DeserializeOwned
+    + Send
+    + Sync
+{
+    /// Generate a new random id.
+    fn random<R: Rng + ?Sized>(rng: &mut R) -> Self;
+
+    /// Convert to the same representation as the id is serialized as in BSON.
+    fn to_bson(self) -> bson::Bson;
+}
+
+impl RawId for u64 {
+    fn random<R: Rng + ?Sized>(rng: &mut R) -> Self {
+        rng.next_u64()
+    }
+
+    fn to_bson(self) -> bson::Bson {
+        bson::Bson::Int64(self as i64)
+    }
+}
+
+#[cfg(feature = "uuid")]
+impl RawId for uuid::Uuid {
+    fn random<R: Rng + ?Sized>(rng: &mut R) -> Self {
+        uuid::Builder::from_random_bytes(rng.gen()).into_uuid()
+    }
+
+    fn to_bson(self) -> bson::Bson {
+        bson::Binary {
+            subtype: bson::spec::BinarySubtype::Generic,
+            bytes: self.as_bytes().to_vec(),
+        }
+        .into()
+    }
+}
+
+/// The error from parsing an [`Id`] with the default representation.
+pub type ParseIdError = std::num::ParseIntError;
+
+/// An [`Id`] backed by a random (version 4) UUID.
+#[cfg(feature = "uuid")]
+pub type UuidId<T> = Id<T, uuid::Uuid>;
+
+/// A typed identifier for a resource.
+///
+/// Ids are generated randomly, so there is a small chance of two objects being given the same id.
+/// By default, ids are 64-bit integers, and among `n` objects, the chance of any collision is
+/// about `n² / 2⁶⁵`: negligible for a million objects, but a few percent for a billion. With the
+/// `uuid` feature, [`UuidId`]s are also available, which are instead random (version 4) UUIDs,
+/// with 122 random bits, so collisions are vanishingly unlikely at any scale, at the cost of twice
+/// the size in storage and in indexes. Either way, random ids don't reveal the order in which
+/// objects were created, or how many there are.
+///
+/// The two kinds of id are stored differently, so an existing database can't switch between them
+/// without migrating it. 64-bit ids are serialized as integers, and UUIDs as hyphenated strings in
+/// human-readable formats and as 16 bytes otherwise.
 #[derive(Derivative, Deserialize, Serialize)]
 #[derivative(
     Clone(bound = ""),
@@ -22,21 +90,21 @@ use serde::{Deserialize, Serialize};
     PartialOrd(bound = "")
 )]
 #[serde(bound = "", transparent)]
-pub struct Id<T> {
-    id: u64,
+pub struct Id<T, R: RawId = u64> {
+    id: R,
     // `PhantomData<fn() -> T>` is covariant in `T`, but unlike `PhantomData<T>` or
     // `PhantomData<*const T>`, it is always `Send` and `Sync`
     _marker: PhantomData<fn() -> T>,
 }
 
-impl<T> Id<T> {
+impl<T, R: RawId> Id<T, R> {
     /// Generate a new random `Id`
     pub fn new_random() -> Self {
         rand::random()
     }
 
     /// Produce an identical `Id` for a different type
-    pub fn transmute<U>(self) -> Id<U> {
+    pub fn transmute<U>(self) -> Id<U, R> {
         Id {
             _marker: PhantomData,
             id: self.id,
@@ -48,7 +116,7 @@ impl<T> Id<T> {
     }
 }
 
-impl<T> fmt::Debug for Id<T> {
+impl<T, R: RawId> fmt::Debug for Id<T, R> {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         f.debug_tuple(std::any::type_name::<T>())
             .field(&self.id)
@@ -56,16 +124,38 @@ impl<T> fmt::Debug for Id<T> {
     }
 }
 
-impl<T> From<Id<T>> for bson::Bson {
-    fn from(id: Id<T>) -> Self {
-        bson::Bson::Int64(id.id as i64)
+/// Displays the bare numeric id, or the hyphenated UUID, as accepted by [`FromStr`].
+impl<T, R: RawId> fmt::Display for Id<T, R> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Display::fmt(&self.id, f)
+    }
+}
+
+/// Parses a bare numeric id, or a UUID in any of the formats accepted by
+/// [`Uuid::parse_str`](https://docs.rs/uuid/1/uuid/struct.Uuid.html#method.parse_str), as
+/// produced by [`Display`](fmt::Display).
+impl<T, R: RawId> FromStr for Id<T, R> {
+    type Err = R::Err;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        s.parse().map(|id| Id {
+            id,
+            _marker: PhantomData,
+        })
+    }
+}
+
+/// Converts to the same representation as the id is serialized as in BSON.
+impl<T, R: RawId> From<Id<T, R>> for bson::Bson {
+    fn from(id: Id<T, R>) -> Self {
+        id.id.to_bson()
     }
 }
 
-impl<T> Distribution<Id<T>> for Standard {
-    fn sample<R: rand::Rng + ?Sized>(&self, rng: &mut R) -> Id<T> {
+impl<T, R: RawId> Distribution<Id<T, R>> for Standard {
+    fn sample<G: Rng + ?Sized>(&self, rng: &mut G) -> Id<T, R> {
         Id {
-            id: rng.next_u64(),
+            id: R::random(rng),
             _marker: PhantomData,
         }
     }
@@ -77,3 +167,75 @@ pub struct WithId<T> {
     #[serde(flatten)]
     pub object: T,
 }
+
+#[cfg(test)]
+mod tests {
+    use super::Id;
+    #[cfg(feature = "uuid")]
+    use super::UuidId;
+    use crate::backend::user::User;
+
+    #[test]
+    fn display_round_trip() {
+        let id = Id::<User>::new_random();
+        assert_eq!(id.to_string().parse::<Id<User>>().unwrap(), id);
+    }
+
+    #[test]
+    fn parse_garbage() {
+        for s in ["", " ", "abc", "1.5", "1 ", "0x10", "{}"] {
+            assert!(s.parse::<Id<User>>().is_err(), "{s:?}");
+        }
+    }
+
+    #[test]
+    fn parse_overflow() {
+        assert_eq!(
+            "18446744073709551615"
+                .parse::<Id<User>>()
+                .unwrap()
+                .to_string(),
+            "18446744073709551615"
+        );
+        for s in ["18446744073709551616", "99999999999999999999999", "-1"] {
+            assert!(s.parse::<Id<User>>().is_err(), "{s:?}");
+        }
+    }
+
+    #[cfg(feature = "uuid")]
+    #[test]
+    fn parse_uuid() {
+        let id = "67e55044-10b1-426f-9247-bb680e5fe0c8";
+        assert_eq!(id.parse::<UuidId<User>>().unwrap().to_string(), id);
+        for s in [
+            "67e55044-10b1-426f-9247-bb680e5fe0c",
+            "67e55044-10b1-426f-9247-bb680e5fe0c80",
+            "67e55044-10b1-426f-9247-bb680e5fe0cg",
+            "12345",
+        ] {
+            assert!(s.parse::<UuidId<User>>().is_err(), "{s:?}");
+        }
+    }
+
+    #[cfg(feature = "uuid")]
+    #[test]
+    fn uuid_round_trip() {
+        let id = UuidId::<User>::new_random();
+        let json = serde_json::to_value(id).unwrap();
+        assert_eq!(json, serde_json::Value::String(id.to_string()));
+        assert_eq!(serde_json::from_value::<UuidId<User>>(json).unwrap(), id);
+        assert_eq!(
+            bson::Bson::from(id),
+            bson::Binary {
+                subtype: bson::spec::BinarySubtype::Generic,
+                bytes: id
+                    .to_string()
+                    .parse::<uuid::Uuid>()
+                    .unwrap()
+                    .as_bytes()
+                    .to_vec(),
+            }
+            .into()
+        );
+    }
+}
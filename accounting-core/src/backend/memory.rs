@@ -0,0 +1,1085 @@
+//! In-memory storage, for testing consumers of the backend without an external database.
+
+use std::{
+    collections::{BTreeMap, BTreeSet, HashMap},
+    sync::{Arc, Mutex, MutexGuard},
+    time::Instant,
+};
+
+use async_trait::async_trait;
+use derivative::Derivative;
+use futures::{
+    channel::mpsc::{self, UnboundedSender},
+    lock::OwnedMutexGuard,
+    stream::{self, BoxStream},
+    StreamExt,
+};
+use time::OffsetDateTime;
+
+use crate::{
+    backend::{
+        audit::{AnyResource, AuditEntry, AuditLog, ResourceType},
+        collection::{ChangeEvent, Collection, CollectionStats, MAX_DISTINCT_VALUES},
+        id::Id,
+        patch::{Patch, Patchable},
+        query::{
+            boolean::BooleanExpr, GroupBy, GroupKey, Pagination, Query, QueryOptions, Queryable,
+            Sort, TextField, WithGroupQuery,
+        },
+        store::{Collections, Store, UnitOfWork},
+        user::{ChangeGroup, Group, User, WithGroup},
+        version::{Version, Versioned},
+    },
+    error::{Error, Result},
+    public::{account::Account, amount::Amount, transaction::Transaction},
+};
+
+/// A collection stored in memory.
+///
+/// Clones of a collection share the same objects. History is always recorded.
+#[derive(Derivative)]
+#[derivative(Clone(bound = ""), Default(bound = ""))]
+pub struct InMemoryCollection<T> {
+    state: Arc<Mutex<State<T>>>,
+}
+
+#[derive(Derivative)]
+#[derivative(Default(bound = ""))]
+struct State<T> {
+    objects: HashMap<Id<T>, WithGroup<Versioned<T>>>,
+    deleted: HashMap<Id<T>, WithGroup<Versioned<T>>>,
+    history: HashMap<Id<T>, Vec<WithGroup<Versioned<T>>>>,
+    watchers: Vec<Watcher<T>>,
+}
+
+/// A predicate on objects, such as a query.
+type Predicate<T> = Box<dyn Fn(&WithGroup<Versioned<T>>) -> bool + Send + Sync>;
+
+/// A stream of changes returned by [`Collection::watch`].
+struct Watcher<T> {
+    matches: Predicate<T>,
+    sender: UnboundedSender<Result<ChangeEvent<T>>>,
+}
+
+impl<T> InMemoryCollection<T> {
+    /// Create an empty collection.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn state(&self) -> MutexGuard<'_, State<T>> {
+        self.state
+            .lock()
+            .expect("in-memory collection lock poisoned")
+    }
+}
+
+impl<T: Clone> InMemoryCollection<T> {
+    /// Create a separate collection containing a copy of the objects in this collection.
+    fn detached(&self) -> Self {
+        let state = self.state();
+        Self {
+            state: Arc::new(Mutex::new(State {
+                objects: state.objects.clone(),
+                deleted: state.deleted.clone(),
+                history: state.history.clone(),
+                watchers: Vec::new(),
+            })),
+        }
+    }
+}
+
+/// The writes made to a copy of a collection within a unit of work, checked against the
+/// collection and ready to be applied to it.
+///
+/// The collection stays locked until the writes are applied or dropped.
+struct PendingWrites<'a, T> {
+    target: MutexGuard<'a, State<T>>,
+    working: MutexGuard<'a, State<T>>,
+    ids: BTreeSet<Id<T>>,
+}
+
+impl<'a, T: Clone> PendingWrites<'a, T> {
+    /// Find the objects written to in `working` since it was copied from `target` as `base`.
+    ///
+    /// If any of those objects have also been changed in `target` since then, this fails with
+    /// `Error::ConflictingEdit`.
+    fn check(
+        target: &'a InMemoryCollection<T>,
+        base: &InMemoryCollection<T>,
+        working: &'a InMemoryCollection<T>,
+    ) -> Result<Self> {
+        let base = base.state();
+        let working = working.state();
+        let target = target.state();
+        let ids = working
+            .objects
+            .keys()
+            .chain(working.deleted.keys())
+            .chain(base.objects.keys())
+            .chain(base.deleted.keys())
+            .copied()
+            .filter(|&id| working.versions(id) != base.versions(id))
+            .collect::<BTreeSet<_>>();
+        if ids
+            .iter()
+            .any(|&id| target.versions(id) != base.versions(id))
+        {
+            return Err(Error::ConflictingEdit);
+        }
+        Ok(Self {
+            target,
+            working,
+            ids,
+        })
+    }
+
+    /// Copy the objects written to from the working copy into the collection, notifying its
+    /// watchers.
+    fn apply(mut self) {
+        for &id in &self.ids {
+            let before = self.target.objects.remove(&id);
+            let after = self.working.objects.get(&id).cloned();
+            let event = match (&before, &after) {
+                (Some(_), Some(after)) => Some(ChangeEvent::Updated {
+                    id,
+                    version: after.object.version,
+                }),
+                (None, Some(after)) => Some(ChangeEvent::Created {
+                    id,
+                    version: after.object.version,
+                }),
+                (Some(_), None) => Some(ChangeEvent::Deleted { id }),
+                (None, None) => None,
+            };
+            if let Some(event) = event {
+                self.target.notify(before.as_ref(), after.as_ref(), event);
+            }
+            if let Some(after) = after {
+                self.target.objects.insert(id, after);
+            }
+            match self.working.deleted.get(&id) {
+                Some(deleted) => self.target.deleted.insert(id, deleted.clone()),
+                None => self.target.deleted.remove(&id),
+            };
+            match self.working.history.get(&id) {
+                Some(history) => self.target.history.insert(id, history.clone()),
+                None => self.target.history.remove(&id),
+            };
+        }
+    }
+}
+
+impl<T: Clone> State<T> {
+    /// The versions of the live and deleted object with id, one of which changes with every write
+    /// to the object.
+    fn versions(&self, id: Id<T>) -> (Option<Version>, Option<Version>) {
+        let version = |object: &WithGroup<Versioned<T>>| object.object.version;
+        (
+            self.objects.get(&id).map(version),
+            self.deleted.get(&id).map(version),
+        )
+    }
+
+    /// Modify the object with id, if it is still at `version`, returning its new version.
+    fn modify(&mut self, id: Id<T>, version: Version, f: impl FnOnce(&mut T)) -> Result<Version> {
+        let current = self.objects.get_mut(&id).ok_or(Error::NotFound)?;
+        if current.object.version != version {
+            return Err(Error::ConflictingEdit);
+        }
+        let previous = current.clone();
+        f(&mut current.object.object);
+        current.object.version = Version::new_random();
+        current.object.modified_at = OffsetDateTime::now_utc();
+        let current = current.clone();
+        self.notify(
+            Some(&previous),
+            Some(&current),
+            ChangeEvent::Updated {
+                id,
+                version: current.object.version,
+            },
+        );
+        self.history.entry(id).or_default().push(previous);
+        Ok(current.object.version)
+    }
+
+    /// Move the object with id to a different group, if it exists.
+    fn change_group(&mut self, id: Id<T>, new_group: Id<Group>) {
+        if let Some(current) = self.objects.get_mut(&id) {
+            let previous = current.clone();
+            current.group = new_group;
+            current.object.version = Version::new_random();
+            current.object.modified_at = OffsetDateTime::now_utc();
+            let current = current.clone();
+            self.notify(
+                Some(&previous),
+                Some(&current),
+                ChangeEvent::Updated {
+                    id,
+                    version: current.object.version,
+                },
+            );
+        }
+    }
+
+    /// Soft-delete the object with id, if it exists.
+    fn delete(&mut self, id: Id<T>) {
+        if let Some(object) = self.objects.remove(&id) {
+            self.notify(Some(&object), None, ChangeEvent::Deleted { id });
+            self.deleted.insert(id, object);
+        }
+    }
+
+    /// Get all live objects matching the predicate, in no particular order.
+    ///
+    /// Every live object is checked, so that is the estimated cost of any query. The objects are
+    /// checked one at a time, failing with `Error::Timeout` once the query's timeout has passed.
+    fn scan(
+        &self,
+        predicate: impl Fn(&WithGroup<Versioned<T>>) -> bool,
+        options: QueryOptions,
+    ) -> Result<Vec<&WithGroup<Versioned<T>>>> {
+        let estimated = self.objects.len() as u64;
+        match options.max_cost {
+            Some(limit) if estimated > limit => {
+                return Err(Error::QueryTooExpensive { estimated, limit })
+            }
+            _ => {}
+        }
+        let deadline = options
+            .timeout
+            .and_then(|timeout| Instant::now().checked_add(timeout));
+        let mut objects = Vec::new();
+        for object in self.objects.values() {
+            if deadline.is_some_and(|deadline| Instant::now() >= deadline) {
+                return Err(Error::Timeout);
+            }
+            if predicate(object) {
+                objects.push(object);
+            }
+        }
+        Ok(objects)
+    }
+
+    /// Get all live objects matching the predicate, ordered by id.
+    fn matching(
+        &self,
+        predicate: impl Fn(&WithGroup<Versioned<T>>) -> bool,
+        options: QueryOptions,
+    ) -> Result<Vec<WithGroup<Versioned<T>>>> {
+        let mut objects = self
+            .scan(predicate, options)?
+            .into_iter()
+            .cloned()
+            .collect::<Vec<_>>();
+        objects.sort_by_key(|object| object.object.id);
+        Ok(objects)
+    }
+}
+
+impl<T> State<T> {
+    /// Send an event to all watchers matching the object before or after the change, and drop
+    /// watchers whose streams have been dropped.
+    fn notify(
+        &mut self,
+        before: Option<&WithGroup<Versioned<T>>>,
+        after: Option<&WithGroup<Versioned<T>>>,
+        event: ChangeEvent<T>,
+    ) {
+        self.watchers.retain(|watcher| {
+            let matches = before.is_some_and(|object| (watcher.matches)(object))
+                || after.is_some_and(|object| (watcher.matches)(object));
+            !matches || watcher.sender.unbounded_send(Ok(event)).is_ok()
+        });
+    }
+}
+
+/// Returns whether the object matches all of the queries.
+fn matches_all<T: Queryable>(
+    query: &[WithGroupQuery<T>],
+    object: &WithGroup<Versioned<T>>,
+) -> bool {
+    query.iter().all(|query| query.matches(object))
+}
+
+#[async_trait]
+impl<T> Collection<T> for InMemoryCollection<T>
+where
+    T: Clone + Send + Sync + 'static,
+{
+    async fn create(&mut self, object: WithGroup<T>) -> Result<Id<T>> {
+        let now = OffsetDateTime::now_utc();
+        let object = Versioned {
+            id: Id::new_random(),
+            version: Version::new_random(),
+            created_at: now,
+            modified_at: now,
+            object,
+        }
+        .transpose();
+        let id = object.object.id;
+        let mut state = self.state();
+        state.notify(
+            None,
+            Some(&object),
+            ChangeEvent::Created {
+                id,
+                version: object.object.version,
+            },
+        );
+        state.objects.insert(id, object);
+        Ok(id)
+    }
+
+    async fn get(&self, id: Id<T>) -> Result<Option<WithGroup<Versioned<T>>>> {
+        Ok(self.state().objects.get(&id).cloned())
+    }
+
+    async fn exists(&self, id: Id<T>) -> Result<bool> {
+        Ok(self.state().objects.contains_key(&id))
+    }
+
+    async fn get_group(&self, id: Id<T>) -> Result<Option<Id<Group>>> {
+        Ok(self.state().objects.get(&id).map(|object| object.group))
+    }
+
+    async fn get_version(&self, id: Id<T>) -> Result<Option<Version>> {
+        Ok(self
+            .state()
+            .objects
+            .get(&id)
+            .map(|object| object.object.version))
+    }
+
+    async fn get_many(&self, ids: &[Id<T>]) -> Result<Vec<WithGroup<Versioned<T>>>> {
+        let state = self.state();
+        Ok(ids
+            .iter()
+            .collect::<BTreeSet<_>>()
+            .into_iter()
+            .filter_map(|id| state.objects.get(id).cloned())
+            .collect())
+    }
+
+    async fn update(&mut self, object: Versioned<T>) -> Result<Version> {
+        self.state().modify(object.id, object.version, |current| {
+            *current = object.object
+        })
+    }
+
+    async fn patch(&mut self, id: Id<T>, version: Version, patch: T::Patch) -> Result<Version>
+    where
+        T: Patchable,
+    {
+        self.state()
+            .modify(id, version, |current| patch.apply(current))
+    }
+
+    async fn upsert(&mut self, id: Id<T>, object: WithGroup<T>) -> Result<Version> {
+        let now = OffsetDateTime::now_utc();
+        let mut state = self.state();
+        let live = state.objects.remove(&id);
+        let previous = live.clone().or_else(|| state.deleted.remove(&id));
+        let object = WithGroup {
+            group: object.group,
+            object: Versioned {
+                id,
+                version: Version::new_random(),
+                created_at: previous
+                    .as_ref()
+                    .map_or(now, |previous| previous.object.created_at),
+                modified_at: now,
+                object: object.object,
+            },
+        };
+        let version = object.object.version;
+        // replacing a deleted object also restores it
+        let event = if live.is_some() {
+            ChangeEvent::Updated { id, version }
+        } else {
+            ChangeEvent::Created { id, version }
+        };
+        state.notify(live.as_ref(), Some(&object), event);
+        state.objects.insert(id, object);
+        if let Some(previous) = previous {
+            state.history.entry(id).or_default().push(previous);
+        }
+        Ok(version)
+    }
+
+    async fn history(&self, id: Id<T>) -> Result<Vec<WithGroup<Versioned<T>>>> {
+        Ok(self.state().history.get(&id).cloned().unwrap_or_default())
+    }
+
+    async fn revert(&mut self, id: Id<T>, version: Version) -> Result<Version> {
+        let previous = self
+            .state()
+            .history
+            .get(&id)
+            .and_then(|history| {
+                history
+                    .iter()
+                    .find(|previous| previous.object.version == version)
+            })
+            .cloned()
+            .ok_or(Error::NotFound)?;
+        let current = self.get_version(id).await?;
+        match current {
+            Some(current) => self
+                .state()
+                .modify(id, current, |object| *object = previous.object.object),
+            None => {
+                let object = WithGroup {
+                    group: previous.group,
+                    object: previous.object.object,
+                };
+                self.upsert(id, object).await
+            }
+        }
+    }
+
+    async fn delete(&mut self, id: Id<T>) -> Result<()> {
+        self.state().delete(id);
+        Ok(())
+    }
+
+    async fn delete_many(&mut self, query: &[WithGroupQuery<T>]) -> Result<usize>
+    where
+        T: Queryable,
+    {
+        let mut state = self.state();
+        let ids = state
+            .matching(|object| matches_all(query, object), QueryOptions::default())?
+            .into_iter()
+            .map(|object| object.object.id)
+            .collect::<Vec<_>>();
+        for &id in &ids {
+            state.delete(id);
+        }
+        Ok(ids.len())
+    }
+
+    async fn get_deleted(&self, id: Id<T>) -> Result<Option<WithGroup<Versioned<T>>>> {
+        Ok(self.state().deleted.get(&id).cloned())
+    }
+
+    async fn query_deleted(
+        &self,
+        query: &[WithGroupQuery<T>],
+    ) -> Result<Vec<WithGroup<Versioned<T>>>>
+    where
+        T: Queryable,
+    {
+        let mut objects = self
+            .state()
+            .deleted
+            .values()
+            .filter(|object| matches_all(query, object))
+            .cloned()
+            .collect::<Vec<_>>();
+        objects.sort_by_key(|object| object.object.id);
+        Ok(objects)
+    }
+
+    async fn restore(&mut self, id: Id<T>) -> Result<()> {
+        let mut state = self.state();
+        let object = state.deleted.remove(&id).ok_or(Error::NotFound)?;
+        state.notify(
+            None,
+            Some(&object),
+            ChangeEvent::Created {
+                id,
+                version: object.object.version,
+            },
+        );
+        state.objects.insert(id, object);
+        Ok(())
+    }
+
+    async fn purge(&mut self, id: Id<T>) -> Result<()> {
+        let mut state = self.state();
+        state.deleted.remove(&id).ok_or(Error::NotFound)?;
+        state.history.remove(&id);
+        Ok(())
+    }
+
+    async fn change_group(&mut self, id: Id<T>, new_group: Id<Group>) -> Result<()>
+    where
+        T: ChangeGroup,
+    {
+        self.state().change_group(id, new_group);
+        Ok(())
+    }
+
+    async fn change_group_many(&mut self, ids: &[Id<T>], new_group: Id<Group>) -> Result<()>
+    where
+        T: ChangeGroup,
+    {
+        let mut state = self.state();
+        for &id in ids {
+            state.change_group(id, new_group);
+        }
+        Ok(())
+    }
+
+    async fn query(
+        &self,
+        query: &[WithGroupQuery<T>],
+        options: QueryOptions,
+    ) -> Result<Vec<WithGroup<Versioned<T>>>>
+    where
+        T: Queryable,
+    {
+        self.state()
+            .matching(|object| matches_all(query, object), options)
+    }
+
+    async fn query_expr(
+        &self,
+        query: &BooleanExpr<WithGroupQuery<T>>,
+        options: QueryOptions,
+    ) -> Result<Vec<WithGroup<Versioned<T>>>>
+    where
+        T: Queryable,
+    {
+        self.state()
+            .matching(|object| query.matches(object), options)
+    }
+
+    async fn query_ids(
+        &self,
+        query: &[WithGroupQuery<T>],
+        options: QueryOptions,
+    ) -> Result<Vec<Id<T>>>
+    where
+        T: Queryable,
+    {
+        Ok(self
+            .state()
+            .matching(|object| matches_all(query, object), options)?
+            .into_iter()
+            .map(|object| object.object.id)
+            .collect())
+    }
+
+    fn query_stream(
+        &self,
+        query: &[WithGroupQuery<T>],
+        options: QueryOptions,
+    ) -> BoxStream<'_, Result<WithGroup<Versioned<T>>>>
+    where
+        T: Queryable,
+    {
+        match self
+            .state()
+            .matching(|object| matches_all(query, object), options)
+        {
+            Ok(objects) => stream::iter(objects.into_iter().map(Ok)).boxed(),
+            Err(error) => stream::iter([Err(error)]).boxed(),
+        }
+    }
+
+    async fn watch(
+        &self,
+        query: &[WithGroupQuery<T>],
+    ) -> Result<BoxStream<'static, Result<ChangeEvent<T>>>>
+    where
+        T: Queryable,
+    {
+        let query = query.to_vec();
+        let (sender, receiver) = mpsc::unbounded();
+        self.state().watchers.push(Watcher {
+            matches: Box::new(move |object| matches_all(&query, object)),
+            sender,
+        });
+        Ok(receiver.boxed())
+    }
+
+    async fn query_sorted(
+        &self,
+        query: &[WithGroupQuery<T>],
+        sort: &[Sort<T>],
+        options: QueryOptions,
+    ) -> Result<Vec<WithGroup<Versioned<T>>>>
+    where
+        T: Queryable,
+    {
+        let mut objects = self
+            .state()
+            .matching(|object| matches_all(query, object), options)?;
+        // the sort is stable, so objects which compare equal stay ordered by id
+        objects.sort_by(|a, b| {
+            sort.iter()
+                .map(|sort| sort.compare(&a.object.object, &b.object.object))
+                .find(|ordering| ordering.is_ne())
+                .unwrap_or(std::cmp::Ordering::Equal)
+        });
+        Ok(objects)
+    }
+
+    async fn query_paginated(
+        &self,
+        query: &[WithGroupQuery<T>],
+        sort: &[Sort<T>],
+        pagination: Pagination,
+        options: QueryOptions,
+    ) -> Result<Vec<WithGroup<Versioned<T>>>>
+    where
+        T: Queryable,
+    {
+        let objects = self.query_sorted(query, sort, options).await?;
+        Ok(objects
+            .into_iter()
+            .skip(pagination.offset as usize)
+            .take(pagination.limit as usize)
+            .collect())
+    }
+
+    async fn query_count(&self, query: &[WithGroupQuery<T>], options: QueryOptions) -> Result<usize>
+    where
+        T: Queryable,
+    {
+        Ok(self
+            .state()
+            .scan(|object| matches_all(query, object), options)?
+            .len())
+    }
+
+    async fn distinct_values(
+        &self,
+        field: T::TextField,
+        query: &[WithGroupQuery<T>],
+        options: QueryOptions,
+    ) -> Result<Vec<String>>
+    where
+        T: Queryable,
+    {
+        let values = self
+            .state()
+            .scan(|object| matches_all(query, object), options)?
+            .into_iter()
+            .map(|object| field.get(&object.object.object).to_owned())
+            .collect::<BTreeSet<_>>();
+        Ok(values.into_iter().take(MAX_DISTINCT_VALUES).collect())
+    }
+
+    async fn query_count_by(
+        &self,
+        query: &[WithGroupQuery<T>],
+        group_by: T::GroupBy,
+        options: QueryOptions,
+    ) -> Result<Vec<(GroupKey<T>, usize)>>
+    where
+        T: Queryable,
+    {
+        let mut counts = BTreeMap::new();
+        let state = self.state();
+        for object in state.scan(|object| matches_all(query, object), options)? {
+            for key in group_by.keys(&object.object.object) {
+                *counts.entry(key).or_insert(0) += 1;
+            }
+        }
+        Ok(counts.into_iter().collect())
+    }
+
+    async fn sum_amounts(
+        &self,
+        query: &[WithGroupQuery<T>],
+        group_by: T::GroupBy,
+        options: QueryOptions,
+    ) -> Result<Vec<(GroupKey<T>, Amount)>>
+    where
+        T: Queryable,
+    {
+        let mut totals = BTreeMap::new();
+        let state = self.state();
+        for object in state.scan(|object| matches_all(query, object), options)? {
+            for (key, amount) in group_by.amounts(&object.object.object) {
+                let total = totals.entry(key).or_insert(Amount::ZERO);
+                *total = *total + amount;
+            }
+        }
+        Ok(totals.into_iter().collect())
+    }
+
+    async fn stats(&self) -> Result<CollectionStats> {
+        let state = self.state();
+        Ok(CollectionStats {
+            count: state.objects.len(),
+            deleted_count: state.deleted.len(),
+            storage_size: None,
+            index_sizes: BTreeMap::new(),
+            last_modified: state
+                .objects
+                .values()
+                .chain(state.deleted.values())
+                .map(|object| object.object.modified_at)
+                .max(),
+        })
+    }
+}
+
+/// An audit log stored in memory.
+///
+/// Clones of a log share the same entries.
+#[derive(Clone, Default)]
+pub struct InMemoryAuditLog {
+    entries: Arc<Mutex<Vec<AuditEntry>>>,
+}
+
+impl InMemoryAuditLog {
+    /// Create an empty log.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn entries(&self) -> MutexGuard<'_, Vec<AuditEntry>> {
+        self.entries
+            .lock()
+            .expect("in-memory audit log lock poisoned")
+    }
+
+    /// Create a separate log containing a copy of the entries in this log.
+    fn detached(&self) -> Self {
+        Self {
+            entries: Arc::new(Mutex::new(self.entries().clone())),
+        }
+    }
+
+    /// Append the entries recorded in `working` since it was copied from this log, when it had
+    /// `base_len` entries.
+    fn append_since(&self, working: &Self, base_len: usize) {
+        let working = working.entries();
+        self.entries().extend_from_slice(&working[base_len..]);
+    }
+}
+
+#[async_trait]
+impl AuditLog for InMemoryAuditLog {
+    async fn record(&mut self, entry: AuditEntry) -> Result<()> {
+        self.entries().push(entry);
+        Ok(())
+    }
+
+    async fn trail(
+        &self,
+        resource_type: ResourceType,
+        resource_id: Id<AnyResource>,
+    ) -> Result<Vec<AuditEntry>> {
+        Ok(self
+            .entries()
+            .iter()
+            .filter(|entry| {
+                entry.resource_type == resource_type && entry.resource_id == resource_id
+            })
+            .cloned()
+            .collect())
+    }
+}
+
+/// A [`Store`] keeping all of its collections in memory.
+///
+/// Units of work operate on a copy of the collections. Committing a unit of work copies the
+/// objects it wrote to back into the collections, and reports the changes to watchers, leaving
+/// any other changes made since it began in place. If any of the objects it wrote to were also
+/// changed outside of it in the meantime, committing fails with `Error::ConflictingEdit`, and
+/// none of its writes are applied. Only one unit of work may be in progress at a time.
+#[derive(Clone, Default)]
+pub struct InMemoryStore {
+    collections: InMemoryCollections,
+    unit_lock: Arc<futures::lock::Mutex<()>>,
+}
+
+impl InMemoryStore {
+    /// Create a store with empty collections.
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+#[derive(Clone, Default)]
+struct InMemoryCollections {
+    users: InMemoryCollection<User>,
+    groups: InMemoryCollection<Group>,
+    accounts: InMemoryCollection<Account>,
+    transactions: InMemoryCollection<Transaction>,
+    audit: InMemoryAuditLog,
+}
+
+impl InMemoryCollections {
+    fn boxed(&self) -> Collections {
+        Collections {
+            users: Box::new(self.users.clone()),
+            groups: Box::new(self.groups.clone()),
+            accounts: Box::new(self.accounts.clone()),
+            transactions: Box::new(self.transactions.clone()),
+            audit: Box::new(self.audit.clone()),
+        }
+    }
+
+    fn detached(&self) -> Self {
+        Self {
+            users: self.users.detached(),
+            groups: self.groups.detached(),
+            accounts: self.accounts.detached(),
+            transactions: self.transactions.detached(),
+            audit: self.audit.detached(),
+        }
+    }
+
+    /// Apply the writes made in `working` since it was copied from these collections as `base`.
+    ///
+    /// If any of the objects written to were also changed here since then, this fails with
+    /// `Error::ConflictingEdit`, and applies none of the writes.
+    fn apply_writes(&self, base: &Self, working: &Self) -> Result<()> {
+        // all of the collections are checked before any writes are applied
+        let users = PendingWrites::check(&self.users, &base.users, &working.users)?;
+        let groups = PendingWrites::check(&self.groups, &base.groups, &working.groups)?;
+        let accounts = PendingWrites::check(&self.accounts, &base.accounts, &working.accounts)?;
+        let transactions = PendingWrites::check(
+            &self.transactions,
+            &base.transactions,
+            &working.transactions,
+        )?;
+        users.apply();
+        groups.apply();
+        accounts.apply();
+        transactions.apply();
+        self.audit
+            .append_since(&working.audit, base.audit.entries().len());
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl Store for InMemoryStore {
+    fn collections(&self) -> Collections {
+        self.collections.boxed()
+    }
+
+    async fn begin(&self) -> Result<Box<dyn UnitOfWork>> {
+        let guard = self.unit_lock.clone().lock_owned().await;
+        let base = self.collections.detached();
+        Ok(Box::new(InMemoryUnitOfWork {
+            working: base.detached(),
+            target: Some(CommitTarget {
+                collections: self.collections.clone(),
+                base,
+            }),
+            _guard: Some(guard),
+        }))
+    }
+
+    async fn snapshot(&self) -> Result<Box<dyn UnitOfWork>> {
+        Ok(Box::new(InMemoryUnitOfWork {
+            target: None,
+            working: self.collections.detached(),
+            _guard: None,
+        }))
+    }
+}
+
+struct InMemoryUnitOfWork {
+    /// The collections to apply the unit of work to when it is committed, or `None` for a
+    /// snapshot.
+    target: Option<CommitTarget>,
+    working: InMemoryCollections,
+    _guard: Option<OwnedMutexGuard<()>>,
+}
+
+struct CommitTarget {
+    collections: InMemoryCollections,
+    /// A copy of the collections as they were when the unit of work began.
+    base: InMemoryCollections,
+}
+
+#[async_trait]
+impl UnitOfWork for InMemoryUnitOfWork {
+    fn collections(&self) -> Collections {
+        self.working.boxed()
+    }
+
+    async fn commit(self: Box<Self>) -> Result<()> {
+        match &self.target {
+            Some(target) => target.collections.apply_writes(&target.base, &self.working),
+            None => Ok(()),
+        }
+    }
+
+    async fn abort(self: Box<Self>) -> Result<()> {
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use futures::{executor::block_on, TryStreamExt};
+    use time::macros::date;
+
+    use super::{InMemoryCollection, InMemoryStore};
+    use crate::{
+        backend::{
+            collection::Collection,
+            id::Id,
+            query::{boolean::BooleanExpr, Pagination, QueryOptions},
+            store::Store,
+            testsuite::run_collection_tests,
+            user::{User, WithGroup},
+        },
+        error::{Error, Result},
+        map::Map,
+        public::{
+            amount::Amount,
+            transaction::{Transaction, TransactionGroupBy, TransactionTextField},
+        },
+    };
+
+    #[test]
+    fn collection_tests() {
+        block_on(run_collection_tests(|| async { InMemoryCollection::new() }));
+    }
+
+    #[test]
+    fn timeout() {
+        fn timed_out<T>(result: Result<T>) -> bool {
+            matches!(result, Err(Error::Timeout))
+        }
+
+        block_on(async {
+            let mut collection = InMemoryCollection::new();
+            let mut amounts = Map::default();
+            amounts.insert(Id::new_random(), Amount::ZERO);
+            let transaction = Transaction {
+                date: date!(2024 - 01 - 01),
+                description: "Rent".into(),
+                amounts,
+            };
+            collection
+                .create(WithGroup {
+                    group: Id::new_random(),
+                    object: transaction,
+                })
+                .await
+                .unwrap();
+            assert_eq!(
+                collection
+                    .query(&[], QueryOptions::default())
+                    .await
+                    .unwrap()
+                    .len(),
+                1
+            );
+
+            // with no time at all, a query times out on the first object it checks
+            let options = QueryOptions {
+                timeout: Some(Duration::ZERO),
+                ..QueryOptions::default()
+            };
+            let pagination = Pagination {
+                limit: 10,
+                offset: 0,
+            };
+            let group_by = TransactionGroupBy::Account;
+            assert!(timed_out(collection.query(&[], options).await));
+            let expr = BooleanExpr::All(Vec::new());
+            assert!(timed_out(collection.query_expr(&expr, options).await));
+            assert!(timed_out(collection.query_ids(&[], options).await));
+            let stream = collection.query_stream(&[], options);
+            assert!(timed_out(stream.try_collect::<Vec<_>>().await));
+            assert!(timed_out(collection.query_sorted(&[], &[], options).await));
+            let page = collection.query_paginated(&[], &[], pagination, options);
+            assert!(timed_out(page.await));
+            assert!(timed_out(collection.query_count(&[], options).await));
+            let field = TransactionTextField::Description;
+            assert!(timed_out(
+                collection.distinct_values(field, &[], options).await
+            ));
+            assert!(timed_out(
+                collection.query_count_by(&[], group_by, options).await
+            ));
+            assert!(timed_out(
+                collection.sum_amounts(&[], group_by, options).await
+            ));
+        });
+    }
+
+    fn user(name: &str) -> WithGroup<User> {
+        WithGroup {
+            group: Id::new_random(),
+            object: User {
+                name: name.into(),
+                is_superuser: false,
+            },
+        }
+    }
+
+    async fn name(store: &InMemoryStore, id: Id<User>) -> Option<String> {
+        let user = store.collections().users.get(id).await.unwrap();
+        user.map(|user| user.object.object.name)
+    }
+
+    #[test]
+    fn commit_keeps_outside_writes() {
+        block_on(async {
+            let store = InMemoryStore::new();
+            let mut outside = store.collections();
+            let existing = outside.users.create(user("Alice")).await.unwrap();
+
+            let unit = store.begin().await.unwrap();
+            let mut inside = unit.collections();
+            let created = inside.users.create(user("Bob")).await.unwrap();
+            // writes outside of the unit of work, to objects it doesn't touch
+            let outside_created = outside.users.create(user("Carol")).await.unwrap();
+            let mut alice = outside.users.get(existing).await.unwrap().unwrap().object;
+            alice.object.name = "Alice Smith".into();
+            outside.users.update(alice).await.unwrap();
+            drop(inside);
+            unit.commit()
+                .await
+                .expect("commit without conflicts should succeed");
+
+            assert_eq!(name(&store, created).await.as_deref(), Some("Bob"));
+            assert_eq!(
+                name(&store, outside_created).await.as_deref(),
+                Some("Carol")
+            );
+            assert_eq!(
+                name(&store, existing).await.as_deref(),
+                Some("Alice Smith"),
+                "commit should not undo writes made outside of the unit of work"
+            );
+        });
+    }
+
+    #[test]
+    fn commit_conflict() {
+        block_on(async {
+            let store = InMemoryStore::new();
+            let mut outside = store.collections();
+            let existing = outside.users.create(user("Alice")).await.unwrap();
+
+            let unit = store.begin().await.unwrap();
+            let mut inside = unit.collections();
+            let created = inside.users.create(user("Bob")).await.unwrap();
+            inside.users.delete(existing).await.unwrap();
+            let mut alice = outside.users.get(existing).await.unwrap().unwrap().object;
+            alice.object.name = "Alice Smith".into();
+            outside.users.update(alice).await.unwrap();
+            drop(inside);
+            assert!(
+                matches!(unit.commit().await, Err(Error::ConflictingEdit)),
+                "commit should fail if an object it wrote to was changed outside of it"
+            );
+
+            assert_eq!(
+                name(&store, created).await,
+                None,
+                "a failed commit should apply none of its writes"
+            );
+            assert_eq!(name(&store, existing).await.as_deref(), Some("Alice Smith"));
+        });
+    }
+}
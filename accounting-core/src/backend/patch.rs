@@ -0,0 +1,19 @@
+//! Partial updates to resources.
+
+use serde::Serialize;
+
+/// A type which can be partially updated.
+pub trait Patchable {
+    /// The type of partial updates to this type.
+    ///
+    /// When serialized, a patch must contain exactly the fields it changes, with the same names
+    /// and representations as in the serialized object. Fields which are cleared, so that they are
+    /// missing from the object, are serialized as `null`.
+    type Patch: Patch<Self> + Clone + Send + Sync + Serialize;
+}
+
+/// A partial update to an object of type `T`.
+pub trait Patch<T: ?Sized> {
+    /// Apply the changes in the patch to the object.
+    fn apply(&self, object: &mut T);
+}
@@ -0,0 +1,1559 @@
+//! Queries over resources.
+//!
+//! # Wire format
+//!
+//! Queries are serialized in a stable format, which clients may construct directly:
+//!
+//! - Queries on objects, such as [`WithGroupQuery`] and the `Query` types of each [`Queryable`]
+//!   type, are objects with a `type`, naming the field or kind of query in `snake_case`, and a
+//!   `value`. Queries on the object itself appear directly, rather than wrapped in another
+//!   query, so the `Query` types must not use the `type`s of [`WithGroupQuery`]: `group`, `ids`,
+//!   `created_at`, `modified_at` and `not`. The `type` must come before the `value`.
+//! - [`SimpleQuery`] is an object with any of the comparisons `eq`, `ne`, `gt`, `lt`, `ge`,
+//!   `le`, `in`, `nin`, and `is_null`. [`StringQuery`] additionally accepts `like`, `contains`,
+//!   `starts_with`, `regex`, and `ignore_case`. Dates and times are compared against RFC 3339
+//!   strings. [`Id`]s are numbers.
+//! - [`BooleanExpr`](boolean::BooleanExpr) is an object with exactly one of `query`, `all`,
+//!   `any`, or `not`.
+//!
+//! For example, transactions in a group dated in 2024, whose description doesn't contain "fee":
+//!
+//! ```json
+//! {
+//!   "all": [
+//!     { "query": { "type": "group", "value": [1234] } },
+//!     { "query": { "type": "date", "value": { "ge": "2024-01-01", "lt": "2025-01-01" } } },
+//!     { "not": { "query": { "type": "description", "value": { "contains": "fee" } } } }
+//!   ]
+//! }
+//! ```
+
+use std::{
+    borrow::Cow,
+    cmp::Ordering,
+    ops::{Bound, RangeBounds},
+    time::Duration,
+};
+
+use derivative::Derivative;
+use serde::{Deserialize, Serialize};
+use time::{Date, OffsetDateTime};
+use unicode_normalization::{is_nfc_quick, IsNormalized, UnicodeNormalization};
+
+use self::schema::QuerySchema;
+use crate::{
+    backend::{
+        id::Id,
+        user::{Group, WithGroup},
+        version::Versioned,
+    },
+    error::{Error, Result},
+    public::amount::Amount,
+};
+
+pub mod boolean;
+pub mod builder;
+pub mod schema;
+mod wire;
+
+/// A type which can be queried.
+pub trait Queryable {
+    /// The type of queries on this type.
+    type Query: Query<Self> + Clone + Send + Sync;
+    /// The fields this type can be grouped by when counting query results.
+    type GroupBy: GroupBy<Self> + Clone + Send + Sync;
+    /// The fields this type can be sorted by.
+    type SortField: SortField<Self> + Clone + Send + Sync;
+    /// The string-valued fields of this type.
+    type TextField: TextField<Self> + Clone + Send + Sync;
+
+    /// Describe the fields of this type which can be queried.
+    fn schema() -> QuerySchema;
+}
+
+/// A query which can be matched against an object of type `T`.
+pub trait Query<T: ?Sized> {
+    /// Returns whether the object matches the query.
+    fn matches(&self, object: &T) -> bool;
+
+    /// Check that the query is well-formed.
+    ///
+    /// Queries which can never match anything, or which match everything because they have no
+    /// comparisons, are rejected, as they are almost certainly mistakes. Backends check queries
+    /// before running them, and fail with `Error::InvalidQuery`.
+    fn validate(&self) -> std::result::Result<(), QueryValidationError> {
+        Ok(())
+    }
+}
+
+/// The reason a query was rejected by [`Query::validate`], and which clause of the query it
+/// applies to.
+#[derive(Clone, Debug, Eq, PartialEq, thiserror::Error)]
+pub struct QueryValidationError {
+    /// The path to the rejected clause, from the outermost, such as `["not", "name", "in"]`.
+    ///
+    /// This is empty if the whole query was rejected.
+    pub clause: Vec<String>,
+    /// Why the clause was rejected.
+    pub reason: String,
+}
+
+impl QueryValidationError {
+    /// An error for the whole query.
+    pub fn new(reason: impl Into<String>) -> Self {
+        Self {
+            clause: Vec::new(),
+            reason: reason.into(),
+        }
+    }
+
+    /// Mark the error as being in a clause of an enclosing query.
+    pub fn within(mut self, clause: impl Into<String>) -> Self {
+        self.clause.insert(0, clause.into());
+        self
+    }
+}
+
+impl std::fmt::Display for QueryValidationError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if self.clause.is_empty() {
+            f.write_str(&self.reason)
+        } else {
+            write!(f, "in `{}`: {}", self.clause.join("."), self.reason)
+        }
+    }
+}
+
+/// A field which objects of type `T` can be grouped by.
+pub trait GroupBy<T: ?Sized> {
+    /// The type of the values of the field.
+    type Key: Ord + Send;
+
+    /// The values of the field for the object.
+    ///
+    /// An object may have any number of values for a field (e.g. the accounts involved in a
+    /// transaction), and is counted once in each group.
+    fn keys(&self, object: &T) -> Vec<Self::Key>;
+
+    /// The values of the field for the object, along with the amount of the object attributed to
+    /// each value, for summing.
+    ///
+    /// For example, when grouping transactions by account, this is the amount posted to each
+    /// account.
+    fn amounts(&self, object: &T) -> Vec<(Self::Key, Amount)>;
+}
+
+/// The type of the values of the fields `T` can be grouped by.
+pub type GroupKey<T> = <<T as Queryable>::GroupBy as GroupBy<T>>::Key;
+
+/// The fields of a type which has no fields to group by.
+#[derive(Clone, Copy, Debug, Deserialize, Eq, Ord, PartialEq, PartialOrd, Serialize)]
+pub enum NoGroupBy {}
+
+impl<T: ?Sized> GroupBy<T> for NoGroupBy {
+    type Key = NoGroupBy;
+
+    fn keys(&self, _object: &T) -> Vec<Self::Key> {
+        match *self {}
+    }
+
+    fn amounts(&self, _object: &T) -> Vec<(Self::Key, Amount)> {
+        match *self {}
+    }
+}
+
+/// A field which objects of type `T` can be sorted by.
+pub trait SortField<T: ?Sized> {
+    /// Compare two objects by the value of the field.
+    fn compare(&self, a: &T, b: &T) -> Ordering;
+}
+
+/// A string-valued field of objects of type `T`.
+pub trait TextField<T: ?Sized> {
+    /// The value of the field for the object.
+    fn get<'a>(&self, object: &'a T) -> &'a str;
+}
+
+/// An ordering of objects by one of their fields.
+#[derive(Derivative, Deserialize, Serialize)]
+#[derivative(Clone(bound = ""), Debug(bound = "T::SortField: std::fmt::Debug"))]
+#[serde(bound(
+    serialize = "T::SortField: Serialize",
+    deserialize = "T::SortField: Deserialize<'de>"
+))]
+pub struct Sort<T: Queryable> {
+    /// The field to sort by.
+    pub field: T::SortField,
+    /// Whether to sort in ascending order, rather than descending.
+    pub ascending: bool,
+}
+
+impl<T: Queryable> Sort<T> {
+    /// Compare two objects by the field, in the direction of the sort.
+    pub fn compare(&self, a: &T, b: &T) -> Ordering {
+        let ordering = self.field.compare(a, b);
+        if self.ascending {
+            ordering
+        } else {
+            ordering.reverse()
+        }
+    }
+}
+
+/// A query on a single value, made up of a set of comparisons which must all be satisfied.
+///
+/// An empty query (with all fields set to `None`) matches all values.
+#[derive(Clone, Debug, Derivative)]
+#[derivative(Default(bound = ""))]
+pub struct SimpleQuery<T> {
+    /// The value must be equal to this.
+    pub eq: Option<T>,
+    /// The value must not be equal to this.
+    pub ne: Option<T>,
+    /// The value must be strictly greater than this.
+    pub gt: Option<T>,
+    /// The value must be strictly less than this.
+    pub lt: Option<T>,
+    /// The value must be greater than or equal to this.
+    pub ge: Option<T>,
+    /// The value must be less than or equal to this.
+    pub le: Option<T>,
+    /// The value must be one of these.
+    pub in_: Option<Vec<T>>,
+    /// The value must not be any of these.
+    pub nin: Option<Vec<T>>,
+    /// The value must be missing if this is `true`, or present if this is `false`.
+    ///
+    /// A missing value doesn't satisfy any of the other comparisons, so a query with any of them
+    /// set only matches missing values if it is empty apart from this. Backends store missing
+    /// values by omitting the field, so optional fields should be marked
+    /// `#[serde(skip_serializing_if = "Option::is_none")]`.
+    pub is_null: Option<bool>,
+}
+
+impl<T> SimpleQuery<T> {
+    /// A query matching values equal to `value`.
+    pub fn eq(value: T) -> Self {
+        Self {
+            eq: Some(value),
+            ..Default::default()
+        }
+    }
+
+    /// A query matching values equal to any of `values`.
+    pub fn in_(values: Vec<T>) -> Self {
+        Self {
+            in_: Some(values),
+            ..Default::default()
+        }
+    }
+
+    /// Convert the values compared against, keeping the same comparisons.
+    pub fn map<U>(self, mut f: impl FnMut(T) -> U) -> SimpleQuery<U> {
+        SimpleQuery {
+            eq: self.eq.map(&mut f),
+            ne: self.ne.map(&mut f),
+            gt: self.gt.map(&mut f),
+            lt: self.lt.map(&mut f),
+            ge: self.ge.map(&mut f),
+            le: self.le.map(&mut f),
+            in_: self
+                .in_
+                .map(|values| values.into_iter().map(&mut f).collect()),
+            nin: self
+                .nin
+                .map(|values| values.into_iter().map(&mut f).collect()),
+            is_null: self.is_null,
+        }
+    }
+
+    /// A query matching values within `range`.
+    pub fn range(range: impl RangeBounds<T>) -> Self
+    where
+        T: Clone,
+    {
+        let mut query = Self::default();
+        match range.start_bound() {
+            Bound::Included(start) => query.ge = Some(start.clone()),
+            Bound::Excluded(start) => query.gt = Some(start.clone()),
+            Bound::Unbounded => {}
+        }
+        match range.end_bound() {
+            Bound::Included(end) => query.le = Some(end.clone()),
+            Bound::Excluded(end) => query.lt = Some(end.clone()),
+            Bound::Unbounded => {}
+        }
+        query
+    }
+
+    /// A query matching values greater than or equal to `low` and less than or equal to `high`.
+    pub fn between(low: T, high: T) -> Self {
+        Self {
+            ge: Some(low),
+            le: Some(high),
+            ..Default::default()
+        }
+    }
+
+    /// Also require values to be equal to `value`, replacing any previous `eq` comparison.
+    pub fn and_eq(self, value: T) -> Self {
+        Self {
+            eq: Some(value),
+            ..self
+        }
+    }
+
+    /// Also require values to not be equal to `value`, replacing any previous `ne` comparison.
+    pub fn and_ne(self, value: T) -> Self {
+        Self {
+            ne: Some(value),
+            ..self
+        }
+    }
+
+    /// Also require values to be greater than `value`, replacing any previous `gt` comparison.
+    pub fn and_gt(self, value: T) -> Self {
+        Self {
+            gt: Some(value),
+            ..self
+        }
+    }
+
+    /// Also require values to be less than `value`, replacing any previous `lt` comparison.
+    pub fn and_lt(self, value: T) -> Self {
+        Self {
+            lt: Some(value),
+            ..self
+        }
+    }
+
+    /// Also require values to be greater than or equal to `value`, replacing any previous `ge`
+    /// comparison.
+    pub fn and_ge(self, value: T) -> Self {
+        Self {
+            ge: Some(value),
+            ..self
+        }
+    }
+
+    /// Also require values to be less than or equal to `value`, replacing any previous `le`
+    /// comparison.
+    pub fn and_le(self, value: T) -> Self {
+        Self {
+            le: Some(value),
+            ..self
+        }
+    }
+
+    /// Also require values to be one of `values`, replacing any previous `in_` comparison.
+    pub fn and_in(self, values: Vec<T>) -> Self {
+        Self {
+            in_: Some(values),
+            ..self
+        }
+    }
+
+    /// Also require values to not be any of `values`, replacing any previous `nin` comparison.
+    pub fn and_nin(self, values: Vec<T>) -> Self {
+        Self {
+            nin: Some(values),
+            ..self
+        }
+    }
+}
+
+impl SimpleQuery<Date> {
+    /// A query matching dates from `start` to `end`, including both.
+    ///
+    /// This is the same as [`between`](Self::between), but named for the common case of a
+    /// statement period, whose last day is included.
+    pub fn date_range(start: Date, end: Date) -> Self {
+        Self::between(start, end)
+    }
+
+    /// A query matching dates in the same calendar month as `date`, from the first to the last
+    /// day of the month, including both.
+    pub fn month_of(date: Date) -> Self {
+        let last_day = date.month().length(date.year());
+        let start = date
+            .replace_day(1)
+            .expect("the first day of the month exists");
+        let end = date
+            .replace_day(last_day)
+            .expect("the last day of the month exists");
+        Self::between(start, end)
+    }
+}
+
+impl<T: PartialOrd> SimpleQuery<T> {
+    /// Returns whether any comparisons on the value are set, other than `is_null`.
+    fn has_comparisons(&self) -> bool {
+        [&self.eq, &self.ne, &self.gt, &self.lt, &self.ge, &self.le]
+            .iter()
+            .any(|value| value.is_some())
+            || self.in_.is_some()
+            || self.nin.is_some()
+    }
+
+    /// Check that the query has at least one comparison, and that its comparisons are
+    /// consistent with each other.
+    ///
+    /// Queries which can't match any value, because their bounds exclude each other, `eq` and `ne`
+    /// are the same value, or `in_` is empty, are rejected, as are empty queries, which match
+    /// every value.
+    pub fn validate(&self) -> std::result::Result<(), QueryValidationError> {
+        if !self.has_comparisons() && self.is_null.is_none() {
+            return Err(QueryValidationError::new("the query has no comparisons"));
+        }
+        self.check_comparisons()
+    }
+
+    /// Check that the comparisons are consistent with each other, allowing an empty query.
+    fn check_comparisons(&self) -> std::result::Result<(), QueryValidationError> {
+        let lower = [(&self.gt, true), (&self.ge, false)];
+        let upper = [(&self.lt, true), (&self.le, false)];
+        for (low, low_strict) in lower {
+            for (high, high_strict) in upper {
+                if let (Some(low), Some(high)) = (low, high) {
+                    // a range with equal bounds is only empty if either bound is strict
+                    let empty = if low_strict || high_strict {
+                        low >= high
+                    } else {
+                        low > high
+                    };
+                    if empty {
+                        return Err(QueryValidationError::new(
+                            "the lower bound of the query is not below its upper bound",
+                        ));
+                    }
+                }
+            }
+        }
+        if let (Some(eq), Some(ne)) = (&self.eq, &self.ne) {
+            if eq == ne {
+                return Err(QueryValidationError::new(
+                    "the query requires a value to be both equal and not equal to the same value",
+                ));
+            }
+        }
+        if self.is_null == Some(true) && self.has_comparisons() {
+            return Err(QueryValidationError::new(
+                "the query requires a value to be missing, but also compares it",
+            ));
+        }
+        if self.in_.as_ref().is_some_and(Vec::is_empty) {
+            return Err(QueryValidationError::new(
+                "the list of values is empty, so nothing matches",
+            )
+            .within("in"));
+        }
+        Ok(())
+    }
+}
+
+impl<T: PartialOrd> Query<T> for SimpleQuery<T> {
+    fn matches(&self, object: &T) -> bool {
+        self.is_null != Some(true)
+            && self.eq.as_ref().is_none_or(|value| object == value)
+            && self.ne.as_ref().is_none_or(|value| object != value)
+            && self.gt.as_ref().is_none_or(|value| object > value)
+            && self.lt.as_ref().is_none_or(|value| object < value)
+            && self.ge.as_ref().is_none_or(|value| object >= value)
+            && self.le.as_ref().is_none_or(|value| object <= value)
+            && self
+                .in_
+                .as_ref()
+                .is_none_or(|values| values.contains(object))
+            && self
+                .nin
+                .as_ref()
+                .is_none_or(|values| !values.contains(object))
+    }
+
+    fn validate(&self) -> std::result::Result<(), QueryValidationError> {
+        SimpleQuery::validate(self)
+    }
+}
+
+impl<T: PartialOrd> Query<Option<T>> for SimpleQuery<T> {
+    fn matches(&self, object: &Option<T>) -> bool {
+        match object {
+            Some(object) => self.matches(object),
+            None => self.is_null != Some(false) && !self.has_comparisons(),
+        }
+    }
+
+    fn validate(&self) -> std::result::Result<(), QueryValidationError> {
+        SimpleQuery::validate(self)
+    }
+}
+
+/// A query on a string value, made up of a set of comparisons which must all be satisfied.
+///
+/// An empty query matches all values. Values are compared in Unicode normalization form C, as
+/// given by [`normalize`], so that the same text written with combining characters matches.
+/// Regular expressions are matched as they are written.
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+#[serde(default)]
+pub struct StringQuery {
+    /// Comparisons which apply to values of any type.
+    #[serde(flatten)]
+    pub simple: SimpleQuery<String>,
+    /// The value must match this pattern, ignoring case.
+    ///
+    /// In the pattern, `%` matches any sequence of characters, `_` matches any single character,
+    /// and `\` matches the following character literally, as in SQL's `ILIKE`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub like: Option<String>,
+    /// The value must contain this. Every value contains the empty string.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub contains: Option<String>,
+    /// The value must start with this.
+    ///
+    /// Unlike `contains`, this can use an index on the field, if it doesn't ignore case.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub starts_with: Option<String>,
+    /// The value must match this regular expression.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub regex: Option<RegexPattern>,
+    /// Whether the equality comparisons (`eq`, `ne`, `in_` and `nin`), `contains`,
+    /// `starts_with`, and `regex` ignore case.
+    ///
+    /// Case is ignored by comparing the values with each character mapped to its lowercase form,
+    /// in the same way as for `like` patterns. The ordering comparisons always respect case.
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    pub ignore_case: bool,
+}
+
+impl StringQuery {
+    /// A query matching values equal to `value`.
+    pub fn eq(value: impl Into<String>) -> Self {
+        SimpleQuery::eq(value.into()).into()
+    }
+
+    /// A query matching values equal to `value`, ignoring case.
+    pub fn eq_ignore_case(value: impl Into<String>) -> Self {
+        Self {
+            ignore_case: true,
+            ..Self::eq(value)
+        }
+    }
+
+    /// Split the comparisons into the equality comparisons and the other comparisons, which are
+    /// affected differently by `ignore_case`.
+    ///
+    /// The other comparisons are the ordering comparisons and `is_null`.
+    pub fn equality_and_ordering(&self) -> (SimpleQuery<String>, SimpleQuery<String>) {
+        let SimpleQuery {
+            eq,
+            ne,
+            gt,
+            lt,
+            ge,
+            le,
+            in_,
+            nin,
+            is_null,
+        } = self.simple.clone();
+        (
+            SimpleQuery {
+                eq,
+                ne,
+                in_,
+                nin,
+                ..Default::default()
+            },
+            SimpleQuery {
+                gt,
+                lt,
+                ge,
+                le,
+                is_null,
+                ..Default::default()
+            },
+        )
+    }
+
+    /// The query with its values normalized, as given by [`normalize`], borrowing it if they
+    /// already are.
+    pub fn normalized(&self) -> Cow<'_, Self> {
+        let simple = &self.simple;
+        let mut values = [
+            &simple.eq,
+            &simple.ne,
+            &simple.gt,
+            &simple.lt,
+            &simple.ge,
+            &simple.le,
+            &self.like,
+            &self.contains,
+            &self.starts_with,
+        ]
+        .into_iter()
+        .flatten()
+        .chain(simple.in_.iter().flatten())
+        .chain(simple.nin.iter().flatten());
+        if values.all(|value| matches!(normalize(value), Cow::Borrowed(_))) {
+            return Cow::Borrowed(self);
+        }
+        let normalize = |value: &String| normalize(value).into_owned();
+        Cow::Owned(Self {
+            simple: simple.clone().map(|value| normalize(&value)),
+            like: self.like.as_ref().map(normalize),
+            contains: self.contains.as_ref().map(normalize),
+            starts_with: self.starts_with.as_ref().map(normalize),
+            regex: self.regex.clone(),
+            ignore_case: self.ignore_case,
+        })
+    }
+
+    /// A query matching values which match the pattern, ignoring case.
+    pub fn like(pattern: impl Into<String>) -> Self {
+        Self {
+            like: Some(pattern.into()),
+            ..Default::default()
+        }
+    }
+
+    /// A query matching values which contain `value`.
+    pub fn contains(value: impl Into<String>) -> Self {
+        Self {
+            contains: Some(value.into()),
+            ..Default::default()
+        }
+    }
+
+    /// A query matching values which start with `prefix`.
+    pub fn starts_with(prefix: impl Into<String>) -> Self {
+        Self {
+            starts_with: Some(prefix.into()),
+            ..Default::default()
+        }
+    }
+
+    /// A query matching values which match the regular expression.
+    ///
+    /// If the pattern is not a valid [`RegexPattern`], this will fail with
+    /// `Error::InvalidQuery`
+    pub fn regex(pattern: impl Into<String>) -> Result<Self> {
+        Ok(Self {
+            regex: Some(RegexPattern::new(pattern)?),
+            ..Default::default()
+        })
+    }
+}
+
+impl From<SimpleQuery<String>> for StringQuery {
+    fn from(simple: SimpleQuery<String>) -> Self {
+        Self {
+            simple,
+            ..Default::default()
+        }
+    }
+}
+
+impl Query<String> for StringQuery {
+    fn matches(&self, object: &String) -> bool {
+        let query = self.normalized();
+        let normalized;
+        let object = match normalize(object) {
+            Cow::Borrowed(_) => object,
+            Cow::Owned(value) => {
+                normalized = value;
+                &normalized
+            }
+        };
+        let simple_matches = if query.ignore_case {
+            let (equality, ordering) = query.equality_and_ordering();
+            let fold = |value: &String| fold_case(value);
+            let folded = SimpleQuery {
+                eq: equality.eq.as_ref().map(fold),
+                ne: equality.ne.as_ref().map(fold),
+                in_: equality.in_.map(|values| values.iter().map(fold).collect()),
+                nin: equality.nin.map(|values| values.iter().map(fold).collect()),
+                ..Default::default()
+            };
+            folded.matches(&fold_case(object)) && ordering.matches(object)
+        } else {
+            query.simple.matches(object)
+        };
+        simple_matches
+            && query
+                .like
+                .as_ref()
+                .is_none_or(|pattern| like_matches(pattern, object))
+            && query.contains.as_ref().is_none_or(|value| {
+                if query.ignore_case {
+                    fold_case(object).contains(&fold_case(value))
+                } else {
+                    object.contains(value.as_str())
+                }
+            })
+            && query.starts_with.as_ref().is_none_or(|prefix| {
+                if query.ignore_case {
+                    fold_case(object).starts_with(&fold_case(prefix))
+                } else {
+                    object.starts_with(prefix.as_str())
+                }
+            })
+            && query
+                .regex
+                .as_ref()
+                .is_none_or(|regex| regex.matches(object, query.ignore_case))
+    }
+
+    fn validate(&self) -> std::result::Result<(), QueryValidationError> {
+        self.simple.check_comparisons()?;
+        let is_empty = [&self.like, &self.contains, &self.starts_with]
+            .iter()
+            .all(|value| value.is_none())
+            && self.regex.is_none();
+        if is_empty && !self.simple.has_comparisons() && self.simple.is_null.is_none() {
+            return Err(QueryValidationError::new("the query has no comparisons"));
+        }
+        Ok(())
+    }
+}
+
+/// A regular expression in the common subset of the dialects supported by all backends.
+///
+/// Patterns are checked when they are created, including when they are deserialized, and are
+/// matched in memory with the `regex` crate. MongoDB uses PCRE, which supports many constructs
+/// that the `regex` crate doesn't, such as lookaround and backreferences, so the `regex` crate's
+/// parser rejects those. The remaining constructs which are specific to the `regex` crate are
+/// also rejected: class set operations (`[a-z&&[^aeiou]]`), the special word boundaries (`\<`,
+/// `\b{start}` and so on), and the `u` and `R` flags, the last of which means recursion to PCRE.
+///
+/// One difference remains: in memory, the classes `\d`, `\s`, `\w` and `\b` are
+/// Unicode-aware, whereas MongoDB only matches ASCII characters with them. Patterns which must
+/// behave the same way everywhere should use explicit classes like `[0-9]` or `\p{L}`.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct RegexPattern {
+    regex: regex::Regex,
+    regex_ignore_case: regex::Regex,
+}
+
+impl RegexPattern {
+    /// Check and compile a pattern.
+    ///
+    /// If the pattern is invalid or uses a construct not supported by all backends, this will
+    /// fail with `Error::InvalidQuery`
+    pub fn new(pattern: impl Into<String>) -> Result<Self> {
+        let pattern = pattern.into();
+        let ast = regex_syntax::ast::parse::Parser::new()
+            .parse(&pattern)
+            .map_err(|err| QueryValidationError::new(err.to_string()))?;
+        regex_syntax::ast::visit(&ast, PortabilityCheck)?;
+        let build = |ignore_case| {
+            regex::RegexBuilder::new(&pattern)
+                .case_insensitive(ignore_case)
+                .build()
+                .map_err(|err| QueryValidationError::new(err.to_string()))
+        };
+        Ok(Self {
+            regex: build(false)?,
+            regex_ignore_case: build(true)?,
+        })
+    }
+
+    /// The pattern, as it was written.
+    pub fn as_str(&self) -> &str {
+        self.regex.as_str()
+    }
+
+    /// Returns whether the pattern matches anywhere in the value.
+    pub fn matches(&self, value: &str, ignore_case: bool) -> bool {
+        if ignore_case {
+            self.regex_ignore_case.is_match(value)
+        } else {
+            self.regex.is_match(value)
+        }
+    }
+}
+
+impl TryFrom<String> for RegexPattern {
+    type Error = Error;
+
+    fn try_from(pattern: String) -> Result<Self> {
+        Self::new(pattern)
+    }
+}
+
+impl From<RegexPattern> for String {
+    fn from(pattern: RegexPattern) -> Self {
+        pattern.as_str().to_owned()
+    }
+}
+
+/// Rejects the constructs in a regular expression which are specific to the `regex` crate.
+struct PortabilityCheck;
+
+impl regex_syntax::ast::Visitor for PortabilityCheck {
+    type Output = ();
+    type Err = Error;
+
+    fn finish(self) -> Result<()> {
+        Ok(())
+    }
+
+    fn visit_pre(&mut self, ast: &regex_syntax::ast::Ast) -> Result<()> {
+        use regex_syntax::ast::{AssertionKind, Ast, Flag, FlagsItemKind, GroupKind};
+
+        let flags = match ast {
+            Ast::Assertion(assertion) => {
+                return match assertion.kind {
+                    AssertionKind::WordBoundaryStart
+                    | AssertionKind::WordBoundaryEnd
+                    | AssertionKind::WordBoundaryStartAngle
+                    | AssertionKind::WordBoundaryEndAngle
+                    | AssertionKind::WordBoundaryStartHalf
+                    | AssertionKind::WordBoundaryEndHalf => {
+                        Err(unsupported("special word boundaries"))
+                    }
+                    _ => Ok(()),
+                };
+            }
+            Ast::Flags(set_flags) => &set_flags.flags,
+            Ast::Group(group) => match &group.kind {
+                GroupKind::NonCapturing(flags) => flags,
+                _ => return Ok(()),
+            },
+            _ => return Ok(()),
+        };
+        for item in &flags.items {
+            match item.kind {
+                FlagsItemKind::Flag(Flag::Unicode) => return Err(unsupported("the `u` flag")),
+                FlagsItemKind::Flag(Flag::CRLF) => return Err(unsupported("the `R` flag")),
+                _ => {}
+            }
+        }
+        Ok(())
+    }
+
+    fn visit_class_set_binary_op_pre(
+        &mut self,
+        _op: &regex_syntax::ast::ClassSetBinaryOp,
+    ) -> Result<()> {
+        Err(unsupported("class set operations"))
+    }
+}
+
+/// The error for a construct in a regular expression which isn't supported by all backends.
+fn unsupported(construct: &str) -> Error {
+    QueryValidationError::new(format!(
+        "regular expressions can't use {construct}, which not all backends support"
+    ))
+    .into()
+}
+
+/// A single element of a `like` pattern.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum LikeToken {
+    /// Matches this character.
+    Literal(char),
+    /// Matches any single character.
+    Any,
+    /// Matches any sequence of characters.
+    AnySequence,
+}
+
+/// Split a `like` pattern into its elements.
+pub fn like_tokens(pattern: &str) -> impl Iterator<Item = LikeToken> + '_ {
+    let mut chars = pattern.chars();
+    std::iter::from_fn(move || {
+        Some(match chars.next()? {
+            '%' => LikeToken::AnySequence,
+            '_' => LikeToken::Any,
+            // a trailing backslash matches itself
+            '\\' => LikeToken::Literal(chars.next().unwrap_or('\\')),
+            c => LikeToken::Literal(c),
+        })
+    })
+}
+
+/// Normalize a string to Unicode normalization form C (NFC), in which characters are composed
+/// where possible, so that the same text is always written the same way.
+///
+/// Strings are compared in this form by [`StringQuery`], and backends which compare stored
+/// strings directly should store them in this form.
+pub fn normalize(value: &str) -> Cow<'_, str> {
+    match is_nfc_quick(value.chars()) {
+        IsNormalized::Yes => Cow::Borrowed(value),
+        IsNormalized::No | IsNormalized::Maybe => Cow::Owned(value.nfc().collect()),
+    }
+}
+
+/// Normalize a string and fold its case, so that strings which differ only in case are equal, as
+/// when a [`StringQuery`] ignores case.
+///
+/// Backends which store folded copies of strings to compare against should fold them with this.
+pub fn fold_case(value: &str) -> String {
+    normalize(value).chars().map(fold_char).collect()
+}
+
+/// Fold the case of a single character.
+///
+/// This is a one-to-one mapping, like the simple case folding used by MongoDB's case-insensitive
+/// regular expressions, so that a character still matches a single wildcard after folding. Unlike
+/// `str::to_lowercase`, it doesn't depend on the surrounding characters, which would make a final
+/// sigma (`ς`) differ from other sigmas.
+fn fold_char(c: char) -> char {
+    let mut lower = c.to_lowercase();
+    match (lower.next(), lower.next()) {
+        (Some('ς'), None) => 'σ',
+        (Some(lower), None) => lower,
+        // the few characters which lowercase to several characters have no simple folding
+        _ => c,
+    }
+}
+
+/// Returns whether the value matches the `like` pattern, ignoring case.
+fn like_matches(pattern: &str, value: &str) -> bool {
+    let pattern = like_tokens(pattern)
+        .map(|token| match token {
+            LikeToken::Literal(c) => LikeToken::Literal(fold_char(c)),
+            token => token,
+        })
+        .collect::<Vec<_>>();
+    let value = value.chars().map(fold_char).collect::<Vec<_>>();
+    // greedy matching, backtracking to the most recent `%` on a mismatch
+    let (mut p, mut v) = (0, 0);
+    let mut backtrack = None;
+    while v < value.len() {
+        match pattern.get(p) {
+            Some(LikeToken::AnySequence) => {
+                backtrack = Some((p, v));
+                p += 1;
+            }
+            Some(LikeToken::Any) => {
+                p += 1;
+                v += 1;
+            }
+            Some(&LikeToken::Literal(c)) if c == value[v] => {
+                p += 1;
+                v += 1;
+            }
+            _ => match backtrack {
+                Some((star_p, star_v)) => {
+                    backtrack = Some((star_p, star_v + 1));
+                    p = star_p + 1;
+                    v = star_v + 1;
+                }
+                None => return false,
+            },
+        }
+    }
+    pattern[p..]
+        .iter()
+        .all(|&token| token == LikeToken::AnySequence)
+}
+
+/// A query on an object, or on the group it belongs to.
+///
+/// This is serialized as an object with a `type`, the name of the variant in `snake_case`, and a
+/// `value`, except for [`Other`](Self::Other) queries, which are serialized as the query itself.
+/// The `type` must come before the `value`.
+#[derive(Derivative)]
+#[derivative(Clone(bound = ""), Debug(bound = "T::Query: std::fmt::Debug"))]
+pub enum WithGroupQuery<T: Queryable> {
+    /// The object belongs to one of these groups.
+    Group(Vec<Id<Group>>),
+    /// The object has one of these ids.
+    Ids(Vec<Id<T>>),
+    /// Query on when the object was created.
+    CreatedAt(SimpleQuery<OffsetDateTime>),
+    /// Query on when the object was last modified.
+    ModifiedAt(SimpleQuery<OffsetDateTime>),
+    /// The object doesn't match the query.
+    ///
+    /// For queries on fields with several values, such as the accounts of a transaction, this
+    /// matches objects for which none of the values match, not objects for which some value
+    /// doesn't match.
+    Not(Box<WithGroupQuery<T>>),
+    /// A query on the object itself.
+    Other(T::Query),
+}
+
+impl<T: Queryable> std::ops::Not for WithGroupQuery<T> {
+    type Output = Self;
+
+    /// A query matching objects which don't match this query.
+    fn not(self) -> Self {
+        Self::Not(Box::new(self))
+    }
+}
+
+impl<T: Queryable> Query<WithGroup<Versioned<T>>> for WithGroupQuery<T> {
+    fn matches(&self, object: &WithGroup<Versioned<T>>) -> bool {
+        match self {
+            Self::Group(groups) => groups.contains(&object.group),
+            Self::Ids(ids) => ids.contains(&object.object.id),
+            Self::CreatedAt(query) => query.matches(&object.object.created_at),
+            Self::ModifiedAt(query) => query.matches(&object.object.modified_at),
+            Self::Other(query) => query.matches(&object.object.object),
+            Self::Not(query) => !query.matches(object),
+        }
+    }
+
+    fn validate(&self) -> std::result::Result<(), QueryValidationError> {
+        match self {
+            Self::Group(groups) if groups.is_empty() => Err(QueryValidationError::new(
+                "the list of groups is empty, so nothing matches",
+            )
+            .within("group")),
+            Self::Group(_) => Ok(()),
+            Self::Ids(ids) if ids.is_empty() => Err(QueryValidationError::new(
+                "the list of ids is empty, so nothing matches",
+            )
+            .within("ids")),
+            Self::Ids(_) => Ok(()),
+            Self::CreatedAt(query) => query.validate().map_err(|err| err.within("created_at")),
+            Self::ModifiedAt(query) => query.validate().map_err(|err| err.within("modified_at")),
+            Self::Other(query) => query.validate(),
+            Self::Not(query) => query.validate().map_err(|err| err.within("not")),
+        }
+    }
+}
+
+/// A page of query results.
+#[derive(Clone, Copy, Debug, Deserialize, Serialize)]
+pub struct Pagination {
+    /// The maximum number of results to return.
+    pub limit: u32,
+    /// The number of results to skip before the first returned result.
+    pub offset: u32,
+}
+
+/// Options controlling how a query is run.
+#[derive(Clone, Copy, Debug, Default, Deserialize, Serialize)]
+pub struct QueryOptions {
+    /// The maximum time the query may run for, after which it fails with `Error::Timeout`.
+    pub timeout: Option<Duration>,
+    /// The maximum number of objects the query is estimated to examine, above which it fails with
+    /// `Error::QueryTooExpensive` without being run.
+    ///
+    /// How the estimate is made depends on the backend, and backends which can't estimate the
+    /// cost of a query ignore this.
+    pub max_cost: Option<u64>,
+}
+
+#[cfg(test)]
+mod tests {
+    use time::macros::{date, datetime};
+
+    use super::*;
+    use crate::{
+        backend::version::Version,
+        map::Map,
+        public::transaction::{Transaction, TransactionQuery},
+    };
+
+    fn stored(description: &str) -> WithGroup<Versioned<Transaction>> {
+        WithGroup {
+            group: Id::new_random(),
+            object: Versioned {
+                id: Id::new_random(),
+                version: Version::new_random(),
+                created_at: datetime!(2024-01-01 12:00 UTC),
+                modified_at: datetime!(2024-02-01 12:00 UTC),
+                object: Transaction {
+                    date: date!(2024 - 01 - 01),
+                    description: description.into(),
+                    amounts: Map::default(),
+                },
+            },
+        }
+    }
+
+    #[test]
+    fn with_group_query_matches() {
+        let object = stored("Rent");
+        let other = stored("Rent");
+        let description = |query| WithGroupQuery::Other(TransactionQuery::Description(query));
+        let cases = [
+            (WithGroupQuery::Group(vec![object.group]), true),
+            (WithGroupQuery::Group(vec![other.group]), false),
+            (
+                WithGroupQuery::Ids(vec![other.object.id, object.object.id]),
+                true,
+            ),
+            (WithGroupQuery::Ids(vec![other.object.id]), false),
+            (
+                WithGroupQuery::CreatedAt(SimpleQuery::eq(datetime!(2024-01-01 12:00 UTC))),
+                true,
+            ),
+            (
+                WithGroupQuery::ModifiedAt(SimpleQuery::range(..datetime!(2024-02-01 12:00 UTC))),
+                false,
+            ),
+            (description(StringQuery::eq("Rent")), true),
+            (description(StringQuery::eq("Food")), false),
+            (!description(StringQuery::eq("Food")), true),
+            (!WithGroupQuery::Group(vec![object.group]), false),
+        ];
+        for (query, expected) in cases {
+            assert_eq!(query.matches(&object), expected, "{query:?}");
+        }
+    }
+
+    fn string_matches(query: &StringQuery, value: &str) -> bool {
+        query.matches(&value.to_owned())
+    }
+
+    #[test]
+    fn ignore_case() {
+        let query = StringQuery::eq_ignore_case("groceries");
+        for value in ["groceries", "Groceries", "GROCERIES"] {
+            assert!(string_matches(&query, value), "{value}");
+        }
+        assert!(!string_matches(&query, "Grocery"));
+        assert!(!string_matches(&StringQuery::eq("groceries"), "Groceries"));
+
+        let query = StringQuery {
+            ignore_case: true,
+            ..SimpleQuery::in_(vec!["rent".into(), "FOOD".into()]).into()
+        };
+        assert!(string_matches(&query, "Food"));
+        assert!(!string_matches(&query, "Fuel"));
+        let query = StringQuery {
+            ignore_case: true,
+            ..SimpleQuery::default().and_ne("rent".into()).into()
+        };
+        assert!(!string_matches(&query, "RENT"));
+        assert!(string_matches(&query, "Food"));
+    }
+
+    #[test]
+    fn ignore_case_unicode() {
+        // final and non-final sigmas fold to the same character
+        let query = StringQuery::eq_ignore_case("ΣΟΦΟΣ");
+        assert!(string_matches(&query, "σοφος"));
+        assert!(string_matches(&query, "σοφοσ"));
+        assert!(string_matches(
+            &StringQuery::eq_ignore_case("Ärger"),
+            "ärger"
+        ));
+        // folding is one character to one character, as in MongoDB, so `ß` isn't `ss`
+        assert!(!string_matches(
+            &StringQuery::eq_ignore_case("STRASSE"),
+            "Straße"
+        ));
+    }
+
+    #[test]
+    fn normalization() {
+        // "Café" with a precomposed "é", and with "e" followed by a combining acute accent
+        let (nfc, nfd) = ("Caf\u{e9}", "Cafe\u{301}");
+        assert_eq!(normalize(nfd), nfc);
+        assert!(matches!(normalize(nfc), Cow::Borrowed(_)));
+        for (query, value) in [(nfc, nfd), (nfd, nfc), (nfd, nfd)] {
+            assert!(string_matches(&StringQuery::eq(query), value), "{query:?}");
+            assert!(string_matches(
+                &StringQuery::eq_ignore_case(query.to_uppercase()),
+                value
+            ));
+            let query = format!("{query} Noir");
+            let value = format!("The {value} Noir");
+            assert!(string_matches(&StringQuery::contains(&query), &value));
+            assert!(string_matches(
+                &StringQuery::like(format!("%{query}")),
+                &value
+            ));
+        }
+        assert!(string_matches(
+            &StringQuery::starts_with(nfd),
+            "Caf\u{e9} Noir"
+        ));
+        assert!(string_matches(
+            &SimpleQuery::in_(vec![nfd.into()]).into(),
+            "Caf\u{e9}"
+        ));
+        // the values are ordered in the same way as well
+        let query: StringQuery = SimpleQuery::range(nfd.to_owned()..="Caf\u{e9}".into()).into();
+        assert!(string_matches(&query, nfc));
+        // "Cafe" followed by an accent sorts after "Cafe" plus any other letter
+        let query: StringQuery = SimpleQuery::default().and_gt("Cafez".into()).into();
+        assert!(string_matches(&query, nfd));
+        assert!(string_matches(&query, nfc));
+    }
+
+    #[test]
+    fn ignore_case_ordering() {
+        // the ordering comparisons respect case, and uppercase letters sort first
+        let query = StringQuery {
+            ignore_case: true,
+            ..SimpleQuery::default().and_gt("a".into()).into()
+        };
+        assert!(string_matches(&query, "b"));
+        assert!(!string_matches(&query, "B"));
+    }
+
+    #[test]
+    fn contains() {
+        // every value contains the empty string
+        let query = StringQuery::contains("");
+        assert!(string_matches(&query, ""));
+        assert!(string_matches(&query, "Rent"));
+
+        let query = StringQuery::contains("fee");
+        assert!(string_matches(&query, "Bank fee"));
+        assert!(string_matches(&query, "feet"));
+        assert!(!string_matches(&query, "Bank FEE"));
+        let query = StringQuery {
+            ignore_case: true,
+            ..StringQuery::contains("fee")
+        };
+        assert!(string_matches(&query, "Bank FEE"));
+
+        // wildcards and regex metacharacters are matched literally
+        for (value, matching, other) in [
+            ("15%", "Tip 15%", "Tip 150"),
+            ("a_b", "x a_b y", "x acb y"),
+            ("(a.b)*", "(a.b)*", "aab"),
+            (r"C:\", r"C:\Users", "C:/Users"),
+        ] {
+            let query = StringQuery::contains(value);
+            assert!(string_matches(&query, matching), "{value} in {matching}");
+            assert!(!string_matches(&query, other), "{value} in {other}");
+        }
+    }
+
+    #[test]
+    fn like() {
+        for (pattern, value, expected) in [
+            ("rent", "Rent", true),
+            ("rent", "Rent due", false),
+            ("%fee%", "Bank FEE refund", true),
+            ("%fee", "fee refund", false),
+            ("%", "", true),
+            ("g_oceries", "Groceries", true),
+            ("g_oceries", "Gceries", false),
+            ("_", "", false),
+            ("a%b%c", "aXbYbZc", true),
+            ("a%b%c", "aXcYb", false),
+            (r"15\%", "15%", true),
+            (r"15\%", "150", false),
+            (r"a\_b", "acb", false),
+            (r"a\\b", r"a\b", true),
+            // a trailing backslash matches itself
+            (r"C:\", r"C:\", true),
+            // wildcards match newlines, and characters rather than bytes
+            ("a_c", "a\nc", true),
+            ("caf_", "café", true),
+            ("σοφο_", "ΣΟΦΟΣ", true),
+        ] {
+            assert_eq!(
+                string_matches(&StringQuery::like(pattern), value),
+                expected,
+                "{pattern:?} against {value:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn regex_portability() {
+        for pattern in [
+            r"^[A-Z][a-z]+$",
+            r"\d{4}-\d{2}",
+            r"(?i)rent",
+            r"(?:ab)+|c*?",
+            r"\bfee\b",
+            r"[^0-9\s]",
+            r"\p{L}+",
+            r"[[:alpha:]]",
+        ] {
+            assert!(RegexPattern::new(pattern).is_ok(), "{pattern}");
+        }
+        for pattern in [
+            // invalid everywhere
+            r"(",
+            // PCRE only
+            r"(?=a)",
+            r"(?<!a)b",
+            r"(a)\1",
+            // `regex` crate only
+            r"[a-z&&[^aeiou]]",
+            r"[a-z--b]",
+            r"[a-z~~m-z]",
+            r"\<word\>",
+            r"\b{start}",
+            r"(?u)\w",
+            r"(?u:\w)",
+            r"(?R)^a$",
+        ] {
+            assert!(
+                matches!(RegexPattern::new(pattern), Err(Error::InvalidQuery(_))),
+                "{pattern}"
+            );
+        }
+    }
+
+    #[test]
+    fn regex_matches() {
+        let query = StringQuery::regex("^rent( |$)").unwrap();
+        assert!(string_matches(&query, "rent for January"));
+        assert!(!string_matches(&query, "Rent"));
+        assert!(!string_matches(&query, "current"));
+        let query = StringQuery {
+            ignore_case: true,
+            ..query
+        };
+        assert!(string_matches(&query, "Rent"));
+    }
+
+    #[test]
+    fn regex_deserialize() {
+        let query = serde_json::from_str::<StringQuery>(r#"{ "regex": "^a+$" }"#).unwrap();
+        assert_eq!(query.regex.unwrap().as_str(), "^a+$");
+        assert!(serde_json::from_str::<StringQuery>(r#"{ "regex": "(?=a)" }"#).is_err());
+    }
+
+    #[test]
+    fn range_constructors() {
+        let query = SimpleQuery::range(1..5);
+        assert_eq!(
+            (query.ge, query.lt, query.gt, query.le),
+            (Some(1), Some(5), None, None)
+        );
+        let query = SimpleQuery::range(..=5);
+        assert_eq!((query.ge, query.le), (None, Some(5)));
+        let query = SimpleQuery::range((Bound::Excluded(1), Bound::Unbounded));
+        assert_eq!((query.gt, query.lt), (Some(1), None));
+        let query = SimpleQuery::between(1, 5);
+        assert_eq!((query.ge, query.le), (Some(1), Some(5)));
+        assert!(query.matches(&1) && query.matches(&5) && !query.matches(&6));
+    }
+
+    #[test]
+    fn simple_query_matrix() {
+        let operators: [(&str, SimpleQuery<i32>, [bool; 3]); 10] = [
+            ("eq", SimpleQuery::eq(2), [false, true, false]),
+            ("ne", SimpleQuery::default().and_ne(2), [true, false, true]),
+            ("gt", SimpleQuery::default().and_gt(2), [false, false, true]),
+            ("lt", SimpleQuery::default().and_lt(2), [true, false, false]),
+            ("ge", SimpleQuery::default().and_ge(2), [false, true, true]),
+            ("le", SimpleQuery::default().and_le(2), [true, true, false]),
+            ("in", SimpleQuery::in_(vec![1, 3]), [true, false, true]),
+            (
+                "nin",
+                SimpleQuery::default().and_nin(vec![1, 3]),
+                [false, true, false],
+            ),
+            ("range", SimpleQuery::range(2..3), [false, true, false]),
+            ("between", SimpleQuery::between(1, 2), [true, true, false]),
+        ];
+        for (name, query, expected) in operators {
+            assert!(query.validate().is_ok(), "{name}");
+            for (value, expected) in [1, 2, 3].into_iter().zip(expected) {
+                assert_eq!(query.matches(&value), expected, "{name} against {value}");
+            }
+        }
+    }
+
+    #[test]
+    fn simple_query_validate() {
+        let rejected = [
+            SimpleQuery::default(),
+            SimpleQuery::default().and_gt(2).and_lt(2),
+            SimpleQuery::default().and_gt(3).and_lt(2),
+            SimpleQuery::default().and_ge(2).and_lt(2),
+            SimpleQuery::default().and_gt(2).and_le(2),
+            SimpleQuery::default().and_ge(3).and_le(2),
+            SimpleQuery::eq(2).and_ne(2),
+            SimpleQuery::in_(Vec::new()),
+        ];
+        for query in rejected {
+            assert!(query.validate().is_err(), "{query:?}");
+        }
+        assert_eq!(
+            SimpleQuery::<i32>::in_(Vec::new())
+                .validate()
+                .unwrap_err()
+                .clause,
+            ["in"]
+        );
+        for query in [
+            SimpleQuery::between(2, 2),
+            SimpleQuery::eq(2).and_ne(3),
+            SimpleQuery::default().and_nin(Vec::new()),
+        ] {
+            assert!(query.validate().is_ok(), "{query:?}");
+        }
+    }
+
+    #[test]
+    fn is_null() {
+        fn matches(query: &SimpleQuery<i32>, value: Option<i32>) -> bool {
+            query.matches(&value)
+        }
+
+        let missing = SimpleQuery {
+            is_null: Some(true),
+            ..Default::default()
+        };
+        assert!(matches(&missing, None));
+        assert!(!matches(&missing, Some(1)));
+        let present = SimpleQuery {
+            is_null: Some(false),
+            ..Default::default()
+        };
+        assert!(!matches(&present, None));
+        assert!(matches(&present, Some(1)));
+        // missing values don't satisfy any comparison, even `ne`
+        assert!(!matches(&SimpleQuery::default().and_ne(1), None));
+        assert!(matches(&SimpleQuery::default().and_ne(1), Some(2)));
+        assert!(matches(&SimpleQuery::default(), None));
+        let present_above = SimpleQuery {
+            is_null: Some(false),
+            ..SimpleQuery::default().and_gt(1)
+        };
+        assert!(!matches(&present_above, None));
+        assert!(!matches(&present_above, Some(1)));
+        assert!(matches(&present_above, Some(2)));
+
+        assert!(missing.validate().is_ok());
+        assert!(present_above.validate().is_ok());
+        let contradictory = SimpleQuery {
+            is_null: Some(true),
+            ..SimpleQuery::eq(1)
+        };
+        assert!(contradictory.validate().is_err());
+    }
+
+    #[test]
+    fn not_involving_account() {
+        let [x, y, z] = [(); 3].map(|()| Id::new_random());
+        let mut object = stored("Transfer");
+        object.object.object.amounts.insert(x, Amount::ZERO);
+        object.object.object.amounts.insert(y, Amount::ZERO);
+        let account = |id| WithGroupQuery::Other(TransactionQuery::Account(SimpleQuery::eq(id)));
+
+        assert!(!(!account(x)).matches(&object));
+        assert!(!(!account(y)).matches(&object));
+        assert!((!account(z)).matches(&object));
+        // unlike "involving an account other than X", which it does
+        let other_than_x = TransactionQuery::Account(SimpleQuery::default().and_ne(x));
+        assert!(WithGroupQuery::Other(other_than_x).matches(&object));
+        assert!((!!account(x)).matches(&object));
+    }
+
+    #[test]
+    fn validate_clauses() {
+        let description = |query| WithGroupQuery::Other(TransactionQuery::Description(query));
+        let rejected: [(WithGroupQuery<Transaction>, &[&str]); 9] = [
+            (WithGroupQuery::Group(Vec::new()), &["group"]),
+            (WithGroupQuery::Ids(Vec::new()), &["ids"]),
+            (
+                WithGroupQuery::CreatedAt(SimpleQuery::default()),
+                &["created_at"],
+            ),
+            (
+                WithGroupQuery::ModifiedAt(SimpleQuery::between(
+                    datetime!(2024-02-01 00:00 UTC),
+                    datetime!(2024-01-01 00:00 UTC),
+                )),
+                &["modified_at"],
+            ),
+            (!WithGroupQuery::Group(Vec::new()), &["not", "group"]),
+            (description(StringQuery::default()), &["description"]),
+            (
+                description(StringQuery {
+                    contains: Some("fee".into()),
+                    ..SimpleQuery::in_(Vec::new()).into()
+                }),
+                &["description", "in"],
+            ),
+            (
+                WithGroupQuery::Other(TransactionQuery::Account(SimpleQuery::in_(Vec::new()))),
+                &["account", "in"],
+            ),
+            (
+                WithGroupQuery::Other(TransactionQuery::Total(
+                    SimpleQuery::default().and_lt(Amount::ZERO),
+                )),
+                &["total"],
+            ),
+        ];
+        for (query, clause) in rejected {
+            let err = query.validate().expect_err(&format!("{query:?}"));
+            assert_eq!(err.clause, clause, "{query:?}");
+        }
+        for query in [
+            WithGroupQuery::Group(vec![Id::new_random()]),
+            description(StringQuery::contains("fee")),
+            description(
+                SimpleQuery {
+                    is_null: Some(false),
+                    ..Default::default()
+                }
+                .into(),
+            ),
+        ] {
+            assert!(query.validate().is_ok(), "{query:?}");
+        }
+
+        let expr = boolean::BooleanExpr::Any(vec![
+            boolean::BooleanExpr::Query(WithGroupQuery::<Transaction>::Group(vec![
+                Id::new_random(),
+            ])),
+            boolean::BooleanExpr::Not(Box::new(boolean::BooleanExpr::Query(WithGroupQuery::Ids(
+                Vec::new(),
+            )))),
+        ]);
+        assert_eq!(
+            expr.validate().unwrap_err().clause,
+            ["any[1]", "not", "ids"]
+        );
+    }
+
+    #[test]
+    fn date_range() {
+        let query = SimpleQuery::date_range(date!(2024 - 01 - 01), date!(2024 - 01 - 31));
+        for (date, expected) in [
+            (date!(2023 - 12 - 31), false),
+            (date!(2024 - 01 - 01), true),
+            (date!(2024 - 01 - 31), true),
+            (date!(2024 - 02 - 01), false),
+        ] {
+            assert_eq!(query.matches(&date), expected, "{date}");
+        }
+        assert!(
+            SimpleQuery::date_range(date!(2024 - 01 - 01), date!(2024 - 01 - 01))
+                .validate()
+                .is_ok()
+        );
+        assert!(
+            SimpleQuery::date_range(date!(2024 - 01 - 02), date!(2024 - 01 - 01))
+                .validate()
+                .is_err()
+        );
+    }
+
+    #[test]
+    fn month_of() {
+        for (date, start, end) in [
+            (
+                date!(2024 - 02 - 15),
+                date!(2024 - 02 - 01),
+                date!(2024 - 02 - 29),
+            ),
+            (
+                date!(2023 - 02 - 28),
+                date!(2023 - 02 - 01),
+                date!(2023 - 02 - 28),
+            ),
+            (
+                date!(2024 - 12 - 01),
+                date!(2024 - 12 - 01),
+                date!(2024 - 12 - 31),
+            ),
+        ] {
+            let query = SimpleQuery::month_of(date);
+            assert_eq!((query.ge, query.le), (Some(start), Some(end)), "{date}");
+            assert!(query.matches(&start) && query.matches(&end));
+            assert!(!query.matches(&start.previous_day().unwrap()));
+            assert!(!query.matches(&end.next_day().unwrap()));
+        }
+    }
+}
@@ -0,0 +1,93 @@
+//! Boolean combinations of queries.
+
+use crate::backend::query::{Query, QueryValidationError};
+
+/// A boolean combination of queries.
+#[derive(Clone, Debug)]
+pub enum BooleanExpr<Q> {
+    /// Matches objects matching the query.
+    Query(Q),
+    /// Matches objects matching all of the expressions. If there are no expressions, this
+    /// matches all objects.
+    All(Vec<BooleanExpr<Q>>),
+    /// Matches objects matching any of the expressions. If there are no expressions, this
+    /// matches no objects.
+    Any(Vec<BooleanExpr<Q>>),
+    /// Matches objects not matching the expression.
+    Not(Box<BooleanExpr<Q>>),
+}
+
+impl<Q> BooleanExpr<Q> {
+    /// An expression matching objects matching all of the queries, as the flat lists of queries
+    /// taken by most `Collection` methods do.
+    pub fn all(query: impl IntoIterator<Item = Q>) -> Self {
+        Self::All(query.into_iter().map(Self::Query).collect())
+    }
+
+    /// Reduce the expression to a single value, by applying `query` to each query, and `all`,
+    /// `any`, and `not` to the results of their subexpressions.
+    pub fn fold<R>(&self, folder: &mut impl Folder<Q, R>) -> R {
+        match self {
+            Self::Query(query) => folder.query(query),
+            Self::All(exprs) => {
+                let results = exprs.iter().map(|expr| expr.fold(folder)).collect();
+                folder.all(results)
+            }
+            Self::Any(exprs) => {
+                let results = exprs.iter().map(|expr| expr.fold(folder)).collect();
+                folder.any(results)
+            }
+            Self::Not(expr) => {
+                let result = expr.fold(folder);
+                folder.not(result)
+            }
+        }
+    }
+}
+
+impl<T: ?Sized, Q: Query<T>> Query<T> for BooleanExpr<Q> {
+    fn matches(&self, object: &T) -> bool {
+        match self {
+            Self::Query(query) => query.matches(object),
+            Self::All(exprs) => exprs.iter().all(|expr| expr.matches(object)),
+            Self::Any(exprs) => exprs.iter().any(|expr| expr.matches(object)),
+            Self::Not(expr) => !expr.matches(object),
+        }
+    }
+
+    fn validate(&self) -> Result<(), QueryValidationError> {
+        match self {
+            Self::Query(query) => query.validate(),
+            Self::All(exprs) => validate_all(exprs, "all"),
+            Self::Any(exprs) => validate_all(exprs, "any"),
+            Self::Not(expr) => expr.validate().map_err(|err| err.within("not")),
+        }
+    }
+}
+
+/// Validate each of the expressions, marking errors with their position in the list.
+fn validate_all<T: ?Sized, Q: Query<T>>(
+    exprs: &[BooleanExpr<Q>],
+    name: &str,
+) -> Result<(), QueryValidationError> {
+    for (i, expr) in exprs.iter().enumerate() {
+        expr.validate()
+            .map_err(|err| err.within(format!("{name}[{i}]")))?;
+    }
+    Ok(())
+}
+
+/// A reduction of a [`BooleanExpr`] to a single value, as by [`BooleanExpr::fold`].
+pub trait Folder<Q, R> {
+    /// Reduce a single query.
+    fn query(&mut self, query: &Q) -> R;
+
+    /// Combine the results of the subexpressions of an `All` expression.
+    fn all(&mut self, results: Vec<R>) -> R;
+
+    /// Combine the results of the subexpressions of an `Any` expression.
+    fn any(&mut self, results: Vec<R>) -> R;
+
+    /// Negate the result of the subexpression of a `Not` expression.
+    fn not(&mut self, result: R) -> R;
+}
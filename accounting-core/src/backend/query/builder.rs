@@ -0,0 +1,88 @@
+//! Building lists of queries one clause at a time.
+
+use derivative::Derivative;
+use time::OffsetDateTime;
+
+use crate::backend::{
+    id::Id,
+    query::{boolean::BooleanExpr, Queryable, SimpleQuery, WithGroupQuery},
+    user::Group,
+};
+
+/// A builder for the lists of queries taken by most `Collection` methods, which match objects
+/// matching all of the queries.
+///
+/// Each method adds a clause; the methods for a particular type, such as
+/// [`TransactionQueryBuilder`](crate::public::transaction::TransactionQueryBuilder), are defined
+/// alongside its `Query` type.
+#[derive(Derivative)]
+#[derivative(Clone(bound = ""), Debug(bound = "T::Query: std::fmt::Debug"))]
+pub struct QueryBuilder<T: Queryable> {
+    queries: Vec<WithGroupQuery<T>>,
+}
+
+impl<T: Queryable> QueryBuilder<T> {
+    /// A builder with no clauses, which matches all objects.
+    pub fn new() -> Self {
+        Self {
+            queries: Vec::new(),
+        }
+    }
+
+    /// Add a clause.
+    pub fn and(mut self, query: WithGroupQuery<T>) -> Self {
+        self.queries.push(query);
+        self
+    }
+
+    /// Add a clause on the object itself.
+    pub fn and_other(self, query: T::Query) -> Self {
+        self.and(WithGroupQuery::Other(query))
+    }
+
+    /// Only match objects in one of the groups.
+    pub fn in_groups(self, groups: impl IntoIterator<Item = Id<Group>>) -> Self {
+        self.and(WithGroupQuery::Group(groups.into_iter().collect()))
+    }
+
+    /// Only match objects with one of the ids.
+    pub fn with_ids(self, ids: impl IntoIterator<Item = Id<T>>) -> Self {
+        self.and(WithGroupQuery::Ids(ids.into_iter().collect()))
+    }
+
+    /// Only match objects created at a time matching the query.
+    pub fn created_at(self, query: SimpleQuery<OffsetDateTime>) -> Self {
+        self.and(WithGroupQuery::CreatedAt(query))
+    }
+
+    /// Only match objects last modified at a time matching the query.
+    pub fn modified_at(self, query: SimpleQuery<OffsetDateTime>) -> Self {
+        self.and(WithGroupQuery::ModifiedAt(query))
+    }
+
+    /// Only match objects modified after `since`, such as the latest `modified_at` of the
+    /// objects a client has already synced.
+    pub fn modified_since(self, since: OffsetDateTime) -> Self {
+        self.modified_at(SimpleQuery {
+            gt: Some(since),
+            ..Default::default()
+        })
+    }
+
+    /// The list of queries.
+    pub fn build(self) -> Vec<WithGroupQuery<T>> {
+        self.queries
+    }
+
+    /// An expression matching objects matching all of the queries, to combine with other
+    /// expressions.
+    pub fn build_expr(self) -> BooleanExpr<WithGroupQuery<T>> {
+        BooleanExpr::all(self.queries)
+    }
+}
+
+impl<T: Queryable> Default for QueryBuilder<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -0,0 +1,168 @@
+//! Descriptions of the fields of each queryable type, for frontends which build queries from
+//! forms rather than constructing them directly.
+
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+
+use crate::backend::query::{Query, QueryValidationError, Queryable};
+
+/// The fields of a type which can be queried by comparing them against a value.
+///
+/// Fields which are queried in other ways, such as by a user and an access level together, are
+/// left out.
+#[derive(Clone, Debug, Serialize)]
+pub struct QuerySchema {
+    pub fields: Vec<FieldSchema>,
+}
+
+/// A field which can be queried.
+#[derive(Clone, Debug, Serialize)]
+pub struct FieldSchema {
+    /// The name of the field, as in the `type` of a serialized query.
+    pub name: &'static str,
+    /// The type of the values the field is compared against.
+    pub value_type: ValueType,
+    /// The comparisons supported on the field.
+    pub operators: &'static [Operator],
+}
+
+/// The type of the values a field is compared against, which are in the same form as in a
+/// serialized query.
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ValueType {
+    String,
+    Integer,
+    /// An amount of money, as an object with its decimal string `value`.
+    Amount,
+    /// A date, as a string such as `2024-01-31`.
+    Date,
+    /// The id of an object.
+    Id,
+    /// One of a fixed set of values, in order, if the values are ordered.
+    Choice {
+        options: &'static [&'static str],
+    },
+}
+
+/// A comparison against a value, named as in a serialized query.
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Operator {
+    Eq,
+    Ne,
+    Gt,
+    Lt,
+    Ge,
+    Le,
+    /// Compared against a list of values.
+    In,
+    /// Compared against a list of values.
+    Nin,
+    /// Compared against a boolean, for fields which may be missing.
+    IsNull,
+    Like,
+    Contains,
+    StartsWith,
+    Regex,
+}
+
+impl Operator {
+    /// The comparisons supported on fields which can only be compared for equality.
+    pub const EQUALITY: &'static [Self] = &[Self::Eq, Self::Ne, Self::In, Self::Nin];
+
+    /// The comparisons supported on ordered fields.
+    pub const ORDERED: &'static [Self] = &[
+        Self::Eq,
+        Self::Ne,
+        Self::Gt,
+        Self::Lt,
+        Self::Ge,
+        Self::Le,
+        Self::In,
+        Self::Nin,
+    ];
+
+    /// The comparisons supported on string fields.
+    pub const STRING: &'static [Self] = &[
+        Self::Eq,
+        Self::Ne,
+        Self::Gt,
+        Self::Lt,
+        Self::Ge,
+        Self::Le,
+        Self::In,
+        Self::Nin,
+        Self::Like,
+        Self::Contains,
+        Self::StartsWith,
+        Self::Regex,
+    ];
+
+    /// The name of the comparison in a serialized query.
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Eq => "eq",
+            Self::Ne => "ne",
+            Self::Gt => "gt",
+            Self::Lt => "lt",
+            Self::Ge => "ge",
+            Self::Le => "le",
+            Self::In => "in",
+            Self::Nin => "nin",
+            Self::IsNull => "is_null",
+            Self::Like => "like",
+            Self::Contains => "contains",
+            Self::StartsWith => "starts_with",
+            Self::Regex => "regex",
+        }
+    }
+}
+
+/// An error constructing a query from a field, operator, and value.
+#[derive(Debug, thiserror::Error)]
+pub enum QueryFieldError {
+    #[error("Unknown field {0:?}")]
+    UnknownField(String),
+    #[error("Field {field:?} does not support the {} comparison", .operator.name())]
+    UnsupportedOperator {
+        field: &'static str,
+        operator: Operator,
+    },
+    #[error("Invalid value: {0}")]
+    InvalidValue(#[from] serde_json::Error),
+    #[error("Invalid query: {0}")]
+    InvalidQuery(#[from] QueryValidationError),
+}
+
+/// Construct a query comparing a field of `T` against a value, as submitted from a form built
+/// from [`Queryable::schema`].
+///
+/// The value is checked against the type of the field, and the query is validated.
+pub fn query_from_field<T>(
+    field: &str,
+    operator: Operator,
+    value: serde_json::Value,
+) -> Result<T::Query, QueryFieldError>
+where
+    T: Queryable,
+    T::Query: DeserializeOwned,
+{
+    let schema = T::schema();
+    let field_schema = schema
+        .fields
+        .iter()
+        .find(|schema| schema.name == field)
+        .ok_or_else(|| QueryFieldError::UnknownField(field.into()))?;
+    if !field_schema.operators.contains(&operator) {
+        return Err(QueryFieldError::UnsupportedOperator {
+            field: field_schema.name,
+            operator,
+        });
+    }
+    let mut comparison = serde_json::Map::new();
+    comparison.insert(operator.name().into(), value);
+    let query = serde_json::json!({ "type": field, "value": comparison });
+    let query = serde_json::from_value::<T::Query>(query)?;
+    query.validate()?;
+    Ok(query)
+}
@@ -0,0 +1,592 @@
+//! Serialization for [`SimpleQuery`], [`BooleanExpr`] and [`WithGroupQuery`], in the format
+//! described in the [parent module](super).
+//!
+//! A [`SimpleQuery`] is an object with a field for each of its comparisons which is set. Other
+//! fields are ignored, so that [`StringQuery`](super::StringQuery) can add its own.
+//!
+//! A [`BooleanExpr`] is an object with exactly one field, naming the kind of expression, whose
+//! value is the query or the subexpressions.
+//!
+//! Each [`WithGroupQuery`] is an object with a `type` and a `value`. The `type`s `group`, `ids`,
+//! `created_at`, `modified_at` and `not` are the queries [`WithGroupQuery`] handles itself; any
+//! other `type` is passed on, along with its `value`, to the `Query` type of the object being
+//! queried, which must therefore not use any of these. The `type` must come before the `value`,
+//! so that the `value` can be read without buffering it.
+
+use std::{fmt, marker::PhantomData};
+
+use serde::{
+    de::{
+        self, value::StrDeserializer, DeserializeSeed, EnumAccess, IgnoredAny, IntoDeserializer,
+        MapAccess, VariantAccess, Visitor,
+    },
+    forward_to_deserialize_any,
+    ser::SerializeStruct,
+    Deserialize, Deserializer, Serialize, Serializer,
+};
+use time::OffsetDateTime;
+
+use super::{boolean::BooleanExpr, Queryable, SimpleQuery, WithGroupQuery};
+
+const SIMPLE_QUERY_FIELDS: &[&str] = &["eq", "ne", "gt", "lt", "ge", "le", "in", "nin", "is_null"];
+
+impl<T: Serialize> Serialize for SimpleQuery<T> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        fn field<S: SerializeStruct, V: Serialize>(
+            state: &mut S,
+            key: &'static str,
+            value: &Option<V>,
+        ) -> Result<(), S::Error> {
+            match value {
+                Some(value) => state.serialize_field(key, value),
+                None => state.skip_field(key),
+            }
+        }
+
+        let Self {
+            eq,
+            ne,
+            gt,
+            lt,
+            ge,
+            le,
+            in_,
+            nin,
+            is_null,
+        } = self;
+        let len = [
+            eq.is_some(),
+            ne.is_some(),
+            gt.is_some(),
+            lt.is_some(),
+            ge.is_some(),
+            le.is_some(),
+            in_.is_some(),
+            nin.is_some(),
+            is_null.is_some(),
+        ]
+        .into_iter()
+        .filter(|&is_set| is_set)
+        .count();
+        let mut state = serializer.serialize_struct("SimpleQuery", len)?;
+        field(&mut state, "eq", eq)?;
+        field(&mut state, "ne", ne)?;
+        field(&mut state, "gt", gt)?;
+        field(&mut state, "lt", lt)?;
+        field(&mut state, "ge", ge)?;
+        field(&mut state, "le", le)?;
+        field(&mut state, "in", in_)?;
+        field(&mut state, "nin", nin)?;
+        field(&mut state, "is_null", is_null)?;
+        state.end()
+    }
+}
+
+impl<'de, T: Deserialize<'de>> Deserialize<'de> for SimpleQuery<T> {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        deserializer.deserialize_struct(
+            "SimpleQuery",
+            SIMPLE_QUERY_FIELDS,
+            SimpleQueryVisitor(PhantomData),
+        )
+    }
+}
+
+struct SimpleQueryVisitor<T>(PhantomData<fn() -> T>);
+
+impl<'de, T: Deserialize<'de>> Visitor<'de> for SimpleQueryVisitor<T> {
+    type Value = SimpleQuery<T>;
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("a query with comparisons")
+    }
+
+    fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<Self::Value, A::Error> {
+        /// Read the value of a field, checking that it hasn't already been read.
+        fn set<'de, A: MapAccess<'de>, V: Deserialize<'de>>(
+            map: &mut A,
+            slot: &mut Option<V>,
+            key: &'static str,
+        ) -> Result<(), A::Error> {
+            if slot.is_some() {
+                return Err(de::Error::duplicate_field(key));
+            }
+            *slot = map.next_value()?;
+            Ok(())
+        }
+
+        let mut query = SimpleQuery::default();
+        while let Some(key) = map.next_key::<String>()? {
+            match key.as_str() {
+                "eq" => set(&mut map, &mut query.eq, "eq")?,
+                "ne" => set(&mut map, &mut query.ne, "ne")?,
+                "gt" => set(&mut map, &mut query.gt, "gt")?,
+                "lt" => set(&mut map, &mut query.lt, "lt")?,
+                "ge" => set(&mut map, &mut query.ge, "ge")?,
+                "le" => set(&mut map, &mut query.le, "le")?,
+                "in" => set(&mut map, &mut query.in_, "in")?,
+                "nin" => set(&mut map, &mut query.nin, "nin")?,
+                "is_null" => set(&mut map, &mut query.is_null, "is_null")?,
+                _ => {
+                    map.next_value::<IgnoredAny>()?;
+                }
+            }
+        }
+        Ok(query)
+    }
+}
+
+const BOOLEAN_EXPR_VARIANTS: &[&str] = &["query", "all", "any", "not"];
+
+impl<Q: Serialize> Serialize for BooleanExpr<Q> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        match self {
+            Self::Query(query) => {
+                serializer.serialize_newtype_variant("BooleanExpr", 0, "query", query)
+            }
+            Self::All(exprs) => {
+                serializer.serialize_newtype_variant("BooleanExpr", 1, "all", exprs)
+            }
+            Self::Any(exprs) => {
+                serializer.serialize_newtype_variant("BooleanExpr", 2, "any", exprs)
+            }
+            Self::Not(expr) => serializer.serialize_newtype_variant("BooleanExpr", 3, "not", expr),
+        }
+    }
+}
+
+impl<'de, Q: Deserialize<'de>> Deserialize<'de> for BooleanExpr<Q> {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        deserializer.deserialize_enum(
+            "BooleanExpr",
+            BOOLEAN_EXPR_VARIANTS,
+            BooleanExprVisitor(PhantomData),
+        )
+    }
+}
+
+struct BooleanExprVisitor<Q>(PhantomData<fn() -> Q>);
+
+impl<'de, Q: Deserialize<'de>> Visitor<'de> for BooleanExprVisitor<Q> {
+    type Value = BooleanExpr<Q>;
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("an object with exactly one of `query`, `all`, `any`, or `not`")
+    }
+
+    fn visit_enum<A: EnumAccess<'de>>(self, data: A) -> Result<Self::Value, A::Error> {
+        let (kind, variant) = data.variant::<String>()?;
+        match kind.as_str() {
+            "query" => variant.newtype_variant().map(BooleanExpr::Query),
+            "all" => variant.newtype_variant().map(BooleanExpr::All),
+            "any" => variant.newtype_variant().map(BooleanExpr::Any),
+            "not" => variant.newtype_variant().map(BooleanExpr::Not),
+            _ => Err(de::Error::unknown_variant(&kind, BOOLEAN_EXPR_VARIANTS)),
+        }
+    }
+}
+
+const FIELDS: &[&str] = &["type", "value"];
+
+#[derive(Serialize)]
+struct Tagged<V> {
+    #[serde(rename = "type")]
+    type_: &'static str,
+    value: V,
+}
+
+#[derive(Deserialize, Serialize)]
+#[serde(transparent)]
+struct DateTimeQuery(#[serde(with = "crate::serde::datetime_query")] SimpleQuery<OffsetDateTime>);
+
+impl<T: Queryable> Serialize for WithGroupQuery<T>
+where
+    T::Query: Serialize,
+{
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        fn tagged<V: Serialize, S: Serializer>(
+            type_: &'static str,
+            value: V,
+            serializer: S,
+        ) -> Result<S::Ok, S::Error> {
+            Tagged { type_, value }.serialize(serializer)
+        }
+
+        match self {
+            Self::Group(groups) => tagged("group", groups, serializer),
+            Self::Ids(ids) => tagged("ids", ids, serializer),
+            Self::CreatedAt(query) => {
+                tagged("created_at", DateTimeQuery(query.clone()), serializer)
+            }
+            Self::ModifiedAt(query) => {
+                tagged("modified_at", DateTimeQuery(query.clone()), serializer)
+            }
+            Self::Not(query) => tagged("not", query, serializer),
+            Self::Other(query) => query.serialize(serializer),
+        }
+    }
+}
+
+impl<'de, T: Queryable> Deserialize<'de> for WithGroupQuery<T>
+where
+    T::Query: Deserialize<'de>,
+{
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let human_readable = deserializer.is_human_readable();
+        deserializer.deserialize_struct(
+            "WithGroupQuery",
+            FIELDS,
+            WithGroupQueryVisitor {
+                human_readable,
+                _marker: PhantomData,
+            },
+        )
+    }
+}
+
+struct WithGroupQueryVisitor<T> {
+    human_readable: bool,
+    _marker: PhantomData<fn() -> T>,
+}
+
+impl<'de, T: Queryable> Visitor<'de> for WithGroupQueryVisitor<T>
+where
+    T::Query: Deserialize<'de>,
+{
+    type Value = WithGroupQuery<T>;
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("a query with a `type` and a `value`")
+    }
+
+    fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<Self::Value, A::Error> {
+        match map.next_key::<String>()?.as_deref() {
+            Some("type") => {}
+            Some("value") => {
+                return Err(de::Error::custom(
+                    "the `type` of a query must come before its `value`",
+                ))
+            }
+            Some(key) => return Err(de::Error::unknown_field(key, FIELDS)),
+            None => return Err(de::Error::missing_field("type")),
+        }
+        let type_ = map.next_value::<String>()?;
+        let query = match type_.as_str() {
+            "group" => WithGroupQuery::Group(next_value(&mut map)?),
+            "ids" => WithGroupQuery::Ids(next_value(&mut map)?),
+            "created_at" => WithGroupQuery::CreatedAt(next_value::<_, DateTimeQuery>(&mut map)?.0),
+            "modified_at" => {
+                WithGroupQuery::ModifiedAt(next_value::<_, DateTimeQuery>(&mut map)?.0)
+            }
+            "not" => WithGroupQuery::Not(next_value(&mut map)?),
+            _ => WithGroupQuery::Other(T::Query::deserialize(TaggedMap {
+                type_: Some(type_),
+                type_key_read: false,
+                map,
+                human_readable: self.human_readable,
+            })?),
+        };
+        Ok(query)
+    }
+}
+
+/// Read the `value` of a query, once its `type` has been read, and check that there are no other
+/// fields.
+fn next_value<'de, A: MapAccess<'de>, V: Deserialize<'de>>(map: &mut A) -> Result<V, A::Error> {
+    match map.next_key::<String>()?.as_deref() {
+        Some("value") => {}
+        Some(key) => return Err(de::Error::unknown_field(key, FIELDS)),
+        None => return Err(de::Error::missing_field("value")),
+    }
+    let value = map.next_value()?;
+    match map.next_key::<String>()? {
+        Some(key) => Err(de::Error::unknown_field(&key, FIELDS)),
+        None => Ok(value),
+    }
+}
+
+/// The rest of a map whose `type` has already been read, with the `type` put back in front.
+struct TaggedMap<A> {
+    type_: Option<String>,
+    type_key_read: bool,
+    map: A,
+    human_readable: bool,
+}
+
+impl<'de, A: MapAccess<'de>> Deserializer<'de> for TaggedMap<A> {
+    type Error = A::Error;
+
+    fn deserialize_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+        visitor.visit_map(self)
+    }
+
+    fn is_human_readable(&self) -> bool {
+        self.human_readable
+    }
+
+    forward_to_deserialize_any! {
+        bool i8 i16 i32 i64 i128 u8 u16 u32 u64 u128 f32 f64 char str string bytes byte_buf option
+        unit unit_struct newtype_struct seq tuple tuple_struct map struct enum identifier
+        ignored_any
+    }
+}
+
+impl<'de, A: MapAccess<'de>> MapAccess<'de> for TaggedMap<A> {
+    type Error = A::Error;
+
+    fn next_key_seed<K: DeserializeSeed<'de>>(
+        &mut self,
+        seed: K,
+    ) -> Result<Option<K::Value>, Self::Error> {
+        if self.type_key_read {
+            self.map.next_key_seed(seed)
+        } else {
+            self.type_key_read = true;
+            let key: StrDeserializer<Self::Error> = "type".into_deserializer();
+            seed.deserialize(key).map(Some)
+        }
+    }
+
+    fn next_value_seed<V: DeserializeSeed<'de>>(
+        &mut self,
+        seed: V,
+    ) -> Result<V::Value, Self::Error> {
+        match self.type_.take() {
+            Some(type_) => seed.deserialize(type_.into_deserializer()),
+            None => self.map.next_value_seed(seed),
+        }
+    }
+
+    fn size_hint(&self) -> Option<usize> {
+        self.map.size_hint().map(|size| size + 1)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::{json, Value};
+
+    use super::*;
+    use crate::{
+        backend::{id::Id, query::StringQuery},
+        public::transaction::{Transaction, TransactionQuery},
+    };
+
+    fn round_trip(fixture: Value) -> WithGroupQuery<Transaction> {
+        let query: WithGroupQuery<Transaction> = serde_json::from_value(fixture.clone()).unwrap();
+        assert_eq!(serde_json::to_value(&query).unwrap(), fixture);
+        query
+    }
+
+    // ids are random, so they aren't written out in the fixtures
+
+    #[test]
+    fn group() {
+        let groups = [Id::new_random(), Id::new_random()];
+        let query = round_trip(json!({ "type": "group", "value": groups }));
+        assert!(matches!(query, WithGroupQuery::Group(actual) if actual == groups));
+    }
+
+    #[test]
+    fn ids() {
+        let ids = [Id::new_random()];
+        let query = round_trip(json!({ "type": "ids", "value": ids }));
+        assert!(matches!(query, WithGroupQuery::Ids(actual) if actual == ids));
+    }
+
+    #[test]
+    fn created_at() {
+        let query = round_trip(json!({
+            "type": "created_at",
+            "value": { "ge": "2024-01-01T00:00:00Z" },
+        }));
+        assert!(matches!(
+            query,
+            WithGroupQuery::CreatedAt(query)
+                if query.ge == Some(time::macros::datetime!(2024-01-01 00:00 UTC))
+        ));
+    }
+
+    #[test]
+    fn modified_at() {
+        let query = round_trip(json!({
+            "type": "modified_at",
+            "value": { "lt": "2024-06-30T12:30:00Z" },
+        }));
+        assert!(matches!(
+            query,
+            WithGroupQuery::ModifiedAt(query)
+                if query.lt == Some(time::macros::datetime!(2024-06-30 12:30 UTC))
+        ));
+    }
+
+    #[test]
+    fn not() {
+        let query = round_trip(json!({
+            "type": "not",
+            "value": { "type": "description", "value": { "contains": "fee" } },
+        }));
+        assert!(matches!(
+            query,
+            WithGroupQuery::Not(query) if matches!(*query, WithGroupQuery::Other(_))
+        ));
+    }
+
+    #[test]
+    fn other() {
+        let query = round_trip(json!({
+            "type": "date",
+            "value": { "ge": "2024-01-01", "lt": "2025-01-01" },
+        }));
+        assert!(matches!(
+            query,
+            WithGroupQuery::Other(TransactionQuery::Date(query))
+                if query.ge == Some(time::macros::date!(2024-01-01))
+        ));
+        let query = round_trip(json!({ "type": "account_count", "value": { "eq": 3 } }));
+        assert!(matches!(
+            query,
+            WithGroupQuery::Other(TransactionQuery::AccountCount(query)) if query.eq == Some(3)
+        ));
+    }
+
+    #[test]
+    fn simple_query() {
+        let fixture = json!({
+            "eq": 1,
+            "ne": 2,
+            "gt": 3,
+            "lt": 4,
+            "ge": 5,
+            "le": 6,
+            "in": [7, 8],
+            "nin": [9],
+            "is_null": false,
+        });
+        let query = serde_json::from_value::<SimpleQuery<i32>>(fixture.clone()).unwrap();
+        assert_eq!(query.eq, Some(1));
+        assert_eq!(query.in_, Some(vec![7, 8]));
+        assert_eq!(query.is_null, Some(false));
+        assert_eq!(serde_json::to_value(&query).unwrap(), fixture);
+        // comparisons which aren't set are left out
+        let query = serde_json::from_value::<SimpleQuery<i32>>(json!({})).unwrap();
+        assert!(query.eq.is_none() && query.is_null.is_none());
+        assert_eq!(serde_json::to_value(&query).unwrap(), json!({}));
+        // the fields of a `StringQuery` are passed over by its `SimpleQuery`
+        let fixture = json!({ "eq": "a", "like": "b%", "ignore_case": true });
+        let query = serde_json::from_value::<StringQuery>(fixture.clone()).unwrap();
+        assert_eq!(query.simple.eq.as_deref(), Some("a"));
+        assert_eq!(query.like.as_deref(), Some("b%"));
+        assert_eq!(serde_json::to_value(&query).unwrap(), fixture);
+    }
+
+    #[test]
+    fn boolean_expr() {
+        // the example from the documentation of the wire format
+        let fixture = json!({
+            "all": [
+                { "query": { "type": "group", "value": [1234] } },
+                {
+                    "query": {
+                        "type": "date",
+                        "value": { "ge": "2024-01-01", "lt": "2025-01-01" },
+                    },
+                },
+                {
+                    "not": {
+                        "query": { "type": "description", "value": { "contains": "fee" } },
+                    },
+                },
+            ],
+        });
+        let expr =
+            serde_json::from_value::<BooleanExpr<WithGroupQuery<Transaction>>>(fixture.clone())
+                .unwrap();
+        let BooleanExpr::All(exprs) = &expr else {
+            panic!("{expr:?}");
+        };
+        assert!(matches!(
+            &exprs[..],
+            [
+                BooleanExpr::Query(WithGroupQuery::Group(_)),
+                BooleanExpr::Query(WithGroupQuery::Other(TransactionQuery::Date(_))),
+                BooleanExpr::Not(expr),
+            ] if matches!(**expr, BooleanExpr::Query(WithGroupQuery::Other(_)))
+        ));
+        assert_eq!(serde_json::to_value(&expr).unwrap(), fixture);
+        let fixture = json!({ "any": [] });
+        let expr =
+            serde_json::from_value::<BooleanExpr<SimpleQuery<i32>>>(fixture.clone()).unwrap();
+        assert!(matches!(&expr, BooleanExpr::Any(exprs) if exprs.is_empty()));
+        assert_eq!(serde_json::to_value(&expr).unwrap(), fixture);
+    }
+
+    #[test]
+    fn bson_round_trip() {
+        // dates are stored as BSON datetimes, rather than strings
+        fn round_trip(query: WithGroupQuery<Transaction>, fixture: bson::Document) {
+            // the raw BSON serializer, which the driver uses, isn't human-readable
+            let serialize =
+                |query| bson::from_slice::<bson::Document>(&bson::to_vec(query).unwrap());
+            assert_eq!(serialize(&query).unwrap(), fixture);
+            let bytes = bson::to_vec(&fixture).unwrap();
+            let query = bson::from_slice(&bytes).unwrap();
+            assert_eq!(serialize(&query).unwrap(), fixture);
+        }
+
+        let date = bson::DateTime::from_millis(1_704_067_200_000);
+        round_trip(
+            WithGroupQuery::CreatedAt(SimpleQuery::eq(
+                time::macros::datetime!(2024-01-01 00:00 UTC),
+            )),
+            bson::doc! { "type": "created_at", "value": { "eq": date } },
+        );
+        round_trip(
+            WithGroupQuery::Other(TransactionQuery::Date(SimpleQuery::eq(
+                time::macros::date!(2024 - 01 - 01),
+            ))),
+            bson::doc! { "type": "date", "value": { "eq": date } },
+        );
+        let expr = BooleanExpr::Not(Box::new(BooleanExpr::Query(SimpleQuery::eq(1))));
+        let fixture = bson::doc! { "not": { "query": { "eq": 1 } } };
+        assert_eq!(bson::to_document(&expr).unwrap(), fixture);
+        let expr = bson::from_document::<BooleanExpr<SimpleQuery<i32>>>(fixture).unwrap();
+        assert!(matches!(
+            expr,
+            BooleanExpr::Not(expr)
+                if matches!(*expr, BooleanExpr::Query(SimpleQuery { eq: Some(1), .. }))
+        ));
+    }
+
+    #[test]
+    fn errors() {
+        for fixture in [
+            r#"{ "value": [], "type": "group" }"#,
+            r#"{ "type": "group", "value": [], "other": 1 }"#,
+            r#"{ "type": "group" }"#,
+            r#"{ "type": "name", "value": {} }"#,
+        ] {
+            assert!(
+                serde_json::from_str::<WithGroupQuery<Transaction>>(fixture).is_err(),
+                "{fixture}"
+            );
+        }
+        for fixture in [r#"{ "eq": 1, "eq": 2 }"#, r#"{ "in": 1 }"#, "[1]"] {
+            assert!(
+                serde_json::from_str::<SimpleQuery<i32>>(fixture).is_err(),
+                "{fixture}"
+            );
+        }
+        for fixture in [
+            r#"{ "some": [] }"#,
+            r#"{ "all": [], "any": [] }"#,
+            r#"{ "query": { "eq": 1 }, "not": { "query": {} } }"#,
+            r#""query""#,
+        ] {
+            assert!(
+                serde_json::from_str::<BooleanExpr<SimpleQuery<i32>>>(fixture).is_err(),
+                "{fixture}"
+            );
+        }
+    }
+}
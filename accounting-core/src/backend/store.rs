@@ -0,0 +1,54 @@
+//! Storage of all of the collections of a backend, and atomic units of work across them.
+
+use async_trait::async_trait;
+
+use crate::{
+    backend::{
+        audit::AuditLog,
+        collection::Collection,
+        user::{Group, User},
+    },
+    error::Result,
+    public::{account::Account, transaction::Transaction},
+};
+
+/// The collections making up a backend.
+pub struct Collections {
+    pub users: Box<dyn Collection<User> + Send + Sync>,
+    pub groups: Box<dyn Collection<Group> + Send + Sync>,
+    pub accounts: Box<dyn Collection<Account> + Send + Sync>,
+    pub transactions: Box<dyn Collection<Transaction> + Send + Sync>,
+    pub audit: Box<dyn AuditLog + Send + Sync>,
+}
+
+/// A storage layer for all of the collections of a backend.
+#[async_trait]
+pub trait Store: Send + Sync {
+    /// Open the collections, with each operation on them taking effect immediately.
+    fn collections(&self) -> Collections;
+
+    /// Begin a unit of work, within which operations on any of the collections take effect
+    /// together or not at all.
+    async fn begin(&self) -> Result<Box<dyn UnitOfWork>>;
+
+    /// Begin a unit of work within which all reads observe the collections as they were at a
+    /// single point in time.
+    ///
+    /// No writes may be performed within the unit of work, and it is always aborted.
+    async fn snapshot(&self) -> Result<Box<dyn UnitOfWork>>;
+}
+
+/// A set of operations across collections which take effect together or not at all.
+#[async_trait]
+pub trait UnitOfWork: Send + Sync {
+    /// Open the collections, with each operation on them taking place within the unit of work.
+    ///
+    /// The collections must be dropped before the unit of work is committed or aborted.
+    fn collections(&self) -> Collections;
+
+    /// Apply all of the operations performed within the unit of work.
+    async fn commit(self: Box<Self>) -> Result<()>;
+
+    /// Discard all of the operations performed within the unit of work.
+    async fn abort(self: Box<Self>) -> Result<()>;
+}
@@ -0,0 +1,945 @@
+//! Checks that implementations of [`Collection`] behave as the trait documents, for backends to
+//! run from their own tests.
+//!
+//! The checks use a collection of transactions, and are run against storage collections, such as
+//! [`InMemoryCollection`](crate::backend::memory::InMemoryCollection) or a database-backed
+//! collection. [`Backend`](crate::backend::Backend) also checks permissions and validates
+//! objects, so it isn't expected to pass them.
+//!
+//! Failures panic, like assertions in a test, with a message describing which behavior differs.
+//! The check of [`Collection::watch`] waits for a change to be reported, so hangs rather than
+//! failing if it never is.
+
+use std::{collections::BTreeSet, future::Future};
+
+use futures::{StreamExt, TryStreamExt};
+
+use time::{macros::date, Date};
+
+use crate::{
+    backend::{
+        collection::{ChangeEvent, Collection},
+        id::Id,
+        query::{
+            boolean::BooleanExpr, Pagination, Query, QueryOptions, SimpleQuery, Sort, StringQuery,
+            WithGroupQuery,
+        },
+        user::{Group, WithGroup},
+        version::{Version, Versioned},
+    },
+    error::{Error, Result},
+    map::Map,
+    public::{
+        account::Account,
+        amount::Amount,
+        transaction::{
+            Transaction, TransactionGroupBy, TransactionPatch, TransactionQuery,
+            TransactionSortField, TransactionTextField,
+        },
+    },
+};
+
+/// Run all of the checks, each against a new, empty collection from `make_collection`.
+///
+/// The collections must record history.
+pub async fn run_collection_tests<C, F, Fut>(mut make_collection: F)
+where
+    C: Collection<Transaction> + Send + Sync,
+    F: FnMut() -> Fut,
+    Fut: Future<Output = C>,
+{
+    check_create_and_get(&mut make_collection().await).await;
+    check_update(&mut make_collection().await).await;
+    check_patch(&mut make_collection().await).await;
+    check_delete(&mut make_collection().await).await;
+    check_change_group(&mut make_collection().await).await;
+    check_history(&mut make_collection().await).await;
+    check_queries(&mut make_collection().await).await;
+    check_sorting(&mut make_collection().await).await;
+    check_pagination(&mut make_collection().await).await;
+    check_date_range(&mut make_collection().await).await;
+    check_watch(&mut make_collection().await).await;
+    check_max_cost(&mut make_collection().await).await;
+}
+
+/// The amount of a whole number of cents.
+fn cents(cents: i64) -> Amount {
+    Amount::from_units(cents * 10_i64.pow(Amount::SCALE - 2))
+}
+
+/// A transaction moving `amount` cents from the second account to the first.
+fn transaction(
+    date: Date,
+    description: &str,
+    accounts: [Id<Account>; 2],
+    amount: i64,
+) -> Transaction {
+    let mut amounts = Map::default();
+    amounts.insert(accounts[0], cents(amount));
+    amounts.insert(accounts[1], cents(-amount));
+    Transaction {
+        date,
+        description: description.into(),
+        amounts,
+    }
+}
+
+/// A transaction moving `amount` cents from each of the other accounts to the first.
+fn split_transaction(description: &str, accounts: &[Id<Account>], amount: i64) -> Transaction {
+    let mut amounts = Map::default();
+    let (&first, others) = accounts
+        .split_first()
+        .expect("there is at least one account");
+    amounts.insert(first, cents(amount * others.len() as i64));
+    for &account in others {
+        amounts.insert(account, cents(-amount));
+    }
+    Transaction {
+        date: date!(2024 - 01 - 01),
+        description: description.into(),
+        amounts,
+    }
+}
+
+/// A transaction in an arbitrary pair of accounts.
+fn simple_transaction(description: &str) -> Transaction {
+    transaction(
+        date!(2024 - 01 - 01),
+        description,
+        [Id::new_random(), Id::new_random()],
+        100,
+    )
+}
+
+async fn create<C: Collection<Transaction>>(
+    collection: &mut C,
+    group: Id<Group>,
+    transaction: Transaction,
+) -> Id<Transaction> {
+    collection
+        .create(WithGroup {
+            group,
+            object: transaction,
+        })
+        .await
+        .expect("create should succeed")
+}
+
+async fn check_create_and_get<C: Collection<Transaction> + Send + Sync>(collection: &mut C) {
+    let group = Id::new_random();
+    let id = create(collection, group, simple_transaction("Rent")).await;
+    let object = collection
+        .get(id)
+        .await
+        .expect("get should succeed")
+        .expect("get should find a created object");
+    assert_eq!(object.group, group, "get should return the object's group");
+    assert_eq!(object.object.id, id, "get should return the object's id");
+    assert_eq!(object.object.object.description, "Rent");
+    assert!(
+        collection.exists(id).await.unwrap(),
+        "a created object should exist"
+    );
+    assert_eq!(collection.get_group(id).await.unwrap(), Some(group));
+    assert_eq!(
+        collection.get_version(id).await.unwrap(),
+        Some(object.object.version),
+        "get_version should match the version returned by get"
+    );
+
+    let missing = Id::new_random();
+    assert!(
+        collection.get(missing).await.unwrap().is_none(),
+        "get should return None for a missing id"
+    );
+    assert!(!collection.exists(missing).await.unwrap());
+    assert_eq!(collection.get_group(missing).await.unwrap(), None);
+    assert_eq!(collection.get_version(missing).await.unwrap(), None);
+    let many = collection.get_many(&[id, missing]).await.unwrap();
+    assert_eq!(
+        many.iter()
+            .map(|object| object.object.id)
+            .collect::<Vec<_>>(),
+        [id],
+        "get_many should omit missing ids"
+    );
+}
+
+async fn check_update<C: Collection<Transaction> + Send + Sync>(collection: &mut C) {
+    let id = create(collection, Id::new_random(), simple_transaction("Rent")).await;
+    let mut object = collection.get(id).await.unwrap().unwrap().object;
+    let old_version = object.version;
+    object.object.description = "Rent for January".into();
+    let new_version = collection
+        .update(object.clone())
+        .await
+        .expect("update at the current version should succeed");
+    assert_ne!(new_version, old_version, "update should change the version");
+    let updated = collection.get(id).await.unwrap().unwrap().object;
+    assert_eq!(updated.version, new_version);
+    assert_eq!(updated.object.description, "Rent for January");
+    assert_eq!(
+        updated.created_at, object.created_at,
+        "update should not change the creation time"
+    );
+
+    assert!(
+        matches!(
+            collection.update(object.clone()).await,
+            Err(Error::ConflictingEdit)
+        ),
+        "update at an old version should fail with ConflictingEdit"
+    );
+    object.id = Id::new_random();
+    assert!(
+        matches!(collection.update(object).await, Err(Error::NotFound)),
+        "update of a missing id should fail with NotFound"
+    );
+}
+
+async fn check_patch<C: Collection<Transaction> + Send + Sync>(collection: &mut C) {
+    let id = create(collection, Id::new_random(), simple_transaction("Rent")).await;
+    let version = collection.get_version(id).await.unwrap().unwrap();
+    let patch = TransactionPatch {
+        date: Some(date!(2024 - 02 - 01)),
+        ..Default::default()
+    };
+    let new_version = collection
+        .patch(id, version, patch.clone())
+        .await
+        .expect("patch at the current version should succeed");
+    assert_ne!(new_version, version, "patch should change the version");
+    let patched = collection.get(id).await.unwrap().unwrap().object.object;
+    assert_eq!(patched.date, date!(2024 - 02 - 01));
+    assert_eq!(
+        patched.description, "Rent",
+        "patch should only change the fields which are set"
+    );
+
+    assert!(
+        matches!(
+            collection.patch(id, version, patch.clone()).await,
+            Err(Error::ConflictingEdit)
+        ),
+        "patch at an old version should fail with ConflictingEdit"
+    );
+    assert!(
+        matches!(
+            collection.patch(Id::new_random(), version, patch).await,
+            Err(Error::NotFound)
+        ),
+        "patch of a missing id should fail with NotFound"
+    );
+}
+
+async fn check_delete<C: Collection<Transaction> + Send + Sync>(collection: &mut C) {
+    let group = Id::new_random();
+    let id = create(collection, group, simple_transaction("Rent")).await;
+    collection.delete(id).await.expect("delete should succeed");
+    assert!(
+        collection.get(id).await.unwrap().is_none(),
+        "get should not return deleted objects"
+    );
+    assert!(!collection.exists(id).await.unwrap());
+    let query = [WithGroupQuery::Group(vec![group])];
+    assert!(
+        collection
+            .query_ids(&query, QueryOptions::default())
+            .await
+            .unwrap()
+            .is_empty(),
+        "queries should not return deleted objects"
+    );
+    assert!(
+        collection.get_deleted(id).await.unwrap().is_some(),
+        "get_deleted should return deleted objects"
+    );
+    assert_eq!(collection.query_deleted(&query).await.unwrap().len(), 1);
+
+    collection
+        .delete(Id::new_random())
+        .await
+        .expect("delete of a missing id should do nothing");
+
+    collection
+        .restore(id)
+        .await
+        .expect("restore should succeed");
+    assert!(
+        collection.get(id).await.unwrap().is_some(),
+        "restored objects should be returned by get"
+    );
+    assert!(collection.get_deleted(id).await.unwrap().is_none());
+    assert!(
+        matches!(collection.restore(id).await, Err(Error::NotFound)),
+        "restore of an object which isn't deleted should fail with NotFound"
+    );
+
+    collection.delete(id).await.unwrap();
+    collection.purge(id).await.expect("purge should succeed");
+    assert!(collection.get(id).await.unwrap().is_none());
+    assert!(
+        collection.get_deleted(id).await.unwrap().is_none(),
+        "purged objects should not be returned by get_deleted"
+    );
+    assert!(
+        matches!(collection.purge(id).await, Err(Error::NotFound)),
+        "purge of an object which isn't deleted should fail with NotFound"
+    );
+
+    let ids = [
+        create(collection, group, simple_transaction("Groceries")).await,
+        create(collection, group, simple_transaction("Salary")).await,
+    ];
+    let query = [WithGroupQuery::Other(TransactionQuery::Description(
+        StringQuery::eq("Groceries"),
+    ))];
+    assert_eq!(
+        collection.delete_many(&query).await.unwrap(),
+        1,
+        "delete_many should return the number of objects deleted"
+    );
+    assert!(collection.get(ids[0]).await.unwrap().is_none());
+    assert!(collection.get(ids[1]).await.unwrap().is_some());
+}
+
+async fn check_change_group<C: Collection<Transaction> + Send + Sync>(collection: &mut C) {
+    let (old_group, new_group) = (Id::new_random(), Id::new_random());
+    let id = create(collection, old_group, simple_transaction("Rent")).await;
+    let other = create(collection, old_group, simple_transaction("Groceries")).await;
+    let version = collection.get_version(id).await.unwrap().unwrap();
+    collection
+        .change_group(id, new_group)
+        .await
+        .expect("change_group should succeed");
+    assert_eq!(collection.get_group(id).await.unwrap(), Some(new_group));
+    assert_ne!(
+        collection.get_version(id).await.unwrap(),
+        Some(version),
+        "change_group should change the version"
+    );
+    assert_eq!(
+        collection.get_group(other).await.unwrap(),
+        Some(old_group),
+        "change_group should only move the object with the id"
+    );
+    collection
+        .change_group(Id::new_random(), new_group)
+        .await
+        .expect("change_group of a missing id should do nothing");
+
+    collection
+        .change_group_many(&[id, other, Id::new_random()], old_group)
+        .await
+        .expect("change_group_many should ignore missing ids");
+    assert_eq!(collection.get_group(id).await.unwrap(), Some(old_group));
+    assert_eq!(collection.get_group(other).await.unwrap(), Some(old_group));
+}
+
+async fn check_history<C: Collection<Transaction> + Send + Sync>(collection: &mut C) {
+    let group = Id::new_random();
+    let id = create(collection, group, simple_transaction("Rent")).await;
+    let first = collection.get(id).await.unwrap().unwrap().object;
+    let mut updated = first.clone();
+    updated.object.description = "Rent for January".into();
+    let second = collection.update(updated).await.unwrap();
+    let third = collection
+        .upsert(
+            id,
+            WithGroup {
+                group,
+                object: simple_transaction("Rent for February"),
+            },
+        )
+        .await
+        .expect("upsert of an existing id should succeed");
+    let history = collection.history(id).await.unwrap();
+    assert_eq!(
+        history
+            .iter()
+            .map(|previous| previous.object.version)
+            .collect::<Vec<_>>(),
+        [first.version, second],
+        "update and upsert should both record the replaced version, oldest first"
+    );
+    assert_eq!(history[1].object.object.description, "Rent for January");
+
+    let reverted = collection
+        .revert(id, first.version)
+        .await
+        .expect("revert to a previous version should succeed");
+    let current = collection.get(id).await.unwrap().unwrap().object;
+    assert_eq!(current.version, reverted);
+    assert_eq!(current.object.description, "Rent");
+    assert_eq!(
+        collection
+            .history(id)
+            .await
+            .unwrap()
+            .last()
+            .unwrap()
+            .object
+            .version,
+        third,
+        "revert should record the replaced version"
+    );
+
+    let new = Id::new_random();
+    collection
+        .upsert(
+            new,
+            WithGroup {
+                group,
+                object: simple_transaction("Rent"),
+            },
+        )
+        .await
+        .expect("upsert of a new id should succeed");
+    assert!(
+        collection.history(new).await.unwrap().is_empty(),
+        "upsert of a new id should not record history"
+    );
+
+    collection.delete(id).await.unwrap();
+    let deleted = collection
+        .get_deleted(id)
+        .await
+        .unwrap()
+        .unwrap()
+        .object
+        .version;
+    collection
+        .upsert(
+            id,
+            WithGroup {
+                group,
+                object: simple_transaction("Rent for March"),
+            },
+        )
+        .await
+        .expect("upsert of a deleted id should succeed");
+    assert_eq!(
+        collection
+            .history(id)
+            .await
+            .unwrap()
+            .last()
+            .unwrap()
+            .object
+            .version,
+        deleted,
+        "upsert of a deleted id should record the deleted version"
+    );
+    assert!(collection.get(id).await.unwrap().is_some());
+
+    collection.delete(id).await.unwrap();
+    collection.purge(id).await.unwrap();
+    assert!(
+        collection.history(id).await.unwrap().is_empty(),
+        "purge should remove the object's history"
+    );
+    assert!(
+        matches!(collection.revert(id, deleted).await, Err(Error::NotFound)),
+        "revert of a purged object should fail with NotFound"
+    );
+}
+
+/// Check that each query returns exactly the objects which match it in memory.
+async fn check_queries<C: Collection<Transaction> + Send + Sync>(collection: &mut C) {
+    let groups = [Id::new_random(), Id::new_random()];
+    let [cash, bank, food, salary] = [(); 4].map(|()| Id::new_random());
+    let fixtures = [
+        (
+            groups[0],
+            date!(2024 - 01 - 01),
+            "Groceries",
+            [food, cash],
+            250,
+        ),
+        (
+            groups[0],
+            date!(2024 - 01 - 15),
+            "groceries",
+            [food, bank],
+            4500,
+        ),
+        (
+            groups[0],
+            date!(2024 - 01 - 31),
+            "Salary",
+            [bank, salary],
+            100000,
+        ),
+        (
+            groups[0],
+            date!(2024 - 02 - 01),
+            "Cash: withdrawal",
+            [cash, bank],
+            10000,
+        ),
+        (
+            groups[1],
+            date!(2024 - 02 - 15),
+            "Cash: deposit",
+            [bank, cash],
+            5000,
+        ),
+        (
+            groups[1],
+            date!(2024 - 03 - 01),
+            "Tip 15%",
+            [food, cash],
+            150,
+        ),
+        // "Café" written with a combining accent, and with a precomposed character
+        (
+            groups[1],
+            date!(2024 - 03 - 02),
+            "Cafe\u{301}",
+            [food, cash],
+            350,
+        ),
+        (
+            groups[1],
+            date!(2024 - 03 - 03),
+            "Caf\u{e9} noir",
+            [food, bank],
+            600,
+        ),
+    ];
+    let mut ids = Vec::new();
+    for (group, date, description, accounts, amount) in fixtures {
+        let object = transaction(date, description, accounts, amount);
+        ids.push(create(collection, group, object).await);
+    }
+    let object = split_transaction("Dinner", &[cash, bank, food], 2000);
+    ids.push(create(collection, groups[1], object).await);
+    let objects = collection.get_many(&ids).await.unwrap();
+
+    let other = WithGroupQuery::Other;
+    let description = |query| other(TransactionQuery::Description(query));
+    let queries = vec![
+        vec![WithGroupQuery::Group(vec![groups[0]])],
+        vec![WithGroupQuery::Group(groups.to_vec())],
+        vec![description(StringQuery::eq("Groceries"))],
+        vec![description(StringQuery::eq_ignore_case("GROCERIES"))],
+        vec![description(StringQuery::from(SimpleQuery::in_(vec![
+            "Salary".into(),
+            "Groceries".into(),
+        ])))],
+        vec![description(StringQuery::from(
+            SimpleQuery::default()
+                .and_gt("C".into())
+                .and_le("Salary".into()),
+        ))],
+        vec![description(StringQuery {
+            contains: Some("CASH".into()),
+            ignore_case: true,
+            ..Default::default()
+        })],
+        vec![description(StringQuery::starts_with("Cash:"))],
+        vec![description(StringQuery::like("%15\\%"))],
+        vec![description(StringQuery::like("g_oceries"))],
+        vec![description(StringQuery::regex("^[A-Z][a-z]+$").unwrap())],
+        vec![description(StringQuery::eq("Caf\u{e9}"))],
+        vec![description(StringQuery::eq_ignore_case("CAFE\u{301} NOIR"))],
+        vec![description(StringQuery::starts_with("Cafe\u{301}"))],
+        vec![description(StringQuery::contains("\u{e9} n"))],
+        vec![description(StringQuery::like("caf\u{e9}%"))],
+        vec![other(TransactionQuery::Account(SimpleQuery::eq(food)))],
+        vec![other(TransactionQuery::Account(SimpleQuery::in_(vec![
+            salary, food,
+        ])))],
+        vec![other(TransactionQuery::Date(SimpleQuery::range(
+            date!(2024 - 01 - 15)..date!(2024 - 02 - 15),
+        )))],
+        vec![other(TransactionQuery::Total(
+            SimpleQuery::default()
+                .and_gt(cents(250))
+                .and_ne(cents(10000)),
+        ))],
+        vec![other(TransactionQuery::Total(SimpleQuery::eq(cents(4500))))],
+        vec![other(TransactionQuery::Total(SimpleQuery::range(
+            cents(150)..=cents(5000),
+        )))],
+        vec![other(TransactionQuery::Total(SimpleQuery::in_(vec![
+            cents(150),
+            cents(100000),
+        ])))],
+        vec![other(TransactionQuery::AccountCount(SimpleQuery::eq(3)))],
+        vec![other(TransactionQuery::AccountCount(
+            SimpleQuery::default().and_le(2),
+        ))],
+        vec![other(TransactionQuery::AccountCount(SimpleQuery::in_(
+            vec![1, 3],
+        )))],
+        vec![!other(TransactionQuery::AccountCount(SimpleQuery::eq(2)))],
+        vec![
+            WithGroupQuery::Group(vec![groups[1]]),
+            !other(TransactionQuery::Total(SimpleQuery::range(..cents(1000)))),
+        ],
+        vec![
+            WithGroupQuery::Group(vec![groups[1]]),
+            other(TransactionQuery::Account(SimpleQuery::eq(bank))),
+        ],
+        vec![!other(TransactionQuery::Account(SimpleQuery::eq(cash)))],
+        vec![WithGroupQuery::CreatedAt(SimpleQuery::range(
+            ..=time::OffsetDateTime::now_utc(),
+        ))],
+    ];
+    for query in &queries {
+        let expected = objects
+            .iter()
+            .filter(|object| query.iter().all(|clause| clause.matches(object)))
+            .map(|object| object.object.id)
+            .collect::<BTreeSet<_>>();
+        let found = collection
+            .query(query, QueryOptions::default())
+            .await
+            .expect("query should succeed");
+        assert_eq!(
+            found
+                .iter()
+                .map(|object| object.object.id)
+                .collect::<BTreeSet<_>>(),
+            expected,
+            "query should match in memory for {query:?}"
+        );
+        for object in &found {
+            let stored = objects
+                .iter()
+                .find(|stored| stored.object.id == object.object.id)
+                .expect("query should only return stored objects");
+            assert_eq!(
+                object.object.version, stored.object.version,
+                "query should return the current version"
+            );
+            assert_eq!(object.group, stored.group, "query should return the group");
+        }
+        let actual = collection
+            .query_ids(query, QueryOptions::default())
+            .await
+            .expect("query_ids should succeed")
+            .into_iter()
+            .collect::<BTreeSet<_>>();
+        assert_eq!(
+            actual, expected,
+            "query_ids should match in memory for {query:?}"
+        );
+        let count = collection
+            .query_count(query, QueryOptions::default())
+            .await
+            .expect("query_count should succeed");
+        assert_eq!(
+            count,
+            expected.len(),
+            "query_count should match for {query:?}"
+        );
+    }
+
+    let expr = BooleanExpr::Any(
+        queries[2..5]
+            .iter()
+            .map(|query| BooleanExpr::All(query.iter().cloned().map(BooleanExpr::Query).collect()))
+            .collect(),
+    );
+    let expected = objects
+        .iter()
+        .filter(|object| expr.matches(object))
+        .map(|object| object.object.id)
+        .collect::<BTreeSet<_>>();
+    let actual = collection
+        .query_expr(&expr, QueryOptions::default())
+        .await
+        .expect("query_expr should succeed")
+        .into_iter()
+        .map(|object| object.object.id)
+        .collect::<BTreeSet<_>>();
+    assert_eq!(
+        actual, expected,
+        "query_expr should match in memory for {expr:?}"
+    );
+
+    let mut object = collection
+        .query(&queries[0], QueryOptions::default())
+        .await
+        .unwrap()
+        .swap_remove(0)
+        .object;
+    object.object.description = "Updated".into();
+    collection
+        .update(object)
+        .await
+        .expect("update at the version returned by query should succeed");
+}
+
+async fn check_sorting<C: Collection<Transaction> + Send + Sync>(collection: &mut C) {
+    let group = Id::new_random();
+    for description in ["b", "a", "c", "a"] {
+        create(collection, group, simple_transaction(description)).await;
+    }
+    let query = [WithGroupQuery::Group(vec![group])];
+    let sort = [Sort {
+        field: TransactionSortField::Description,
+        ascending: false,
+    }];
+    let sorted = collection
+        .query_sorted(&query, &sort, QueryOptions::default())
+        .await
+        .unwrap();
+    let descriptions = sorted
+        .iter()
+        .map(|object| object.object.object.description.as_str())
+        .collect::<Vec<_>>();
+    assert_eq!(
+        descriptions,
+        ["c", "b", "a", "a"],
+        "query_sorted should sort by the field"
+    );
+    assert!(
+        sorted[2].object.id < sorted[3].object.id,
+        "objects which compare equal should be ordered by id"
+    );
+}
+
+async fn check_pagination<C: Collection<Transaction> + Send + Sync>(collection: &mut C) {
+    let group = Id::new_random();
+    for description in ["d", "b", "a", "c", "e"] {
+        create(collection, group, simple_transaction(description)).await;
+    }
+    let query = [WithGroupQuery::Group(vec![group])];
+    let sort = [Sort {
+        field: TransactionSortField::Description,
+        ascending: true,
+    }];
+    for (offset, expected) in [
+        (0, &["a", "b"][..]),
+        (2, &["c", "d"]),
+        (4, &["e"]),
+        (5, &[]),
+        (100, &[]),
+    ] {
+        let pagination = Pagination { limit: 2, offset };
+        let page = collection
+            .query_paginated(&query, &sort, pagination, QueryOptions::default())
+            .await
+            .expect("query_paginated should succeed, even past the end of the results");
+        let descriptions = page
+            .iter()
+            .map(|object| object.object.object.description.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            descriptions, expected,
+            "query_paginated should return the page at offset {offset}"
+        );
+    }
+}
+
+/// Check that date ranges include both of their boundaries.
+async fn check_date_range<C: Collection<Transaction> + Send + Sync>(collection: &mut C) {
+    let group = Id::new_random();
+    for date in [
+        date!(2024 - 01 - 31),
+        date!(2024 - 02 - 01),
+        date!(2024 - 02 - 15),
+        date!(2024 - 02 - 29),
+        date!(2024 - 03 - 01),
+    ] {
+        let object = transaction(date, "Rent", [Id::new_random(), Id::new_random()], 100);
+        create(collection, group, object).await;
+    }
+    for (query, expected) in [
+        (SimpleQuery::month_of(date!(2024 - 02 - 10)), 3),
+        (
+            SimpleQuery::date_range(date!(2024 - 01 - 31), date!(2024 - 02 - 01)),
+            2,
+        ),
+        (
+            SimpleQuery::date_range(date!(2024 - 02 - 29), date!(2024 - 02 - 29)),
+            1,
+        ),
+    ] {
+        let query = [
+            WithGroupQuery::Group(vec![group]),
+            WithGroupQuery::Other(TransactionQuery::Date(query)),
+        ];
+        let count = collection
+            .query_count(&query, QueryOptions::default())
+            .await
+            .unwrap();
+        assert_eq!(count, expected, "query_count should match for {query:?}");
+    }
+}
+
+/// Check that watching with each query reports a change to an object matching it, but not an
+/// earlier change to an object which doesn't.
+async fn check_watch<C: Collection<Transaction> + Send + Sync>(collection: &mut C) {
+    let group = Id::new_random();
+    let accounts = [Id::new_random(), Id::new_random()];
+    let mut cases = vec![
+        (
+            vec![WithGroupQuery::Group(vec![group])],
+            (group, simple_transaction("Rent")),
+            (Id::new_random(), simple_transaction("Rent")),
+        ),
+        (
+            vec![WithGroupQuery::Other(TransactionQuery::Description(
+                StringQuery::eq("Rent"),
+            ))],
+            (group, simple_transaction("Rent")),
+            (group, simple_transaction("Groceries")),
+        ),
+    ];
+    let total = |query| vec![WithGroupQuery::Other(TransactionQuery::Total(query))];
+    cases.push((
+        total(SimpleQuery::default().and_gt(cents(5000))),
+        (
+            group,
+            transaction(date!(2024 - 01 - 01), "Rent", accounts, 100000),
+        ),
+        (
+            group,
+            transaction(date!(2024 - 01 - 01), "Rent", accounts, 5000),
+        ),
+    ));
+    cases.push((
+        total(SimpleQuery::in_(vec![cents(250), cents(300)])),
+        (
+            group,
+            transaction(date!(2024 - 01 - 01), "Rent", accounts, 300),
+        ),
+        (
+            group,
+            transaction(date!(2024 - 01 - 01), "Rent", accounts, 200),
+        ),
+    ));
+    cases.push((
+        vec![WithGroupQuery::Other(TransactionQuery::AccountCount(
+            SimpleQuery::default().and_gt(2),
+        ))],
+        (
+            group,
+            split_transaction("Dinner", &[(); 3].map(|()| Id::new_random()), 100),
+        ),
+        (
+            group,
+            split_transaction("Dinner", &[(); 2].map(|()| Id::new_random()), 100),
+        ),
+    ));
+    for (query, matching, other) in cases {
+        check_watch_query(collection, &query, matching, other).await;
+    }
+}
+
+async fn check_watch_query<C: Collection<Transaction> + Send + Sync>(
+    collection: &mut C,
+    query: &[WithGroupQuery<Transaction>],
+    (group, matching): (Id<Group>, Transaction),
+    (other_group, other): (Id<Group>, Transaction),
+) {
+    let object = WithGroup {
+        group,
+        object: matching,
+    };
+    assert!(
+        query
+            .iter()
+            .all(|clause| clause.matches(&versioned(&object))),
+        "{query:?} {:?}",
+        object.object
+    );
+    let mut events = collection.watch(query).await.expect("watch should succeed");
+    create(collection, other_group, other).await;
+    let id = create(collection, object.group, object.object).await;
+    let event = events
+        .next()
+        .await
+        .expect("the stream of changes should not end")
+        .expect("watch should not fail");
+    assert!(
+        matches!(event, ChangeEvent::Created { id: created, .. } if created == id),
+        "watch should report only the creation of the matching object for {query:?}, not \
+         {event:?}"
+    );
+}
+
+/// An object as it would be stored, to match queries against.
+fn versioned(object: &WithGroup<Transaction>) -> WithGroup<Versioned<Transaction>> {
+    let now = time::OffsetDateTime::now_utc();
+    WithGroup {
+        group: object.group,
+        object: Versioned {
+            id: Id::new_random(),
+            version: Version::new_random(),
+            created_at: now,
+            modified_at: now,
+            object: object.object.clone(),
+        },
+    }
+}
+
+/// Check that every query method enforces `max_cost`.
+///
+/// The collection has no indexes, so every query has to examine every object.
+async fn check_max_cost<C: Collection<Transaction> + Send + Sync>(collection: &mut C) {
+    fn too_expensive<T>(result: Result<T>) -> bool {
+        matches!(result, Err(Error::QueryTooExpensive { .. }))
+    }
+
+    let group = Id::new_random();
+    for description in ["Rent", "Groceries", "Coffee"] {
+        create(collection, group, simple_transaction(description)).await;
+    }
+    let query = [WithGroupQuery::Group(vec![group])];
+    let within = QueryOptions {
+        max_cost: Some(3),
+        ..QueryOptions::default()
+    };
+    assert_eq!(
+        collection
+            .query(&query, within)
+            .await
+            .expect("a query within max_cost should succeed")
+            .len(),
+        3
+    );
+
+    let options = QueryOptions {
+        max_cost: Some(2),
+        ..QueryOptions::default()
+    };
+    let sort = [Sort {
+        field: TransactionSortField::Date,
+        ascending: true,
+    }];
+    let pagination = Pagination {
+        limit: 10,
+        offset: 0,
+    };
+    let expr = BooleanExpr::Query(query[0].clone());
+    let group_by = TransactionGroupBy::Account;
+    let field = TransactionTextField::Description;
+    assert!(too_expensive(collection.query(&query, options).await));
+    assert!(too_expensive(collection.query_expr(&expr, options).await));
+    assert!(too_expensive(collection.query_ids(&query, options).await));
+    let stream = collection.query_stream(&query, options);
+    assert!(too_expensive(stream.try_collect::<Vec<_>>().await));
+    assert!(too_expensive(
+        collection.query_sorted(&query, &sort, options).await
+    ));
+    assert!(too_expensive(
+        collection
+            .query_paginated(&query, &sort, pagination, options)
+            .await
+    ));
+    assert!(too_expensive(collection.query_count(&query, options).await));
+    assert!(too_expensive(
+        collection.distinct_values(field, &query, options).await
+    ));
+    assert!(too_expensive(
+        collection.query_count_by(&query, group_by, options).await
+    ));
+    assert!(too_expensive(
+        collection.sum_amounts(&query, group_by, options).await
+    ));
+}
@@ -1,7 +1,19 @@
+use std::cmp::Ordering;
+
 use serde::{Deserialize, Serialize};
 
 use crate::{
-    backend::{id::Id, version::Versioned},
+    backend::{
+        id::Id,
+        patch::{Patch, Patchable},
+        query::{
+            builder::QueryBuilder,
+            schema::{FieldSchema, Operator, QuerySchema, ValueType},
+            NoGroupBy, Query, QueryValidationError, Queryable, SimpleQuery, SortField, StringQuery,
+            TextField,
+        },
+        version::Versioned,
+    },
     map::Map,
 };
 
@@ -11,10 +23,294 @@ pub struct User {
     pub is_superuser: bool,
 }
 
+impl Patchable for User {
+    type Patch = UserPatch;
+}
+
+/// A partial update to a user, changing only the fields which are set.
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+pub struct UserPatch {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub is_superuser: Option<bool>,
+}
+
+impl Patch<User> for UserPatch {
+    fn apply(&self, user: &mut User) {
+        if let Some(name) = &self.name {
+            user.name.clone_from(name);
+        }
+        if let Some(is_superuser) = self.is_superuser {
+            user.is_superuser = is_superuser;
+        }
+    }
+}
+
+impl Queryable for User {
+    type Query = UserQuery;
+    type GroupBy = NoGroupBy;
+    type SortField = UserSortField;
+    type TextField = UserTextField;
+
+    fn schema() -> QuerySchema {
+        QuerySchema {
+            fields: vec![FieldSchema {
+                name: "name",
+                value_type: ValueType::String,
+                operators: Operator::STRING,
+            }],
+        }
+    }
+}
+
+// queries are short-lived, so the size of the name query isn't worth boxing
+#[allow(clippy::large_enum_variant)]
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(tag = "type", content = "value", rename_all = "snake_case")]
+pub enum UserQuery {
+    /// Query on the name of the user.
+    Name(StringQuery),
+    /// The user is a superuser if this is `true`, or is not if it is `false`.
+    Superuser(bool),
+}
+
+impl Query<User> for UserQuery {
+    fn matches(&self, user: &User) -> bool {
+        match self {
+            Self::Name(query) => query.matches(&user.name),
+            Self::Superuser(is_superuser) => user.is_superuser == *is_superuser,
+        }
+    }
+
+    fn validate(&self) -> Result<(), QueryValidationError> {
+        match self {
+            Self::Name(query) => query.validate().map_err(|err| err.within("name")),
+            Self::Superuser(_) => Ok(()),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Deserialize, Serialize)]
+pub enum UserSortField {
+    /// Sort by the name of the user.
+    Name,
+}
+
+impl SortField<User> for UserSortField {
+    fn compare(&self, a: &User, b: &User) -> Ordering {
+        match self {
+            Self::Name => a.name.cmp(&b.name),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Deserialize, Serialize)]
+pub enum UserTextField {
+    /// The name of the user.
+    Name,
+}
+
+impl TextField<User> for UserTextField {
+    fn get<'a>(&self, user: &'a User) -> &'a str {
+        match self {
+            Self::Name => &user.name,
+        }
+    }
+}
+
 #[derive(Clone, Debug, Deserialize, Serialize)]
 pub struct Group {
     pub name: String,
     pub permissions: Permissions,
+    /// The group this group is nested in, whose permissions also apply to this group.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub parent: Option<Id<Group>>,
+}
+
+impl Patchable for Group {
+    type Patch = GroupPatch;
+}
+
+/// A partial update to a group, changing only the fields which are set.
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+pub struct GroupPatch {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub permissions: Option<Permissions>,
+    /// The new parent of the group, which is removed if this is `Some(None)`.
+    #[serde(
+        default,
+        deserialize_with = "crate::serde::double_option::deserialize",
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub parent: Option<Option<Id<Group>>>,
+}
+
+impl Patch<Group> for GroupPatch {
+    fn apply(&self, group: &mut Group) {
+        if let Some(name) = &self.name {
+            group.name.clone_from(name);
+        }
+        if let Some(permissions) = &self.permissions {
+            group.permissions.clone_from(permissions);
+        }
+        if let Some(parent) = self.parent {
+            group.parent = parent;
+        }
+    }
+}
+
+impl Queryable for Group {
+    type Query = GroupQuery;
+    type GroupBy = NoGroupBy;
+    type SortField = GroupSortField;
+    type TextField = GroupTextField;
+
+    fn schema() -> QuerySchema {
+        QuerySchema {
+            fields: vec![
+                FieldSchema {
+                    name: "name",
+                    value_type: ValueType::String,
+                    operators: Operator::STRING,
+                },
+                FieldSchema {
+                    name: "parent",
+                    value_type: ValueType::Id,
+                    operators: &[
+                        Operator::Eq,
+                        Operator::Ne,
+                        Operator::In,
+                        Operator::Nin,
+                        Operator::IsNull,
+                    ],
+                },
+                FieldSchema {
+                    name: "default_access",
+                    value_type: ValueType::Choice {
+                        options: &["None", "Read", "Write"],
+                    },
+                    operators: Operator::ORDERED,
+                },
+            ],
+        }
+    }
+}
+
+// queries are short-lived, so the size of the name query isn't worth boxing
+#[allow(clippy::large_enum_variant)]
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(tag = "type", content = "value", rename_all = "snake_case")]
+pub enum GroupQuery {
+    /// Query on the name of the group.
+    Name(StringQuery),
+    /// At least one of these users has explicit permissions on the group.
+    UserAny(Vec<Id<User>>),
+    /// The user has explicit permissions on the group matching the query.
+    UserPerm(Id<User>, SimpleQuery<AccessLevel>),
+    /// The user has a role in the group matching the query.
+    UserRole(Id<User>, SimpleQuery<Role>),
+    /// Query on the parent of the group.
+    Parent(SimpleQuery<Id<Group>>),
+    /// Query on the access level of users without explicit permissions on the group.
+    DefaultAccess(SimpleQuery<AccessLevel>),
+    /// The user has at least the access level to the group, as determined by
+    /// [`Permissions::get`].
+    ///
+    /// Access inherited from the group's ancestors is not considered.
+    UserCanRead(Id<User>, AccessLevel),
+}
+
+/// A builder for queries on groups.
+pub type GroupQueryBuilder = QueryBuilder<Group>;
+
+impl QueryBuilder<Group> {
+    /// Only match groups whose name matches the query.
+    pub fn name(self, query: StringQuery) -> Self {
+        self.and_other(GroupQuery::Name(query))
+    }
+
+    /// Only match groups which are children of the group.
+    pub fn child_of(self, parent: Id<Group>) -> Self {
+        self.and_other(GroupQuery::Parent(SimpleQuery::eq(parent)))
+    }
+
+    /// Only match groups which the user has at least the access level to, not considering
+    /// access inherited from the group's ancestors.
+    pub fn accessible_by(self, user: Id<User>, level: AccessLevel) -> Self {
+        self.and_other(GroupQuery::UserCanRead(user, level))
+    }
+}
+
+impl Query<Group> for GroupQuery {
+    fn matches(&self, group: &Group) -> bool {
+        match self {
+            Self::Name(query) => query.matches(&group.name),
+            Self::UserAny(users) => users
+                .iter()
+                .any(|user| group.permissions.users.contains_key(user)),
+            Self::UserPerm(user, query) => group
+                .permissions
+                .users
+                .get(user)
+                .is_some_and(|level| query.matches(level)),
+            Self::UserRole(user, query) => group
+                .permissions
+                .roles
+                .get(user)
+                .is_some_and(|role| query.matches(role)),
+            Self::Parent(query) => query.matches(&group.parent),
+            Self::DefaultAccess(query) => query.matches(&group.permissions.default),
+            Self::UserCanRead(user, level) => group.permissions.get(*user) >= *level,
+        }
+    }
+
+    fn validate(&self) -> Result<(), QueryValidationError> {
+        match self {
+            Self::Name(query) => query.validate().map_err(|err| err.within("name")),
+            Self::UserAny(users) if users.is_empty() => Err(QueryValidationError::new(
+                "the list of users is empty, so nothing matches",
+            )
+            .within("user_any")),
+            Self::UserAny(_) | Self::UserCanRead(..) => Ok(()),
+            Self::UserPerm(_, query) => query.validate().map_err(|err| err.within("user_perm")),
+            Self::UserRole(_, query) => query.validate().map_err(|err| err.within("user_role")),
+            Self::Parent(query) => query.validate().map_err(|err| err.within("parent")),
+            Self::DefaultAccess(query) => {
+                query.validate().map_err(|err| err.within("default_access"))
+            }
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Deserialize, Serialize)]
+pub enum GroupSortField {
+    /// Sort by the name of the group.
+    Name,
+}
+
+impl SortField<Group> for GroupSortField {
+    fn compare(&self, a: &Group, b: &Group) -> Ordering {
+        match self {
+            Self::Name => a.name.cmp(&b.name),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Deserialize, Serialize)]
+pub enum GroupTextField {
+    /// The name of the group.
+    Name,
+}
+
+impl TextField<Group> for GroupTextField {
+    fn get<'a>(&self, group: &'a Group) -> &'a str {
+        match self {
+            Self::Name => &group.name,
+        }
+    }
 }
 
 #[derive(Clone, Debug, Deserialize, Serialize)]
@@ -30,6 +326,8 @@ impl<T> WithGroup<Versioned<T>> {
         Versioned {
             id: self.object.id.transmute(),
             version: self.object.version,
+            created_at: self.object.created_at,
+            modified_at: self.object.modified_at,
             object: WithGroup {
                 group: self.group,
                 object: self.object.object,
@@ -45,21 +343,42 @@ impl<T> Versioned<WithGroup<T>> {
             object: Versioned {
                 id: self.id.transmute(),
                 version: self.version,
+                created_at: self.created_at,
+                modified_at: self.modified_at,
                 object: self.object.object,
             },
         }
     }
 }
 
-#[derive(Clone, Debug, Deserialize, Serialize)]
+#[derive(Clone, Debug, Eq, PartialEq, Deserialize, Serialize)]
 pub struct Permissions {
     pub users: Map<Id<User>, AccessLevel>,
+    /// The roles of users in the group, which grant access in addition to `users`.
+    #[serde(default)]
+    pub roles: Map<Id<User>, Role>,
     pub default: AccessLevel,
 }
 
 impl Permissions {
+    /// Get the access level of the user, from their role and explicit access level, or the
+    /// default access level if they have neither.
     pub fn get(&self, id: Id<User>) -> AccessLevel {
-        self.users.get(&id).copied().unwrap_or(self.default)
+        let role = self.roles.get(&id).map(|role| role.access_level());
+        let level = self.users.get(&id).copied();
+        role.max(level).unwrap_or(self.default)
+    }
+
+    /// Returns whether the user has the capability, from their role or explicit access level, or
+    /// from the default access level if they have neither.
+    pub fn allows(&self, id: Id<User>, capability: Capability) -> bool {
+        let role = self.roles.get(&id);
+        let level = self.users.get(&id);
+        if role.is_none() && level.is_none() {
+            return self.default.allows(capability);
+        }
+        role.is_some_and(|role| role.allows(capability))
+            || level.is_some_and(|level| level.allows(capability))
     }
 }
 
@@ -74,7 +393,136 @@ pub enum AccessLevel {
     Write,
 }
 
+impl AccessLevel {
+    /// All of the access levels, from lowest to highest.
+    pub const ALL: [Self; 3] = [Self::None, Self::Read, Self::Write];
+
+    /// Returns whether the access level grants the capability.
+    ///
+    /// Read-write access grants every capability.
+    pub fn allows(self, capability: Capability) -> bool {
+        match self {
+            Self::None => false,
+            Self::Read => capability == Capability::Read,
+            Self::Write => true,
+        }
+    }
+}
+
+/// A role of a user in a group, granting a set of capabilities.
+#[derive(Clone, Copy, Debug, Eq, Ord, PartialEq, PartialOrd, Deserialize, Serialize)]
+pub enum Role {
+    /// Can read from the group
+    Viewer,
+    /// Can also create and edit objects in the group
+    Member,
+    /// Can also delete, restore, and purge objects in the group
+    Admin,
+    /// Can also edit the permissions of groups in the group, and move objects into or out of it
+    Owner,
+}
+
+impl Role {
+    /// All of the roles, from least to most capable.
+    pub const ALL: [Self; 4] = [Self::Viewer, Self::Member, Self::Admin, Self::Owner];
+
+    /// Returns whether the role grants the capability.
+    pub fn allows(self, capability: Capability) -> bool {
+        match capability {
+            Capability::Read => true,
+            Capability::Write => self >= Self::Member,
+            Capability::Delete => self >= Self::Admin,
+            Capability::ManagePermissions | Capability::ChangeGroup => self >= Self::Owner,
+        }
+    }
+
+    /// The access level granted by the role.
+    pub fn access_level(self) -> AccessLevel {
+        if self.allows(Capability::Write) {
+            AccessLevel::Write
+        } else {
+            AccessLevel::Read
+        }
+    }
+}
+
+/// Something a user may be allowed to do in a group.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Capability {
+    /// Read objects in the group
+    Read,
+    /// Create and edit objects in the group
+    Write,
+    /// Delete, restore, and purge objects in the group
+    Delete,
+    /// Edit the permissions and parents of groups in the group
+    ManagePermissions,
+    /// Move objects into or out of the group
+    ChangeGroup,
+}
+
 /// Marker trait indicating that a type can be moved to a different group.
 pub trait ChangeGroup {}
 
 impl ChangeGroup for Group {}
+
+#[cfg(test)]
+mod tests {
+    use super::{AccessLevel, Group, GroupQuery, Permissions, Role, User};
+    use crate::{
+        backend::{id::Id, query::Query},
+        map::Map,
+    };
+
+    fn group(
+        users: &[(Id<User>, AccessLevel)],
+        roles: &[(Id<User>, Role)],
+        default: AccessLevel,
+    ) -> Group {
+        Group {
+            name: "Books".into(),
+            permissions: Permissions {
+                users: Map(users.iter().copied().collect()),
+                roles: Map(roles.iter().copied().collect()),
+                default,
+            },
+            parent: None,
+        }
+    }
+
+    #[test]
+    fn user_can_read() {
+        let [none, read, write] = AccessLevel::ALL;
+        let user = Id::new_random();
+        let other = Id::new_random();
+        // the highest level each group grants the user
+        let cases = [
+            (group(&[], &[], none), none),
+            (group(&[], &[], read), read),
+            (group(&[(other, write)], &[], write), write),
+            (group(&[(user, write)], &[], none), write),
+            // an explicit level replaces the default, even when it is lower
+            (group(&[(user, none)], &[], write), none),
+            (group(&[(user, read)], &[], write), read),
+            // as does a role
+            (group(&[], &[(user, Role::Viewer)], write), read),
+            (group(&[], &[(user, Role::Member)], none), write),
+            // a role and an explicit level both grant access
+            (
+                group(&[(user, write)], &[(user, Role::Viewer)], none),
+                write,
+            ),
+            (group(&[(user, none)], &[(user, Role::Viewer)], write), read),
+        ];
+        for (group, granted) in &cases {
+            for level in AccessLevel::ALL {
+                assert_eq!(
+                    GroupQuery::UserCanRead(user, level).matches(group),
+                    level <= *granted,
+                    "{level:?} for {:?}",
+                    group.permissions
+                );
+            }
+        }
+    }
+}
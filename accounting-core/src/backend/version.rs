@@ -1,5 +1,6 @@
 use rand::distributions::{Distribution, Standard};
 use serde::{Deserialize, Serialize};
+use time::OffsetDateTime;
 
 use crate::backend::id::Id;
 
@@ -9,12 +10,34 @@ pub struct Versioned<T> {
     pub id: Id<T>,
     #[serde(rename = "_version")]
     pub version: Version,
+    /// When the object was created.
+    #[serde(
+        rename = "_created",
+        with = "crate::serde::datetime",
+        default = "unrecorded_time"
+    )]
+    pub created_at: OffsetDateTime,
+    /// When the object was last modified.
+    ///
+    /// This is set by the collection when the object is modified, so any value passed in with an
+    /// update is ignored.
+    #[serde(
+        rename = "_modified",
+        with = "crate::serde::datetime",
+        default = "unrecorded_time"
+    )]
+    pub modified_at: OffsetDateTime,
     #[serde(flatten)]
     pub object: T,
 }
 
+/// The timestamp of objects stored before timestamps were recorded.
+fn unrecorded_time() -> OffsetDateTime {
+    OffsetDateTime::UNIX_EPOCH
+}
+
 /// An opaque identifier for a version of a document, to detect conflicting edits.
-#[derive(Clone, Copy, Debug, Deserialize, Serialize)]
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Deserialize, Serialize)]
 #[serde(transparent)]
 pub struct Version(u64);
 
@@ -1,11 +1,25 @@
 use std::error::Error as StdError;
 
+use crate::backend::{audit::AnyResource, id::Id, query::QueryValidationError};
+
 #[derive(Debug, thiserror::Error)]
 #[non_exhaustive]
 pub enum Error {
     #[error("Transaction includes account from different group")]
     TransactionGroup,
 
+    #[error("Transaction amounts do not sum to zero in each currency")]
+    UnbalancedTransaction,
+
+    #[error("Transaction amount is more precise than the minor unit of its account's currency")]
+    AmountTooPrecise,
+
+    #[error("Transaction amounts are too large to total")]
+    AmountOutOfRange,
+
+    #[error("The resource is still referenced by {} other resources", .0.len())]
+    StillReferenced(Vec<Id<AnyResource>>),
+
     #[error("The requested resource was not found")]
     NotFound,
 
@@ -15,12 +29,26 @@ pub enum Error {
     #[error("A conflicting edit occurred")]
     ConflictingEdit,
 
+    #[error("Conflicting edits occurred on each of {0} attempts")]
+    RetriesExhausted(usize),
+
+    #[error("The query did not complete within its timeout")]
+    Timeout,
+
+    #[error(
+        "The query is estimated to examine {estimated} objects, more than its limit of {limit}"
+    )]
+    QueryTooExpensive { estimated: u64, limit: u64 },
+
+    #[error("Invalid query: {0}")]
+    InvalidQuery(#[from] QueryValidationError),
+
     #[error("Backend error: {0}")]
-    Backend(#[source] Box<dyn StdError + 'static>),
+    Backend(#[source] Box<dyn StdError + Send + Sync + 'static>),
 }
 
 impl Error {
-    pub fn backend<E: StdError + 'static>(error: E) -> Self {
+    pub fn backend<E: StdError + Send + Sync + 'static>(error: E) -> Self {
         Error::Backend(Box::new(error))
     }
 }
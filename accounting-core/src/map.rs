@@ -4,7 +4,7 @@ use derivative::Derivative;
 use serde::{Deserialize, Deserializer, Serialize, Serializer};
 
 /// A wrapper around [`BTreeMap`] that (de)serializes as an array of key-value pairs.
-#[derive(Clone, Debug, Derivative)]
+#[derive(Clone, Debug, Eq, PartialEq, Derivative)]
 #[derivative(Default(bound = ""))]
 pub struct Map<K, V>(pub BTreeMap<K, V>);
 
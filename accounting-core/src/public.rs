@@ -2,6 +2,8 @@ pub use crate::error::Result;
 
 pub mod account;
 pub mod amount;
+#[cfg(feature = "import")]
+pub mod import;
 pub mod transaction;
 
 #[non_exhaustive]
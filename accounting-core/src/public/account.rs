@@ -1,7 +1,245 @@
+use std::cmp::Ordering;
+
 use serde::{Deserialize, Serialize};
+use time::Date;
+
+use crate::backend::patch::{Patch, Patchable};
+use crate::backend::query::{
+    builder::QueryBuilder,
+    schema::{FieldSchema, Operator, QuerySchema, ValueType},
+    NoGroupBy, Query, QueryValidationError, Queryable, SimpleQuery, SortField, StringQuery,
+    TextField,
+};
+use crate::backend::user::ChangeGroup;
+use crate::public::amount::{Amount, Currency};
 
 #[derive(Clone, Debug, Deserialize, Serialize)]
 pub struct Account {
     pub name: String,
     pub description: String,
+    #[serde(default)]
+    pub account_type: AccountType,
+    /// The balance of the account at the start of `opening_date`, as on the last statement
+    /// before the account was tracked here.
+    #[serde(default)]
+    pub opening_balance: Amount,
+    /// The date of the opening balance, or `None` for the date of the account's earliest
+    /// transaction.
+    ///
+    /// Transactions dated before the opening date are already accounted for by the opening
+    /// balance, so they are excluded from the account's balance and ledger.
+    #[serde(default, with = "crate::serde::option_date")]
+    pub opening_date: Option<Date>,
+    /// The currency of the account's amounts, or `None` for the books' main currency.
+    ///
+    /// Transactions must balance separately in each currency, so moving money between
+    /// currencies goes through an account in each currency, such as a pair of trading accounts.
+    #[serde(default)]
+    pub currency: Option<Currency>,
+}
+
+impl Account {
+    /// The opening balance of the account, if it applies to a balance as of `date`.
+    pub fn opening_balance_as_of(&self, date: Date) -> Amount {
+        match self.opening_date {
+            Some(opening_date) if opening_date > date => Amount::ZERO,
+            _ => self.opening_balance,
+        }
+    }
+}
+
+/// The classification of an account, which determines how its balance is reported.
+#[derive(Clone, Copy, Debug, Default, Eq, Ord, PartialEq, PartialOrd, Deserialize, Serialize)]
+pub enum AccountType {
+    /// Resources owned, such as cash or receivables.
+    #[default]
+    Asset,
+    /// Obligations owed, such as loans or payables.
+    Liability,
+    /// The residual interest after liabilities are subtracted from assets.
+    Equity,
+    /// Increases in equity from revenue.
+    Income,
+    /// Decreases in equity from costs.
+    Expense,
+}
+
+impl ChangeGroup for Account {}
+
+impl Patchable for Account {
+    type Patch = AccountPatch;
+}
+
+/// A partial update to an account, changing only the fields which are set.
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+pub struct AccountPatch {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub account_type: Option<AccountType>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub opening_balance: Option<Amount>,
+    /// The new date of the opening balance, which is removed if this is `Some(None)`.
+    #[serde(
+        default,
+        with = "crate::serde::double_option_date",
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub opening_date: Option<Option<Date>>,
+    /// The new currency of the account, which is removed if this is `Some(None)`.
+    #[serde(
+        default,
+        deserialize_with = "crate::serde::double_option::deserialize",
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub currency: Option<Option<Currency>>,
+}
+
+impl Patch<Account> for AccountPatch {
+    fn apply(&self, account: &mut Account) {
+        if let Some(name) = &self.name {
+            account.name.clone_from(name);
+        }
+        if let Some(description) = &self.description {
+            account.description.clone_from(description);
+        }
+        if let Some(account_type) = self.account_type {
+            account.account_type = account_type;
+        }
+        if let Some(opening_balance) = self.opening_balance {
+            account.opening_balance = opening_balance;
+        }
+        if let Some(opening_date) = self.opening_date {
+            account.opening_date = opening_date;
+        }
+        if let Some(currency) = self.currency {
+            account.currency = currency;
+        }
+    }
+}
+
+impl Queryable for Account {
+    type Query = AccountQuery;
+    type GroupBy = NoGroupBy;
+    type SortField = AccountSortField;
+    type TextField = AccountTextField;
+
+    fn schema() -> QuerySchema {
+        QuerySchema {
+            fields: vec![
+                FieldSchema {
+                    name: "name",
+                    value_type: ValueType::String,
+                    operators: Operator::STRING,
+                },
+                FieldSchema {
+                    name: "description",
+                    value_type: ValueType::String,
+                    operators: Operator::STRING,
+                },
+                FieldSchema {
+                    name: "account_type",
+                    value_type: ValueType::Choice {
+                        options: &["Asset", "Liability", "Equity", "Income", "Expense"],
+                    },
+                    operators: Operator::EQUALITY,
+                },
+            ],
+        }
+    }
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(tag = "type", content = "value", rename_all = "snake_case")]
+pub enum AccountQuery {
+    /// Query on the name of the account.
+    Name(StringQuery),
+    /// The name of the account is equal to this, ignoring case, as with
+    /// [`StringQuery::eq_ignore_case`].
+    NameExact(String),
+    /// Query on the description of the account.
+    Description(StringQuery),
+    /// Query on the type of the account.
+    AccountType(SimpleQuery<AccountType>),
+}
+
+/// A builder for queries on accounts.
+pub type AccountQueryBuilder = QueryBuilder<Account>;
+
+impl QueryBuilder<Account> {
+    /// Only match accounts whose name matches the query.
+    pub fn name(self, query: StringQuery) -> Self {
+        self.and_other(AccountQuery::Name(query))
+    }
+
+    /// Only match accounts whose name contains `text`.
+    pub fn name_contains(self, text: impl Into<String>) -> Self {
+        self.name(StringQuery::contains(text))
+    }
+
+    /// Only match accounts whose description contains `text`.
+    pub fn description_contains(self, text: impl Into<String>) -> Self {
+        self.and_other(AccountQuery::Description(StringQuery::contains(text)))
+    }
+
+    /// Only match accounts of one of the types.
+    pub fn account_type(self, types: Vec<AccountType>) -> Self {
+        self.and_other(AccountQuery::AccountType(SimpleQuery::in_(types)))
+    }
+}
+
+impl Query<Account> for AccountQuery {
+    fn matches(&self, account: &Account) -> bool {
+        match self {
+            Self::Name(query) => query.matches(&account.name),
+            Self::NameExact(name) => StringQuery::eq_ignore_case(name).matches(&account.name),
+            Self::Description(query) => query.matches(&account.description),
+            Self::AccountType(query) => query.matches(&account.account_type),
+        }
+    }
+
+    fn validate(&self) -> Result<(), QueryValidationError> {
+        match self {
+            Self::Name(query) => query.validate().map_err(|err| err.within("name")),
+            Self::NameExact(_) => Ok(()),
+            Self::Description(query) => query.validate().map_err(|err| err.within("description")),
+            Self::AccountType(query) => query.validate().map_err(|err| err.within("account_type")),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Deserialize, Serialize)]
+pub enum AccountSortField {
+    /// Sort by the name of the account.
+    Name,
+    /// Sort by the description of the account.
+    Description,
+}
+
+impl SortField<Account> for AccountSortField {
+    fn compare(&self, a: &Account, b: &Account) -> Ordering {
+        match self {
+            Self::Name => a.name.cmp(&b.name),
+            Self::Description => a.description.cmp(&b.description),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Deserialize, Serialize)]
+pub enum AccountTextField {
+    /// The name of the account.
+    Name,
+    /// The description of the account.
+    Description,
+}
+
+impl TextField<Account> for AccountTextField {
+    fn get<'a>(&self, account: &'a Account) -> &'a str {
+        match self {
+            Self::Name => &account.name,
+            Self::Description => &account.description,
+        }
+    }
 }
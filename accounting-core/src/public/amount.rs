@@ -1,90 +1,840 @@
-use std::{fmt, ops};
+use std::{fmt, iter, ops, str::FromStr};
 
-use rust_decimal::Decimal;
+use rust_decimal::{prelude::ToPrimitive, Decimal};
 use serde::{Deserialize, Serialize};
 
-#[derive(Clone, Copy, Eq, PartialEq, Ord, PartialOrd, Serialize, Deserialize)]
+#[derive(Clone, Copy, Default, Eq, PartialEq, Ord, PartialOrd, Serialize, Deserialize)]
+#[serde(try_from = "DecimalAmount", into = "DecimalAmount")]
 pub struct Amount {
-    /// Credits are negative, debits are positive.
-    #[serde(with = "rust_decimal::serde::str")]
-    value: Decimal,
+    /// The number of units, each `10^-SCALE` of a major unit. Credits are negative, debits are
+    /// positive.
+    units: i64,
 }
 
 impl Amount {
+    /// The number of decimal places in an amount, i.e. the number of units in a major unit is
+    /// `10.pow(SCALE)`.
+    ///
+    /// This is the most decimal places any ISO 4217 currency uses for its minor unit, so amounts
+    /// in any currency are exact, whether it has no minor unit (such as JPY), or thousandths
+    /// (such as BHD).
+    pub const SCALE: u32 = 4;
+
+    /// The number of decimal places amounts are displayed and allocated with, unless they are
+    /// more precise.
+    const MIN_PLACES: u32 = 2;
+
+    /// The zero amount
+    pub const ZERO: Self = Self { units: 0 };
+
+    /// Construct an amount from a number of units, each `10^-SCALE` of a major unit
+    pub const fn from_units(units: i64) -> Self {
+        Self { units }
+    }
+
+    /// The number of units, each `10^-SCALE` of a major unit, in the amount
+    pub const fn to_units(self) -> i64 {
+        self.units
+    }
+
+    /// Construct an amount from a number of minor units with `exponent` decimal places, such as
+    /// cents with an exponent of 2.
+    ///
+    /// Fails if the amount is out of range, or `exponent` is more than [`SCALE`](Self::SCALE).
+    pub fn from_minor_units(minor_units: i64, exponent: u32) -> Result<Self, AmountError> {
+        if exponent > Self::SCALE {
+            return Err(AmountError::TooPrecise);
+        }
+        minor_units
+            .checked_mul(10_i64.pow(Self::SCALE - exponent))
+            .map(Self::from_units)
+            .ok_or(AmountError::OutOfRange)
+    }
+
+    /// The number of minor units with `exponent` decimal places in the amount, rounded to the
+    /// nearest minor unit, with ties rounding to even.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `exponent` is more than [`SCALE`](Self::SCALE).
+    pub fn to_minor_units(self, exponent: u32) -> i64 {
+        assert!(
+            exponent <= Self::SCALE,
+            "minor units can't be more precise than an amount"
+        );
+        let rounded = self.to_decimal().round_dp(exponent);
+        (rounded * Decimal::from(10_i64.pow(exponent)))
+            .to_i64()
+            .expect("minor units of an amount are in range")
+    }
+
+    /// Add two amounts, returning `None` if the sum is out of range.
+    pub const fn checked_add(self, other: Self) -> Option<Self> {
+        match self.units.checked_add(other.units) {
+            Some(units) => Some(Self { units }),
+            None => None,
+        }
+    }
+
+    /// Subtract two amounts, returning `None` if the difference is out of range.
+    pub const fn checked_sub(self, other: Self) -> Option<Self> {
+        match self.units.checked_sub(other.units) {
+            Some(units) => Some(Self { units }),
+            None => None,
+        }
+    }
+
+    /// Sum the amounts, returning `None` if the sum, or any partial sum, is out of range.
+    pub fn checked_sum(amounts: impl IntoIterator<Item = Self>) -> Option<Self> {
+        amounts
+            .into_iter()
+            .try_fold(Self::ZERO, |total, amount| total.checked_add(amount))
+    }
+
+    /// Returns whether the amounts sum to exactly zero.
+    ///
+    /// The sum is exact, so amounts whose partial sums are out of range are still checked
+    /// correctly.
+    pub fn sum_is_zero(amounts: impl IntoIterator<Item = Self>) -> bool {
+        // each amount fits in an `i64`, so no realistic number of them can overflow an `i128`
+        amounts
+            .into_iter()
+            .map(|amount| i128::from(amount.units))
+            .sum::<i128>()
+            == 0
+    }
+
     /// Returns whether the amount is a debit amount
     pub fn is_debit(self) -> bool {
-        self.value > Decimal::ZERO
+        self.units > 0
     }
 
     /// Returns whether the amount is a credit amount
     pub fn is_credit(self) -> bool {
-        self.value < Decimal::ZERO
+        self.units < 0
     }
 
     /// Returns whether the amount is zero
     pub const fn is_zero(self) -> bool {
-        self.value.is_zero()
+        self.units == 0
     }
 
     pub fn abs(&self) -> Decimal {
-        self.value.abs()
+        self.to_decimal().abs()
+    }
+
+    /// The number of decimal places needed to represent the amount exactly.
+    pub fn places(self) -> u32 {
+        self.to_decimal().normalize().scale()
+    }
+
+    /// Round the amount to `places` decimal places, with ties rounding to even.
+    ///
+    /// Amounts already have [`SCALE`](Self::SCALE) decimal places, so rounding to that many or
+    /// more leaves the amount unchanged.
+    pub fn round_to(self, places: u32) -> Self {
+        Self::from_decimal_rounded(self.to_decimal().round_dp(places))
+    }
+
+    /// Divide the amount into parts in proportion to `weights`, which always sum back to the
+    /// amount.
+    ///
+    /// The parts are whole hundredths, or as precise as the amount if it has more decimal places.
+    /// Each part is first rounded towards zero, and the hundredths left over are then given out
+    /// one at a time to the parts which lost the most to rounding (the largest remainder
+    /// method), with ties going to the earlier part.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `weights` is empty, if any weight is negative, or if they sum to zero or to more
+    /// than [`Decimal::MAX`].
+    pub fn allocate(self, weights: &[Decimal]) -> Vec<Self> {
+        self.allocate_in_places(weights, Self::MIN_PLACES)
+    }
+
+    /// Divide the amount into parts in proportion to `weights`, as
+    /// [`allocate`](Self::allocate), with parts of at least `places` decimal places.
+    pub(crate) fn allocate_in_places(self, weights: &[Decimal], places: u32) -> Vec<Self> {
+        assert!(
+            weights.iter().all(|weight| !weight.is_sign_negative()),
+            "allocation weights must not be negative"
+        );
+        let total_weight = weights
+            .iter()
+            .try_fold(Decimal::ZERO, |total, weight| total.checked_add(*weight))
+            .expect("allocation weights must not overflow");
+        assert!(
+            !total_weight.is_zero(),
+            "allocation weights must not sum to zero"
+        );
+        let places = places.max(self.places()).min(Self::SCALE);
+        // the amount is a whole number of steps, since it has no more than `places` places
+        let step = 10_u64.pow(Self::SCALE - places);
+        let steps = self.units.unsigned_abs() / step;
+        let (mut parts, remainders): (Vec<u64>, Vec<Decimal>) = weights
+            .iter()
+            .map(|weight| {
+                // dividing first keeps the product no larger than `steps`, so it can't overflow
+                let exact = Decimal::from(steps) * (weight / total_weight);
+                let part = exact.trunc();
+                let part_steps = part.to_u64().expect("part of an amount is in range");
+                (part_steps, exact - part)
+            })
+            .unzip();
+        let mut order = (0..parts.len()).collect::<Vec<_>>();
+        // stable, so ties keep the earlier part first
+        order.sort_by(|&a, &b| remainders[b].cmp(&remainders[a]));
+        // the exact parts are only as precise as `Decimal`, so they can overshoot by a little, and
+        // the leftover can be negative
+        let mut leftover = i128::from(steps) - parts.iter().copied().map(i128::from).sum::<i128>();
+        while leftover > 0 {
+            for &index in order.iter().take(leftover as usize) {
+                parts[index] += 1;
+                leftover -= 1;
+            }
+        }
+        while leftover < 0 {
+            for &index in order.iter().rev() {
+                if leftover < 0 && parts[index] > 0 {
+                    parts[index] -= 1;
+                    leftover += 1;
+                }
+            }
+        }
+        let sign = i128::from(self.units.signum());
+        parts
+            .into_iter()
+            .map(|part| {
+                let units = sign * i128::from(part) * i128::from(step);
+                Self::from_units(units.try_into().expect("part is within the amount"))
+            })
+            .collect()
+    }
+
+    fn to_decimal(self) -> Decimal {
+        Decimal::new(self.units, Self::SCALE)
+    }
+
+    /// Round a decimal to the nearest unit, with ties rounding to even.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the result is out of range, like integer arithmetic does.
+    fn from_decimal_rounded(value: Decimal) -> Self {
+        value
+            .round_dp(Self::SCALE)
+            .try_into()
+            .expect("amount out of range")
+    }
+}
+
+/// An error converting a decimal into an `Amount`.
+#[derive(Debug, thiserror::Error)]
+pub enum AmountError {
+    #[error("Amount has more than {} decimal places", Amount::SCALE)]
+    TooPrecise,
+    #[error("Amount is out of range")]
+    OutOfRange,
+}
+
+impl TryFrom<Decimal> for Amount {
+    type Error = AmountError;
+
+    fn try_from(value: Decimal) -> Result<Self, Self::Error> {
+        if value.normalize().scale() > Self::SCALE {
+            return Err(AmountError::TooPrecise);
+        }
+        value
+            .checked_mul(Decimal::from(10_i64.pow(Self::SCALE)))
+            .and_then(|units| units.to_i64())
+            .map(Self::from_units)
+            .ok_or(AmountError::OutOfRange)
+    }
+}
+
+impl From<Amount> for Decimal {
+    fn from(amount: Amount) -> Self {
+        amount.to_decimal()
+    }
+}
+
+/// The serialized form of an `Amount`, as a decimal string.
+#[derive(Serialize, Deserialize)]
+struct DecimalAmount {
+    #[serde(with = "rust_decimal::serde::str")]
+    value: Decimal,
+}
+
+/// Stored amounts with more than [`SCALE`](Amount::SCALE) decimal places are rounded to the
+/// nearest unit, with ties rounding to even, rather than rejected, so that data written with
+/// more precision can still be read.
+impl TryFrom<DecimalAmount> for Amount {
+    type Error = AmountError;
+
+    fn try_from(amount: DecimalAmount) -> Result<Self, Self::Error> {
+        amount.value.round_dp(Self::SCALE).try_into()
+    }
+}
+
+impl From<Amount> for DecimalAmount {
+    fn from(amount: Amount) -> Self {
+        let mut value = amount.to_decimal();
+        value.rescale(amount.places().max(Amount::MIN_PLACES));
+        Self { value }
     }
 }
 
 impl fmt::Debug for Amount {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        fmt::Debug::fmt(&self.value, f)
+        fmt::Debug::fmt(&self.to_decimal().normalize(), f)
+    }
+}
+
+/// Formats the amount with two decimal places, or more if the amount is more precise, and a
+/// leading minus sign for credits, such as `-1234.50`.
+///
+/// With the alternate flag (`{:#}`), the amount is formatted as in an accounting report instead,
+/// with thousands separators and credits in parentheses, such as `(1,234.50)`.
+impl fmt::Display for Amount {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        self.fmt_with_currency(f, "")
+    }
+}
+
+impl Amount {
+    /// Format the amount as its [`Display`](fmt::Display) impl does, with a currency symbol
+    /// before the digits, such as `-$1234.50` or `($1,234.50)`.
+    ///
+    /// The width, fill, and alignment of the formatter apply to the whole string, including the
+    /// sign and symbol.
+    pub fn fmt_with_currency(&self, f: &mut fmt::Formatter, symbol: &str) -> fmt::Result {
+        let places = self.places().max(Self::MIN_PLACES);
+        f.pad(&self.to_string_in_places(symbol, places, f.alternate()))
+    }
+
+    /// Format the amount with exactly `places` decimal places, which must be at least as many as
+    /// the amount has.
+    fn to_string_in_places(self, symbol: &str, places: u32, alternate: bool) -> String {
+        let units_per_major = 10_u64.pow(Self::SCALE);
+        let units = self.units.unsigned_abs();
+        let major = (units / units_per_major).to_string();
+        let minor = (units % units_per_major) / 10_u64.pow(Self::SCALE - places);
+        let major = if alternate {
+            group_thousands(&major)
+        } else {
+            major
+        };
+        let digits = if places == 0 {
+            format!("{symbol}{major}")
+        } else {
+            format!("{symbol}{major}.{minor:0places$}", places = places as usize)
+        };
+        match (self.is_credit(), alternate) {
+            (false, _) => digits,
+            (true, false) => format!("-{digits}"),
+            (true, true) => format!("({digits})"),
+        }
     }
 }
 
+/// Insert commas between each group of three digits, from the right.
+fn group_thousands(digits: &str) -> String {
+    let mut grouped = String::with_capacity(digits.len() + digits.len() / 3);
+    for (i, digit) in digits.chars().enumerate() {
+        if i > 0 && (digits.len() - i).is_multiple_of(3) {
+            grouped.push(',');
+        }
+        grouped.push(digit);
+    }
+    grouped
+}
+
+/// Panics if the sum is out of range, even in release builds, since a wrapped amount would be
+/// silently wrong. Use [`Amount::checked_add`] for amounts which may be out of range.
 impl ops::Add for Amount {
     type Output = Self;
     fn add(self, other: Self) -> Self {
-        Self {
-            value: self.value + other.value,
-        }
+        self.checked_add(other).expect("amount out of range")
     }
 }
 
+/// Panics if the difference is out of range, even in release builds. Use
+/// [`Amount::checked_sub`] for amounts which may be out of range.
 impl ops::Sub for Amount {
     type Output = Self;
     fn sub(self, other: Self) -> Self {
-        Self {
-            value: self.value - other.value,
-        }
+        self.checked_sub(other).expect("amount out of range")
+    }
+}
+
+impl iter::Sum for Amount {
+    fn sum<I: Iterator<Item = Self>>(iter: I) -> Self {
+        iter.fold(Self::ZERO, ops::Add::add)
+    }
+}
+
+impl<'a> iter::Sum<&'a Amount> for Amount {
+    fn sum<I: Iterator<Item = &'a Amount>>(iter: I) -> Self {
+        iter.copied().sum()
     }
 }
 
 impl ops::Mul<Decimal> for Amount {
     type Output = Self;
     fn mul(self, other: Decimal) -> Self {
-        Self {
-            value: self.value * other,
-        }
+        Self::from_decimal_rounded(self.to_decimal() * other)
     }
 }
 
 impl ops::Mul<Amount> for Decimal {
     type Output = Amount;
     fn mul(self, other: Amount) -> Amount {
-        Amount {
-            value: self * other.value,
-        }
+        other * self
     }
 }
 
 impl ops::Div<Decimal> for Amount {
     type Output = Self;
     fn div(self, other: Decimal) -> Self {
+        Self::from_decimal_rounded(self.to_decimal() / other)
+    }
+}
+
+impl ops::Neg for Amount {
+    type Output = Self;
+    fn neg(self) -> Self {
         Self {
-            value: self.value / other,
+            units: self.units.checked_neg().expect("amount out of range"),
         }
     }
 }
 
-impl ops::Neg for Amount {
+/// A currency, identified by its three-letter ISO 4217 code, such as `USD`.
+#[derive(Clone, Copy, Eq, PartialEq, Ord, PartialOrd, Hash, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct Currency([u8; 3]);
+
+impl Currency {
+    /// The three-letter code of the currency.
+    pub fn code(&self) -> &str {
+        // the code is always ASCII, as checked when it is parsed
+        std::str::from_utf8(&self.0).expect("currency codes are ASCII")
+    }
+
+    /// The number of decimal places of the currency's minor unit, as listed in ISO 4217, such as
+    /// 2 for USD (cents), 0 for JPY, and 3 for BHD.
+    ///
+    /// Codes which ISO 4217 doesn't list, or lists without a minor unit (such as gold, `XAU`),
+    /// are treated as having 2.
+    pub fn exponent(&self) -> u32 {
+        match &self.0 {
+            b"BIF" | b"CLP" | b"DJF" | b"GNF" | b"ISK" | b"JPY" | b"KMF" | b"KRW" | b"PYG"
+            | b"RWF" | b"UGX" | b"UYI" | b"VND" | b"VUV" | b"XAF" | b"XOF" | b"XPF" => 0,
+            b"BHD" | b"IQD" | b"JOD" | b"KWD" | b"LYD" | b"OMR" | b"TND" => 3,
+            b"CLF" | b"UYW" => 4,
+            _ => 2,
+        }
+    }
+}
+
+/// An error parsing a currency code.
+#[derive(Debug, thiserror::Error)]
+#[error("Currency code {0:?} is not three uppercase letters")]
+pub struct CurrencyError(String);
+
+impl FromStr for Currency {
+    type Err = CurrencyError;
+
+    fn from_str(code: &str) -> Result<Self, Self::Err> {
+        match code.as_bytes() {
+            &[a, b, c] if [a, b, c].iter().all(u8::is_ascii_uppercase) => Ok(Self([a, b, c])),
+            _ => Err(CurrencyError(code.into())),
+        }
+    }
+}
+
+impl TryFrom<String> for Currency {
+    type Error = CurrencyError;
+
+    fn try_from(code: String) -> Result<Self, Self::Error> {
+        code.parse()
+    }
+}
+
+impl From<Currency> for String {
+    fn from(currency: Currency) -> Self {
+        currency.code().into()
+    }
+}
+
+impl fmt::Debug for Currency {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.code())
+    }
+}
+
+impl fmt::Display for Currency {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.code())
+    }
+}
+
+/// An amount in a particular currency.
+///
+/// Adding or subtracting amounts in different currencies is meaningless, so the arithmetic
+/// operators panic if the currencies differ. Use [`checked_add`](Self::checked_add) and
+/// [`checked_sub`](Self::checked_sub) to handle that case instead.
+///
+/// Conversions, allocation, and formatting use the minor unit of the currency, given by
+/// [`Currency::exponent`].
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Serialize, Deserialize)]
+pub struct Money {
+    pub amount: Amount,
+    pub currency: Currency,
+}
+
+/// The error when combining amounts in different currencies.
+#[derive(Debug, thiserror::Error)]
+#[error("Cannot combine amounts in {0} and {1}")]
+pub struct CurrencyMismatch(pub Currency, pub Currency);
+
+impl Money {
+    pub const fn new(amount: Amount, currency: Currency) -> Self {
+        Self { amount, currency }
+    }
+
+    /// Add two amounts, failing if they are in different currencies.
+    pub fn checked_add(self, other: Self) -> Result<Self, CurrencyMismatch> {
+        if self.currency != other.currency {
+            return Err(CurrencyMismatch(self.currency, other.currency));
+        }
+        Ok(Self::new(self.amount + other.amount, self.currency))
+    }
+
+    /// Subtract two amounts, failing if they are in different currencies.
+    pub fn checked_sub(self, other: Self) -> Result<Self, CurrencyMismatch> {
+        self.checked_add(-other)
+    }
+
+    /// Convert a decimal into an amount in `currency`, failing if it is more precise than the
+    /// currency's minor unit.
+    pub fn from_decimal(value: Decimal, currency: Currency) -> Result<Self, AmountError> {
+        if value.normalize().scale() > currency.exponent() {
+            return Err(AmountError::TooPrecise);
+        }
+        Ok(Self::new(value.try_into()?, currency))
+    }
+
+    /// Construct an amount from a number of the currency's minor units, such as cents for USD or
+    /// yen for JPY.
+    pub fn from_minor_units(minor_units: i64, currency: Currency) -> Result<Self, AmountError> {
+        Amount::from_minor_units(minor_units, currency.exponent())
+            .map(|amount| Self::new(amount, currency))
+    }
+
+    /// The number of the currency's minor units in the amount, rounded to the nearest minor unit,
+    /// with ties rounding to even.
+    pub fn to_minor_units(self) -> i64 {
+        self.amount.to_minor_units(self.currency.exponent())
+    }
+
+    /// Round the amount to the nearest minor unit of its currency, with ties rounding to even.
+    pub fn round(self) -> Self {
+        Self::new(
+            self.amount.round_to(self.currency.exponent()),
+            self.currency,
+        )
+    }
+
+    /// Divide the amount into parts in proportion to `weights`, which always sum back to the
+    /// amount, as [`Amount::allocate`] does, except that the parts are whole minor units of the
+    /// currency.
+    ///
+    /// # Panics
+    ///
+    /// Panics under the same conditions as [`Amount::allocate`].
+    pub fn allocate(self, weights: &[Decimal]) -> Vec<Self> {
+        self.amount
+            .allocate_in_places(weights, self.currency.exponent())
+            .into_iter()
+            .map(|amount| Self::new(amount, self.currency))
+            .collect()
+    }
+}
+
+/// Formats the amount with the number of decimal places of the currency's minor unit, followed by
+/// the currency code, such as `-1234.50 USD`, `1000 JPY`, or `1.234 BHD`.
+///
+/// An amount more precise than the minor unit is shown in full rather than rounded. The alternate
+/// flag (`{:#}`) and the width, fill, and alignment of the formatter apply as for
+/// [`Amount`]'s [`Display`](fmt::Display) impl.
+impl fmt::Display for Money {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let places = self.currency.exponent().max(self.amount.places());
+        let amount = self.amount.to_string_in_places("", places, f.alternate());
+        f.pad(&format!("{amount} {}", self.currency))
+    }
+}
+
+impl ops::Add for Money {
+    type Output = Self;
+    fn add(self, other: Self) -> Self {
+        match self.checked_add(other) {
+            Ok(sum) => sum,
+            Err(err) => panic!("{err}"),
+        }
+    }
+}
+
+impl ops::Sub for Money {
+    type Output = Self;
+    fn sub(self, other: Self) -> Self {
+        match self.checked_sub(other) {
+            Ok(difference) => difference,
+            Err(err) => panic!("{err}"),
+        }
+    }
+}
+
+impl ops::Neg for Money {
     type Output = Self;
     fn neg(self) -> Self {
-        Self { value: -self.value }
+        Self::new(-self.amount, self.currency)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rust_decimal::Decimal;
+
+    use super::{Amount, Currency, Money};
+
+    fn amount(value: &str) -> Amount {
+        value.parse::<Decimal>().unwrap().try_into().unwrap()
+    }
+
+    fn assert_allocates(amount: Amount, weights: &[Decimal]) {
+        let parts = amount.allocate(weights);
+        assert_eq!(parts.len(), weights.len());
+        let total = parts
+            .iter()
+            .map(|part| i128::from(part.to_units()))
+            .sum::<i128>();
+        assert_eq!(
+            total,
+            i128::from(amount.to_units()),
+            "{amount:?} {weights:?}"
+        );
+        for part in parts {
+            assert!(part.is_zero() || part.is_credit() == amount.is_credit());
+            assert!(part.places() <= amount.places().max(2));
+        }
+    }
+
+    #[test]
+    fn allocate_sums_to_amount() {
+        let weight_sets = [
+            vec![Decimal::ONE],
+            vec![Decimal::ONE; 3],
+            vec![Decimal::ONE, Decimal::TWO, Decimal::from(7)],
+            vec![Decimal::new(1, 3), Decimal::new(333, 2), Decimal::ZERO],
+            vec![Decimal::MAX / Decimal::from(4); 3],
+            vec![Decimal::new(1, 28), Decimal::ONE],
+        ];
+        let amounts = (-1000..=1000)
+            .map(|cents| cents * 100)
+            .chain(-1000..=1000)
+            .chain([i64::MAX, i64::MIN, i64::MAX - 1, i64::MIN + 1, 1 << 62])
+            .map(Amount::from_units);
+        for amount in amounts {
+            for weights in &weight_sets {
+                assert_allocates(amount, weights);
+            }
+        }
+    }
+
+    #[test]
+    fn allocate_largest_remainder() {
+        let parts = amount("1.00").allocate(&[Decimal::ONE; 3]);
+        assert_eq!(parts, ["0.34", "0.33", "0.33"].map(amount));
+        let parts = amount("-1.00").allocate(&[Decimal::ONE; 3]);
+        assert_eq!(parts, ["-0.34", "-0.33", "-0.33"].map(amount));
+        let parts = amount("0.05").allocate(&[Decimal::ONE, Decimal::TWO]);
+        assert_eq!(parts, ["0.02", "0.03"].map(amount));
+        let parts = amount("0.001").allocate(&[Decimal::ONE, Decimal::ONE]);
+        assert_eq!(parts, ["0.001", "0"].map(amount));
+    }
+
+    #[test]
+    #[should_panic = "must not be negative"]
+    fn allocate_negative_weight() {
+        amount("1.00").allocate(&[Decimal::ONE, Decimal::NEGATIVE_ONE]);
+    }
+
+    #[test]
+    fn display() {
+        let credit = amount("-1234.50");
+        let debit = amount("1234.5");
+        assert_eq!(credit.to_string(), "-1234.50");
+        assert_eq!(format!("{credit:#}"), "(1,234.50)");
+        assert_eq!(format!("{debit:#}"), "1,234.50");
+        assert_eq!(amount("-0.05").to_string(), "-0.05");
+        assert_eq!(amount("12").to_string(), "12.00");
+        assert_eq!(amount("1.234").to_string(), "1.234");
+        assert_eq!(amount("-1000.0001").to_string(), "-1000.0001");
+        assert_eq!(format!("{credit:>12}"), "    -1234.50");
+        assert_eq!(format!("{credit:<12}|"), "-1234.50    |");
+        assert_eq!(format!("{credit:*^#14}"), "**(1,234.50)**");
+        assert_eq!(format!("{debit:>#10}"), "  1,234.50");
+    }
+
+    #[test]
+    fn display_with_currency() {
+        struct Dollars(Amount);
+
+        impl std::fmt::Display for Dollars {
+            fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+                self.0.fmt_with_currency(f, "$")
+            }
+        }
+
+        let credit = Dollars(amount("-1234.50"));
+        assert_eq!(credit.to_string(), "-$1234.50");
+        assert_eq!(format!("{credit:#}"), "($1,234.50)");
+        assert_eq!(format!("{credit:>12}"), "   -$1234.50");
+        assert_eq!(format!("{credit:>#14}"), "   ($1,234.50)");
+    }
+
+    #[test]
+    fn currency_exponent() {
+        let exponent = |code: &str| code.parse::<Currency>().unwrap().exponent();
+        assert_eq!(exponent("USD"), 2);
+        assert_eq!(exponent("EUR"), 2);
+        assert_eq!(exponent("JPY"), 0);
+        assert_eq!(exponent("KRW"), 0);
+        assert_eq!(exponent("BHD"), 3);
+        assert_eq!(exponent("KWD"), 3);
+        assert_eq!(exponent("CLF"), 4);
+        assert!(exponent("CLF") <= Amount::SCALE);
+    }
+
+    #[test]
+    fn minor_units() {
+        assert_eq!(Amount::from_minor_units(1234, 2).unwrap(), amount("12.34"));
+        assert_eq!(Amount::from_minor_units(-5, 0).unwrap(), amount("-5"));
+        assert_eq!(Amount::from_minor_units(7, 4).unwrap(), amount("0.0007"));
+        assert!(Amount::from_minor_units(i64::MAX, 2).is_err());
+        assert!(Amount::from_minor_units(1, 5).is_err());
+        assert_eq!(amount("12.34").to_minor_units(2), 1234);
+        assert_eq!(amount("-12.345").to_minor_units(2), -1234);
+        assert_eq!(amount("12.355").to_minor_units(2), 1236);
+        assert_eq!(amount("2.5").to_minor_units(0), 2);
+        assert_eq!(Amount::from_units(i64::MIN).to_minor_units(4), i64::MIN);
+    }
+
+    #[test]
+    fn checked_arithmetic() {
+        let max = Amount::from_units(i64::MAX);
+        let one = Amount::from_units(1);
+        assert_eq!(max.checked_add(one), None);
+        assert_eq!(
+            max.checked_add(-one),
+            Some(Amount::from_units(i64::MAX - 1))
+        );
+        assert_eq!((-max).checked_sub(one), Some(Amount::from_units(i64::MIN)));
+        assert_eq!((-max).checked_sub(one + one), None);
+        assert_eq!(Amount::checked_sum([max, -max, max]), Some(max));
+        assert_eq!(Amount::checked_sum([max, max, -max]), None);
+        assert!(Amount::sum_is_zero([max, max, -max, -max]));
+        // wraps to zero in 64 bits
+        assert!(!Amount::sum_is_zero([max, max, one + one]));
+    }
+
+    #[test]
+    #[should_panic = "out of range"]
+    fn add_overflow() {
+        let _ = Amount::from_units(i64::MAX) + Amount::from_units(1);
+    }
+
+    #[test]
+    fn money_conversions() {
+        let usd = "USD".parse::<Currency>().unwrap();
+        let jpy = "JPY".parse::<Currency>().unwrap();
+        let bhd = "BHD".parse::<Currency>().unwrap();
+        let money = |value: &str, currency| Money::from_decimal(value.parse().unwrap(), currency);
+
+        assert_eq!(money("12.34", usd).unwrap().to_minor_units(), 1234);
+        assert_eq!(money("1000", jpy).unwrap().to_minor_units(), 1000);
+        assert_eq!(money("1.234", bhd).unwrap().to_minor_units(), 1234);
+        assert!(money("12.345", usd).is_err());
+        assert!(money("0.5", jpy).is_err());
+        assert!(money("1.2345", bhd).is_err());
+
+        assert_eq!(
+            Money::from_minor_units(1234, usd).unwrap().amount,
+            amount("12.34")
+        );
+        assert_eq!(
+            Money::from_minor_units(1234, jpy).unwrap().amount,
+            amount("1234")
+        );
+        assert_eq!(
+            Money::from_minor_units(-1234, bhd).unwrap().amount,
+            amount("-1.234")
+        );
+        assert!(Money::from_minor_units(i64::MAX, jpy).is_err());
+
+        let unrounded = Money::new(amount("2.5"), jpy);
+        assert_eq!(unrounded.round().amount, amount("2"));
+        assert_eq!(unrounded.to_minor_units(), 2);
+    }
+
+    #[test]
+    fn money_allocate() {
+        let jpy = "JPY".parse::<Currency>().unwrap();
+        let bhd = "BHD".parse::<Currency>().unwrap();
+        let parts = Money::new(amount("100"), jpy).allocate(&[Decimal::ONE; 3]);
+        let parts = parts.iter().map(|part| part.amount).collect::<Vec<_>>();
+        assert_eq!(parts, ["34", "33", "33"].map(amount));
+        let parts = Money::new(amount("-1"), bhd).allocate(&[Decimal::ONE; 3]);
+        let parts = parts.iter().map(|part| part.amount).collect::<Vec<_>>();
+        assert_eq!(parts, ["-0.334", "-0.333", "-0.333"].map(amount));
+    }
+
+    #[test]
+    fn money_display() {
+        let money = |value: &str, code: &str| Money::new(amount(value), code.parse().unwrap());
+        assert_eq!(money("-1234.5", "USD").to_string(), "-1234.50 USD");
+        assert_eq!(format!("{:#}", money("-1234.5", "USD")), "(1,234.50) USD");
+        assert_eq!(money("1000", "JPY").to_string(), "1000 JPY");
+        assert_eq!(format!("{:#}", money("1000000", "JPY")), "1,000,000 JPY");
+        assert_eq!(money("1.2", "BHD").to_string(), "1.200 BHD");
+        assert_eq!(money("0.5", "JPY").to_string(), "0.5 JPY");
+        assert_eq!(format!("{:>14}", money("-1.2", "BHD")), "    -1.200 BHD");
+    }
+
+    #[test]
+    fn precision() {
+        // no minor unit, as JPY, and thousandths, as BHD
+        assert_eq!(amount("1000").to_units(), 10_000_000);
+        assert_eq!(amount("1.234").to_units(), 12_340);
+        assert_eq!(amount("0.0001").to_units(), 1);
+        assert!(Amount::try_from(Decimal::new(1, 5)).is_err());
+        assert!(Amount::try_from(Decimal::MAX).is_err());
+    }
+
+    #[test]
+    fn deserialize() {
+        let parse = |json| serde_json::from_str::<Amount>(json).unwrap();
+        assert_eq!(parse(r#"{"value":"12.50"}"#), amount("12.5"));
+        assert_eq!(parse(r#"{"value":"-0.001"}"#), amount("-0.001"));
+        // more precise than an amount can be, as may have been stored before, is rounded
+        assert_eq!(parse(r#"{"value":"1.23456"}"#), amount("1.2346"));
+        assert_eq!(parse(r#"{"value":"1.00005"}"#), amount("1.0000"));
+        assert_eq!(parse(r#"{"value":"-1.00015"}"#), amount("-1.0002"));
+        assert!(serde_json::from_str::<Amount>(r#"{"value":"1e30"}"#).is_err());
+        let json = serde_json::to_string(&amount("-1234.5")).unwrap();
+        assert_eq!(json, r#"{"value":"-1234.50"}"#);
+        assert_eq!(parse(&json), amount("-1234.5"));
     }
 }
@@ -0,0 +1,178 @@
+//! Importing transactions from files exported by banks and other programs.
+//!
+//! CSV files, read by [`csv::parse_csv`], name all of the accounts involved in each transaction.
+//!
+//! OFX and QIF statements are each for a single account, which the caller passes in. The other
+//! accounts involved in each transaction are named in the statement, by a category or payee, and
+//! are resolved to ids by a closure passed in by the caller. Names the closure doesn't recognize
+//! are returned in [`Import::unknown_accounts`], so the caller can create those accounts and
+//! import the statement again.
+//!
+//! A malformed record doesn't stop the rest of a statement from being read: its error is
+//! returned in [`Import::errors`] or [`csv::CsvImport::errors`], and its transaction is left out.
+
+use std::{collections::BTreeSet, str::FromStr};
+
+use rust_decimal::Decimal;
+use time::{Date, Month};
+
+use crate::{
+    backend::id::Id,
+    map::Map,
+    public::{account::Account, amount::Amount, transaction::Transaction},
+};
+
+pub mod csv;
+pub mod ofx;
+pub mod qif;
+
+/// The transactions read from a statement.
+#[derive(Clone, Debug, Default)]
+pub struct Import {
+    /// The transactions whose accounts were all resolved, in the order they appear in the
+    /// statement.
+    pub transactions: Vec<ImportedTransaction>,
+    /// The names of the accounts which couldn't be resolved, in the order they first appear.
+    ///
+    /// Transactions involving these accounts are left out of `transactions`.
+    pub unknown_accounts: Vec<String>,
+    /// The errors in records which couldn't be read, in the order of the records.
+    ///
+    /// The transactions of these records are left out of `transactions`.
+    pub errors: Vec<ImportError>,
+}
+
+/// A transaction read from a statement.
+#[derive(Clone, Debug)]
+pub struct ImportedTransaction {
+    /// The statement's identifier for the transaction, if it has one.
+    ///
+    /// Banks keep this the same when a transaction appears in several statements, so it can be
+    /// used to skip transactions which were already imported. Transactions with the same
+    /// identifier within one statement are only imported once.
+    pub external_id: Option<String>,
+    pub transaction: Transaction,
+}
+
+/// An error reading an imported file.
+#[derive(Clone, Debug, thiserror::Error)]
+pub enum ImportError {
+    #[error("Invalid date {value:?} on line {line}")]
+    InvalidDate { line: usize, value: String },
+    #[error("Invalid amount {value:?} on line {line}")]
+    InvalidAmount { line: usize, value: String },
+    #[error("Transaction on line {line} is missing its {field}")]
+    MissingField { line: usize, field: &'static str },
+    #[error("Unknown account {name:?} on line {line}")]
+    UnknownAccount { line: usize, name: String },
+    #[error("The splits of the transaction on line {line} don't balance")]
+    UnbalancedSplits { line: usize },
+    #[error("The header row is missing the {0} column")]
+    MissingColumn(&'static str),
+    #[error("Malformed row on line {line}: {message}")]
+    Malformed { line: usize, message: String },
+}
+
+/// Collects the transactions of a statement, resolving the names of the accounts involved.
+struct Importer<F> {
+    account: Id<Account>,
+    resolve: F,
+    import: Import,
+    external_ids: BTreeSet<String>,
+}
+
+impl<F: FnMut(&str) -> Option<Id<Account>>> Importer<F> {
+    fn new(account: Id<Account>, resolve: F) -> Self {
+        Self {
+            account,
+            resolve,
+            import: Import::default(),
+            external_ids: BTreeSet::new(),
+        }
+    }
+
+    /// Add a transaction moving `amount` into the statement's account, out of the accounts named
+    /// in `splits`, each with its share of `amount`.
+    fn add(
+        &mut self,
+        line: usize,
+        external_id: Option<String>,
+        date: Date,
+        description: String,
+        amount: Amount,
+        splits: Vec<(String, Amount)>,
+    ) -> Result<(), ImportError> {
+        if let Some(external_id) = &external_id {
+            if !self.external_ids.insert(external_id.clone()) {
+                return Ok(());
+            }
+        }
+        if Amount::checked_sum(splits.iter().map(|(_, share)| *share)) != Some(amount) {
+            return Err(ImportError::UnbalancedSplits { line });
+        }
+        let mut amounts = Map::default();
+        amounts.insert(self.account, amount);
+        let mut is_resolved = true;
+        for (name, share) in splits {
+            match (self.resolve)(&name) {
+                Some(id) => {
+                    let total = amounts.entry(id).or_insert(Amount::ZERO);
+                    *total = total
+                        .checked_sub(share)
+                        .ok_or_else(|| ImportError::Malformed {
+                            line,
+                            message: "the total for the account is out of range".into(),
+                        })?;
+                }
+                None => {
+                    is_resolved = false;
+                    if !self.import.unknown_accounts.contains(&name) {
+                        self.import.unknown_accounts.push(name);
+                    }
+                }
+            }
+        }
+        if is_resolved {
+            self.import.transactions.push(ImportedTransaction {
+                external_id,
+                transaction: Transaction {
+                    date,
+                    description,
+                    amounts,
+                },
+            });
+        }
+        Ok(())
+    }
+
+    /// Keep the error of a record which couldn't be added, and go on to the next record.
+    fn record(&mut self, result: Result<(), ImportError>) {
+        if let Err(err) = result {
+            self.import.errors.push(err);
+        }
+    }
+
+    fn finish(self) -> Import {
+        self.import
+    }
+}
+
+/// Parse a decimal amount, ignoring thousands separators.
+fn parse_amount(line: usize, value: &str) -> Result<Amount, ImportError> {
+    let invalid = || ImportError::InvalidAmount {
+        line,
+        value: value.into(),
+    };
+    let decimal = Decimal::from_str(&value.trim().replace(',', "")).map_err(|_| invalid())?;
+    Amount::try_from(decimal).map_err(|_| invalid())
+}
+
+/// Construct a date, failing with an error about `value` if it doesn't exist.
+fn make_date(line: usize, value: &str, year: i32, month: u8, day: u8) -> Result<Date, ImportError> {
+    Month::try_from(month)
+        .and_then(|month| Date::from_calendar_date(year, month, day))
+        .map_err(|_| ImportError::InvalidDate {
+            line,
+            value: value.into(),
+        })
+}
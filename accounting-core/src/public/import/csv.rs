@@ -0,0 +1,388 @@
+//! Importing CSV files.
+//!
+//! The file must start with a header row naming its columns, in any order: `date`,
+//! `description`, `account`, `amount`, and `reference`. Other columns are ignored. Each row is
+//! one split of a transaction, moving its amount into the named account, and the rows with the
+//! same reference make up a transaction, which must balance. The date and description of a
+//! transaction are taken from its first row.
+//!
+//! Dates are in the ISO 8601 form `YYYY-MM-DD`.
+
+use std::{collections::BTreeMap, io};
+
+use time::{format_description::well_known::Iso8601, Date};
+
+use crate::{
+    backend::id::Id,
+    map::Map,
+    public::{
+        account::Account,
+        amount::Amount,
+        import::{parse_amount, ImportError},
+        transaction::Transaction,
+    },
+};
+
+/// The transactions read from a CSV file.
+#[derive(Clone, Debug, Default)]
+pub struct CsvImport {
+    /// The transactions whose rows were all read, in the order of their first rows.
+    pub transactions: Vec<Transaction>,
+    /// The errors in rows which couldn't be read, in the order of the rows, followed by the
+    /// transactions which don't balance.
+    ///
+    /// Transactions with any rows in error are left out of `transactions`.
+    pub errors: Vec<ImportError>,
+}
+
+/// Read the transactions of a CSV file, resolving the names of the accounts with `resolve`.
+///
+/// Fails only if the header row can't be read or is missing a column; errors in other rows are
+/// returned in [`CsvImport::errors`].
+pub fn parse_csv(
+    input: impl io::Read,
+    mut resolve: impl FnMut(&str) -> Option<Id<Account>>,
+) -> Result<CsvImport, ImportError> {
+    let mut reader = ::csv::ReaderBuilder::new()
+        .flexible(true)
+        .trim(::csv::Trim::All)
+        .from_reader(input);
+    let header = reader.headers().map_err(|err| ImportError::Malformed {
+        line: 1,
+        message: err.to_string(),
+    })?;
+    let columns = Columns::new(header)?;
+    let mut transactions = Vec::<PendingTransaction>::new();
+    let mut by_reference = BTreeMap::new();
+    let mut import = CsvImport::default();
+    for record in reader.records() {
+        let record = match record {
+            Ok(record) => record,
+            Err(err) => {
+                let line = err
+                    .position()
+                    .map_or(0, |position| position.line() as usize);
+                import.errors.push(ImportError::Malformed {
+                    line,
+                    message: err.to_string(),
+                });
+                continue;
+            }
+        };
+        let line = record
+            .position()
+            .map_or(0, |position| position.line() as usize);
+        let reference = columns.get(&record, Column::Reference);
+        if reference.is_empty() {
+            import.errors.push(ImportError::MissingField {
+                line,
+                field: "reference",
+            });
+            continue;
+        }
+        let index = *by_reference.entry(reference.to_owned()).or_insert_with(|| {
+            transactions.push(PendingTransaction::new(line));
+            transactions.len() - 1
+        });
+        let transaction = &mut transactions[index];
+        let split = columns.split(&record, line, &mut resolve);
+        match split.and_then(|split| transaction.add(line, split)) {
+            Ok(()) => {}
+            Err(err) => {
+                transaction.is_valid = false;
+                import.errors.push(err);
+            }
+        }
+    }
+    for transaction in transactions {
+        match transaction.finish() {
+            Ok(Some(transaction)) => import.transactions.push(transaction),
+            Ok(None) => {}
+            Err(err) => import.errors.push(err),
+        }
+    }
+    Ok(import)
+}
+
+#[derive(Clone, Copy)]
+enum Column {
+    Date,
+    Description,
+    Account,
+    Amount,
+    Reference,
+}
+
+impl Column {
+    const ALL: [Self; 5] = [
+        Self::Date,
+        Self::Description,
+        Self::Account,
+        Self::Amount,
+        Self::Reference,
+    ];
+
+    fn name(self) -> &'static str {
+        match self {
+            Self::Date => "date",
+            Self::Description => "description",
+            Self::Account => "account",
+            Self::Amount => "amount",
+            Self::Reference => "reference",
+        }
+    }
+}
+
+/// The positions of each of the columns in a row.
+struct Columns([usize; 5]);
+
+impl Columns {
+    fn new(header: &::csv::StringRecord) -> Result<Self, ImportError> {
+        let mut positions = [0; 5];
+        for (position, column) in positions.iter_mut().zip(Column::ALL) {
+            *position = header
+                .iter()
+                .position(|name| name.eq_ignore_ascii_case(column.name()))
+                .ok_or(ImportError::MissingColumn(column.name()))?;
+        }
+        Ok(Self(positions))
+    }
+
+    /// The value of the column in the row, or an empty string if the row is too short.
+    fn get<'a>(&self, record: &'a ::csv::StringRecord, column: Column) -> &'a str {
+        record.get(self.0[column as usize]).unwrap_or("")
+    }
+
+    fn split(
+        &self,
+        record: &::csv::StringRecord,
+        line: usize,
+        resolve: &mut impl FnMut(&str) -> Option<Id<Account>>,
+    ) -> Result<Split, ImportError> {
+        let required = |column: Column| match self.get(record, column) {
+            "" => Err(ImportError::MissingField {
+                line,
+                field: column.name(),
+            }),
+            value => Ok(value),
+        };
+        let date = required(Column::Date)?;
+        let date = Date::parse(date, &Iso8601::DATE).map_err(|_| ImportError::InvalidDate {
+            line,
+            value: date.into(),
+        })?;
+        let amount = parse_amount(line, required(Column::Amount)?)?;
+        let name = required(Column::Account)?;
+        let account = resolve(name).ok_or_else(|| ImportError::UnknownAccount {
+            line,
+            name: name.into(),
+        })?;
+        Ok(Split {
+            date,
+            description: self.get(record, Column::Description).into(),
+            account,
+            amount,
+        })
+    }
+}
+
+/// A row of the file.
+struct Split {
+    date: Date,
+    description: String,
+    account: Id<Account>,
+    amount: Amount,
+}
+
+/// The rows of a transaction read so far.
+struct PendingTransaction {
+    /// The line of the first row of the transaction.
+    line: usize,
+    /// Whether all of the rows of the transaction have been read without errors.
+    is_valid: bool,
+    /// The date and description of the first row of the transaction.
+    first: Option<(Date, String)>,
+    amounts: Map<Id<Account>, Amount>,
+}
+
+impl PendingTransaction {
+    fn new(line: usize) -> Self {
+        Self {
+            line,
+            is_valid: true,
+            first: None,
+            amounts: Map::default(),
+        }
+    }
+
+    fn add(&mut self, line: usize, split: Split) -> Result<(), ImportError> {
+        let total = self.amounts.entry(split.account).or_insert(Amount::ZERO);
+        *total = total
+            .checked_add(split.amount)
+            .ok_or_else(|| ImportError::Malformed {
+                line,
+                message: "the total for the account is out of range".into(),
+            })?;
+        self.first.get_or_insert((split.date, split.description));
+        Ok(())
+    }
+
+    /// The transaction, or `None` if any of its rows had errors.
+    fn finish(self) -> Result<Option<Transaction>, ImportError> {
+        let Some((date, description)) = self.first.filter(|_| self.is_valid) else {
+            return Ok(None);
+        };
+        let transaction = Transaction {
+            date,
+            description,
+            amounts: self.amounts,
+        };
+        if !transaction.is_balanced() {
+            return Err(ImportError::UnbalancedSplits { line: self.line });
+        }
+        Ok(Some(transaction))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use time::macros::date;
+
+    use super::parse_csv;
+    use crate::{
+        backend::id::Id,
+        public::{account::Account, amount::Amount, import::ImportError},
+    };
+
+    /// Ids for the accounts named `cash`, `bank` and `food`.
+    fn accounts() -> impl Fn(&str) -> Option<Id<Account>> {
+        let ids = [(); 3].map(|()| Id::new_random());
+        move |name| match name {
+            "cash" => Some(ids[0]),
+            "bank" => Some(ids[1]),
+            "food" => Some(ids[2]),
+            _ => None,
+        }
+    }
+
+    fn amount(units: i64) -> Amount {
+        Amount::from_units(units)
+    }
+
+    #[test]
+    fn columns_in_any_order() {
+        let resolve = accounts();
+        let input = "\
+            Amount,Reference,Notes,Account,Date,Description\n\
+            -12.50,1,ignored,cash,2024-01-15,Lunch\n\
+            12.50,1,,food,2024-01-16,Ignored\n";
+        let import = parse_csv(input.as_bytes(), &resolve).unwrap();
+        assert!(import.errors.is_empty(), "{:?}", import.errors);
+        let [transaction] = &import.transactions[..] else {
+            panic!("{:?}", import.transactions);
+        };
+        assert_eq!(transaction.date, date!(2024 - 01 - 15));
+        assert_eq!(transaction.description, "Lunch");
+        assert_eq!(
+            transaction.amounts[&resolve("cash").unwrap()],
+            amount(-125_000)
+        );
+        assert_eq!(
+            transaction.amounts[&resolve("food").unwrap()],
+            amount(125_000)
+        );
+    }
+
+    #[test]
+    fn missing_column() {
+        let input = "date,description,account,amount\n2024-01-15,Lunch,cash,-12.50\n";
+        assert!(matches!(
+            parse_csv(input.as_bytes(), accounts()),
+            Err(ImportError::MissingColumn("reference"))
+        ));
+    }
+
+    #[test]
+    fn grouped_by_reference() {
+        let resolve = accounts();
+        let input = "\
+            date,description,account,amount,reference\n\
+            2024-01-15,Dinner,bank,-30,a\n\
+            2024-01-15,Rent,bank,-1000,b\n\
+            2024-01-15,,food,20,a\n\
+            2024-01-15,,cash,10,a\n\
+            2024-01-15,,cash,1000,b\n";
+        let import = parse_csv(input.as_bytes(), &resolve).unwrap();
+        assert!(import.errors.is_empty(), "{:?}", import.errors);
+        let descriptions = import
+            .transactions
+            .iter()
+            .map(|transaction| transaction.description.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(descriptions, ["Dinner", "Rent"]);
+        let dinner = &import.transactions[0];
+        assert_eq!(dinner.amounts.len(), 3);
+        assert_eq!(dinner.amounts[&resolve("food").unwrap()], amount(200_000));
+        assert!(dinner.is_balanced());
+    }
+
+    #[test]
+    fn unbalanced() {
+        let input = "\
+            date,description,account,amount,reference\n\
+            2024-01-15,Lunch,cash,-12.50,1\n\
+            2024-01-15,Lunch,food,12.00,1\n\
+            2024-01-16,Rent,bank,-1000,2\n\
+            2024-01-16,Rent,cash,1000,2\n";
+        let import = parse_csv(input.as_bytes(), accounts()).unwrap();
+        assert!(matches!(
+            &import.errors[..],
+            [ImportError::UnbalancedSplits { line: 2 }]
+        ));
+        assert_eq!(import.transactions.len(), 1);
+        assert_eq!(import.transactions[0].description, "Rent");
+    }
+
+    #[test]
+    fn bad_rows() {
+        let input = "\
+            date,description,account,amount,reference\n\
+            2024-13-01,Lunch,cash,-12.50,1\n\
+            2024-01-15,Lunch,food,12.50,1\n\
+            2024-01-16,Coffee,cash,-3.x,2\n\
+            2024-01-16,Coffee,food,3,2\n\
+            2024-01-17,Book,wallet,-20,3\n\
+            2024-01-17,Book,cash,20,3\n\
+            2024-01-18,Rent,bank,-1000,4\n\
+            2024-01-18,Rent,cash,1000,4\n\
+            2024-01-19,Fee,bank,-1,\n";
+        let import = parse_csv(input.as_bytes(), accounts()).unwrap();
+        let errors = import
+            .errors
+            .iter()
+            .map(ToString::to_string)
+            .collect::<Vec<_>>();
+        assert!(
+            matches!(
+                &import.errors[..],
+                [
+                    ImportError::InvalidDate { line: 2, .. },
+                    ImportError::InvalidAmount { line: 4, .. },
+                    ImportError::UnknownAccount { line: 6, .. },
+                    ImportError::MissingField {
+                        line: 10,
+                        field: "reference"
+                    },
+                ]
+            ),
+            "{errors:?}"
+        );
+        // the bad rows leave out their transactions, but not the others
+        let descriptions = import
+            .transactions
+            .iter()
+            .map(|transaction| transaction.description.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(descriptions, ["Rent"]);
+    }
+}
@@ -0,0 +1,307 @@
+//! Importing OFX statements.
+//!
+//! Both the SGML form of OFX 1.x, in which elements holding values aren't closed, and the XML
+//! form of OFX 2.x are read. Only the statement transactions (`<STMTTRN>`) are used; each
+//! becomes a transaction moving its `<TRNAMT>` into the statement's account, out of the account
+//! named by its `<NAME>`, or its `<MEMO>` if it has no name. Transactions are de-duplicated by
+//! their `<FITID>`.
+
+use std::iter;
+
+use crate::{
+    backend::id::Id,
+    public::{
+        account::Account,
+        import::{make_date, parse_amount, Import, ImportError, Importer},
+    },
+};
+
+/// Read the transactions of an OFX statement for `account`, resolving the names of the other
+/// accounts involved with `resolve`.
+///
+/// Errors in transactions are returned in [`Import::errors`].
+pub fn parse_ofx(
+    input: &str,
+    account: Id<Account>,
+    resolve: impl FnMut(&str) -> Option<Id<Account>>,
+) -> Import {
+    let mut importer = Importer::new(account, resolve);
+    let mut current: Option<StatementTransaction> = None;
+    for (line, tag, text) in elements(input) {
+        match (tag, &mut current) {
+            ("STMTTRN", _) => current = Some(StatementTransaction::new(line)),
+            ("/STMTTRN", Some(_)) => {
+                if let Some(transaction) = current.take() {
+                    let result = transaction.add_to(&mut importer);
+                    importer.record(result);
+                }
+            }
+            (_, Some(transaction)) if !text.is_empty() => {
+                let value = Some(decode_entities(text));
+                match tag {
+                    "DTPOSTED" => transaction.date_posted = value,
+                    "TRNAMT" => transaction.amount = value,
+                    "FITID" => transaction.fitid = value,
+                    "NAME" => transaction.name = value,
+                    "MEMO" => transaction.memo = value,
+                    _ => {}
+                }
+            }
+            _ => {}
+        }
+    }
+    importer.finish()
+}
+
+/// The fields of a `<STMTTRN>` element which are used.
+struct StatementTransaction {
+    /// The line the element starts on.
+    line: usize,
+    date_posted: Option<String>,
+    amount: Option<String>,
+    fitid: Option<String>,
+    name: Option<String>,
+    memo: Option<String>,
+}
+
+impl StatementTransaction {
+    fn new(line: usize) -> Self {
+        Self {
+            line,
+            date_posted: None,
+            amount: None,
+            fitid: None,
+            name: None,
+            memo: None,
+        }
+    }
+
+    fn add_to<F: FnMut(&str) -> Option<Id<Account>>>(
+        self,
+        importer: &mut Importer<F>,
+    ) -> Result<(), ImportError> {
+        let line = self.line;
+        let missing = |field| ImportError::MissingField { line, field };
+        let date_posted = self.date_posted.ok_or_else(|| missing("DTPOSTED"))?;
+        let date = parse_date(line, &date_posted)?;
+        let amount = parse_amount(line, &self.amount.ok_or_else(|| missing("TRNAMT"))?)?;
+        let description = match (&self.name, &self.memo) {
+            (Some(name), Some(memo)) => format!("{name} - {memo}"),
+            (Some(text), None) | (None, Some(text)) => text.clone(),
+            (None, None) => return Err(missing("NAME")),
+        };
+        let other = self.name.or(self.memo).unwrap_or_default();
+        importer.add(
+            line,
+            self.fitid,
+            date,
+            description,
+            amount,
+            vec![(other, amount)],
+        )
+    }
+}
+
+/// Parse an OFX date, of the form `YYYYMMDD`, optionally followed by a time and time zone,
+/// which are ignored.
+fn parse_date(line: usize, value: &str) -> Result<time::Date, ImportError> {
+    let invalid = || ImportError::InvalidDate {
+        line,
+        value: value.into(),
+    };
+    let digits = value
+        .get(..8)
+        .filter(|digits| digits.bytes().all(|b| b.is_ascii_digit()));
+    let digits = digits.ok_or_else(invalid)?;
+    let year = digits[..4].parse().map_err(|_| invalid())?;
+    let month = digits[4..6].parse().map_err(|_| invalid())?;
+    let day = digits[6..].parse().map_err(|_| invalid())?;
+    make_date(line, value, year, month, day)
+}
+
+/// Split a document into its tags, each with the line it is on and the text following it, up to
+/// the next tag.
+///
+/// Anything before the first tag, such as the header of an OFX 1.x document, is skipped, as are
+/// processing instructions and comments.
+fn elements(input: &str) -> impl Iterator<Item = (usize, &str, &str)> {
+    let mut rest = input;
+    let mut line = 1;
+    iter::from_fn(move || loop {
+        let start = rest.find('<')?;
+        line += rest[..start].matches('\n').count();
+        let after = &rest[start + 1..];
+        let end = after.find('>')?;
+        let tag = &after[..end];
+        rest = &after[end + 1..];
+        let text = &rest[..rest.find('<').unwrap_or(rest.len())];
+        let tag_line = line;
+        line += tag.matches('\n').count();
+        if !tag.starts_with(['?', '!']) {
+            return Some((tag_line, tag.trim(), text.trim()));
+        }
+    })
+}
+
+/// Replace the character entities which may appear in OFX values.
+fn decode_entities(text: &str) -> String {
+    text.replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&quot;", "\"")
+        .replace("&apos;", "'")
+        .replace("&nbsp;", "\u{a0}")
+        .replace("&amp;", "&")
+}
+
+#[cfg(test)]
+mod tests {
+    use time::macros::date;
+
+    use super::parse_ofx;
+    use crate::{
+        backend::id::Id,
+        public::{account::Account, amount::Amount, import::ImportError},
+    };
+
+    /// Ids for the statement's account, and the accounts named `Cafe` and `Smith & Sons`.
+    fn accounts() -> (Id<Account>, impl Fn(&str) -> Option<Id<Account>>) {
+        let ids = [(); 3].map(|()| Id::new_random());
+        let resolve = move |name: &str| match name {
+            "Cafe" => Some(ids[1]),
+            "Smith & Sons" => Some(ids[2]),
+            _ => None,
+        };
+        (ids[0], resolve)
+    }
+
+    const SGML: &str = "\
+        OFXHEADER:100\n\
+        DATA:OFXSGML\n\
+        VERSION:102\n\
+        \n\
+        <OFX>\n\
+        <BANKMSGSRSV1><STMTTRNRS><STMTRS><BANKTRANLIST>\n\
+        <STMTTRN>\n\
+        <TRNTYPE>DEBIT\n\
+        <DTPOSTED>20240115120000[-5:EST]\n\
+        <TRNAMT>-12.50\n\
+        <FITID>1001\n\
+        <NAME>Cafe\n\
+        <MEMO>Lunch\n\
+        </STMTTRN>\n\
+        <STMTTRN>\n\
+        <TRNTYPE>CREDIT\n\
+        <DTPOSTED>20240131\n\
+        <TRNAMT>1,000.00\n\
+        <FITID>1002\n\
+        <NAME>Smith &amp; Sons\n\
+        </STMTTRN>\n\
+        </BANKTRANLIST></STMTRS></STMTTRNRS></BANKMSGSRSV1>\n\
+        </OFX>\n";
+
+    const XML: &str = "\
+        <?xml version=\"1.0\" encoding=\"UTF-8\"?>\n\
+        <?OFX OFXHEADER=\"200\" VERSION=\"211\"?>\n\
+        <OFX>\n\
+        <!-- a comment -->\n\
+        <BANKMSGSRSV1><STMTTRNRS><STMTRS><BANKTRANLIST>\n\
+        <STMTTRN>\n\
+        <TRNTYPE>DEBIT</TRNTYPE>\n\
+        <DTPOSTED>20240115120000[-5:EST]</DTPOSTED>\n\
+        <TRNAMT>-12.50</TRNAMT>\n\
+        <FITID>1001</FITID>\n\
+        <NAME>Cafe</NAME>\n\
+        <MEMO>Lunch</MEMO>\n\
+        </STMTTRN>\n\
+        <STMTTRN>\n\
+        <TRNTYPE>CREDIT</TRNTYPE>\n\
+        <DTPOSTED>20240131</DTPOSTED>\n\
+        <TRNAMT>1,000.00</TRNAMT>\n\
+        <FITID>1002</FITID>\n\
+        <NAME>Smith &amp; Sons</NAME>\n\
+        </STMTTRN>\n\
+        </BANKTRANLIST></STMTRS></STMTTRNRS></BANKMSGSRSV1>\n\
+        </OFX>\n";
+
+    #[test]
+    fn sgml_and_xml() {
+        for input in [SGML, XML] {
+            let (account, resolve) = accounts();
+            let import = parse_ofx(input, account, &resolve);
+            assert!(import.errors.is_empty(), "{:?}", import.errors);
+            assert!(import.unknown_accounts.is_empty());
+            let [lunch, pay] = &import.transactions[..] else {
+                panic!("{:?}", import.transactions);
+            };
+            assert_eq!(lunch.external_id.as_deref(), Some("1001"));
+            assert_eq!(lunch.transaction.date, date!(2024 - 01 - 15));
+            assert_eq!(lunch.transaction.description, "Cafe - Lunch");
+            let amounts = &lunch.transaction.amounts;
+            assert_eq!(amounts[&account], Amount::from_units(-125_000));
+            assert_eq!(
+                amounts[&resolve("Cafe").unwrap()],
+                Amount::from_units(125_000)
+            );
+            assert_eq!(pay.transaction.date, date!(2024 - 01 - 31));
+            assert_eq!(pay.transaction.description, "Smith & Sons");
+            let amounts = &pay.transaction.amounts;
+            assert_eq!(amounts[&account], Amount::from_units(10_000_000));
+        }
+    }
+
+    #[test]
+    fn entities() {
+        assert_eq!(
+            super::decode_entities("&lt;a&gt; &quot;b&quot; &apos;c&apos;&nbsp;&amp;lt;"),
+            "<a> \"b\" 'c'\u{a0}&lt;"
+        );
+    }
+
+    #[test]
+    fn duplicate_fitid() {
+        let (account, resolve) = accounts();
+        let input = "\
+            <STMTTRN><DTPOSTED>20240115<TRNAMT>-1<FITID>1<NAME>Cafe</STMTTRN>\n\
+            <STMTTRN><DTPOSTED>20240116<TRNAMT>-2<FITID>1<NAME>Cafe</STMTTRN>\n\
+            <STMTTRN><DTPOSTED>20240117<TRNAMT>-3<FITID>2<NAME>Cafe</STMTTRN>\n";
+        let import = parse_ofx(input, account, resolve);
+        let dates = import
+            .transactions
+            .iter()
+            .map(|imported| imported.transaction.date)
+            .collect::<Vec<_>>();
+        assert_eq!(dates, [date!(2024 - 01 - 15), date!(2024 - 01 - 17)]);
+    }
+
+    #[test]
+    fn bad_transactions() {
+        let (account, resolve) = accounts();
+        let input = "\
+            <STMTTRN><DTPOSTED>20241301<TRNAMT>-1<NAME>Cafe</STMTTRN>\n\
+            <STMTTRN><DTPOSTED>20240115<TRNAMT>lots<NAME>Cafe</STMTTRN>\n\
+            <STMTTRN><TRNAMT>-1<NAME>Cafe</STMTTRN>\n\
+            <STMTTRN><DTPOSTED>20240116<TRNAMT>-1<NAME>Bakery</STMTTRN>\n\
+            <STMTTRN><DTPOSTED>20240117<TRNAMT>-1<NAME>Cafe</STMTTRN>\n";
+        let import = parse_ofx(input, account, resolve);
+        assert!(
+            matches!(
+                &import.errors[..],
+                [
+                    ImportError::InvalidDate { line: 1, .. },
+                    ImportError::InvalidAmount { line: 2, .. },
+                    ImportError::MissingField {
+                        line: 3,
+                        field: "DTPOSTED"
+                    },
+                ]
+            ),
+            "{:?}",
+            import.errors
+        );
+        assert_eq!(import.unknown_accounts, ["Bakery"]);
+        let [imported] = &import.transactions[..] else {
+            panic!("{:?}", import.transactions);
+        };
+        assert_eq!(imported.transaction.date, date!(2024 - 01 - 17));
+    }
+}
@@ -0,0 +1,349 @@
+//! Importing QIF statements.
+//!
+//! Only the transactions of bank, cash, credit card, and other asset or liability accounts are
+//! read; lists of categories, classes, and so on are skipped. Each transaction moves its amount
+//! into the statement's account, out of the account named by its category (`L`), or by its payee
+//! (`P`) if it has no category. Split transactions move each split's amount out of the account
+//! named by the split's category (`S`). Transfers, whose categories are account names in square
+//! brackets, are resolved by the account name.
+//!
+//! Dates are read in the US order, month first, as written by Quicken.
+
+use crate::{
+    backend::id::Id,
+    public::{
+        account::Account,
+        amount::Amount,
+        import::{make_date, parse_amount, Import, ImportError, Importer},
+    },
+};
+
+/// Read the transactions of a QIF statement for `account`, resolving the names of the other
+/// accounts involved with `resolve`.
+///
+/// Errors in records are returned in [`Import::errors`].
+pub fn parse_qif(
+    input: &str,
+    account: Id<Account>,
+    resolve: impl FnMut(&str) -> Option<Id<Account>>,
+) -> Import {
+    let mut importer = Importer::new(account, resolve);
+    let mut in_transactions = false;
+    let mut current: Option<Record> = None;
+    for (index, text) in input.lines().enumerate() {
+        let line = index + 1;
+        let text = text.trim_end();
+        let Some(code) = text.chars().next() else {
+            continue;
+        };
+        let value = text[code.len_utf8()..].trim();
+        if code == '!' {
+            in_transactions = is_transaction_type(value);
+            current = None;
+            continue;
+        }
+        if !in_transactions {
+            continue;
+        }
+        if code == '^' {
+            if let Some(record) = current.take() {
+                let result = record.add_to(&mut importer);
+                importer.record(result);
+            }
+            continue;
+        }
+        let record = current.get_or_insert_with(|| Record::new(line));
+        match code {
+            'D' => record.date = Some(value.into()),
+            'T' | 'U' => record.amount = Some(value.into()),
+            'P' => record.payee = Some(value.into()),
+            'M' => record.memo = Some(value.into()),
+            'L' => record.category = Some(value.into()),
+            'S' => record.splits.push(Split {
+                category: value.into(),
+                amount: None,
+            }),
+            '$' => {
+                if let Some(split) = record.splits.last_mut() {
+                    match parse_amount(line, value) {
+                        Ok(amount) => split.amount = Some(amount),
+                        Err(err) => {
+                            record.error.get_or_insert(err);
+                        }
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+    importer.finish()
+}
+
+/// Returns whether a header introduces a list of transactions.
+fn is_transaction_type(header: &str) -> bool {
+    let Some(account_type) = header.strip_prefix("Type:") else {
+        return false;
+    };
+    ["Bank", "Cash", "CCard", "Oth A", "Oth L"]
+        .iter()
+        .any(|name| account_type.trim().eq_ignore_ascii_case(name))
+}
+
+/// The fields of a transaction record which are used.
+struct Record {
+    /// The line the record starts on.
+    line: usize,
+    date: Option<String>,
+    amount: Option<String>,
+    payee: Option<String>,
+    memo: Option<String>,
+    category: Option<String>,
+    splits: Vec<Split>,
+    /// The first error in a field which is read as soon as it is seen.
+    error: Option<ImportError>,
+}
+
+struct Split {
+    category: String,
+    amount: Option<Amount>,
+}
+
+impl Record {
+    fn new(line: usize) -> Self {
+        Self {
+            line,
+            date: None,
+            amount: None,
+            payee: None,
+            memo: None,
+            category: None,
+            splits: Vec::new(),
+            error: None,
+        }
+    }
+
+    fn add_to<F: FnMut(&str) -> Option<Id<Account>>>(
+        self,
+        importer: &mut Importer<F>,
+    ) -> Result<(), ImportError> {
+        if let Some(err) = self.error {
+            return Err(err);
+        }
+        let line = self.line;
+        let missing = |field| ImportError::MissingField { line, field };
+        let date = parse_date(line, &self.date.ok_or_else(|| missing("date"))?)?;
+        let amount = parse_amount(line, &self.amount.ok_or_else(|| missing("amount"))?)?;
+        let description = match (&self.payee, &self.memo) {
+            (Some(payee), Some(memo)) => format!("{payee} - {memo}"),
+            (Some(text), None) | (None, Some(text)) => text.clone(),
+            (None, None) => String::new(),
+        };
+        let splits = if self.splits.is_empty() {
+            let other = self
+                .category
+                .as_deref()
+                .map(account_name)
+                .or(self.payee.as_deref())
+                .ok_or_else(|| missing("category"))?;
+            vec![(other.into(), amount)]
+        } else {
+            self.splits
+                .into_iter()
+                .map(|split| {
+                    let share = split.amount.ok_or_else(|| missing("split amount"))?;
+                    Ok((account_name(&split.category).into(), share))
+                })
+                .collect::<Result<_, _>>()?
+        };
+        importer.add(line, None, date, description, amount, splits)
+    }
+}
+
+/// The name of the account in a category, without the brackets of a transfer or the class
+/// following a `/`.
+fn account_name(category: &str) -> &str {
+    let name = category.split('/').next().unwrap_or(category).trim();
+    name.strip_prefix('[')
+        .and_then(|name| name.strip_suffix(']'))
+        .unwrap_or(name)
+}
+
+/// Parse a QIF date, either `M/D/Y` or `YYYY-MM-DD`.
+///
+/// Two-digit years are in the 1900s when they follow a `/`, as written by Quicken, unless they
+/// are below 70, and are in the 2000s when they follow a `'`.
+fn parse_date(line: usize, value: &str) -> Result<time::Date, ImportError> {
+    let invalid = || ImportError::InvalidDate {
+        line,
+        value: value.into(),
+    };
+    let parse = |part: &str| part.trim().parse::<u16>().map_err(|_| invalid());
+    if let [year, month, day] = value.split('-').collect::<Vec<_>>()[..] {
+        let month = parse(month)?.try_into().map_err(|_| invalid())?;
+        let day = parse(day)?.try_into().map_err(|_| invalid())?;
+        return make_date(line, value, parse(year)?.into(), month, day);
+    }
+    let (month_day, year, is_2000s) = match value.split_once('\'') {
+        Some((month_day, year)) => (month_day, year, true),
+        None => value
+            .rsplit_once('/')
+            .map(|(md, y)| (md, y, false))
+            .ok_or_else(invalid)?,
+    };
+    let (month, day) = month_day.split_once('/').ok_or_else(invalid)?;
+    let month = parse(month)?.try_into().map_err(|_| invalid())?;
+    let day = parse(day)?.try_into().map_err(|_| invalid())?;
+    let year = i32::from(parse(year)?);
+    let year = match year {
+        100.. => year,
+        _ if is_2000s || year < 70 => 2000 + year,
+        _ => 1900 + year,
+    };
+    make_date(line, value, year, month, day)
+}
+
+#[cfg(test)]
+mod tests {
+    use time::macros::date;
+
+    use super::{parse_date, parse_qif};
+    use crate::{
+        backend::id::Id,
+        public::{account::Account, amount::Amount, import::ImportError},
+    };
+
+    /// Ids for the statement's account, and the accounts named `Food`, `Household`, `Salary` and
+    /// `Savings`.
+    fn accounts() -> (Id<Account>, impl Fn(&str) -> Option<Id<Account>>) {
+        let ids = [(); 5].map(|()| Id::new_random());
+        let resolve = move |name: &str| match name {
+            "Food" => Some(ids[1]),
+            "Household" => Some(ids[2]),
+            "Salary" => Some(ids[3]),
+            "Savings" => Some(ids[4]),
+            _ => None,
+        };
+        (ids[0], resolve)
+    }
+
+    fn amount(units: i64) -> Amount {
+        Amount::from_units(units)
+    }
+
+    #[test]
+    fn transactions() {
+        let (account, resolve) = accounts();
+        let input = "\
+            !Type:Cat\n\
+            NFood\n\
+            ^\n\
+            !Type:Bank\n\
+            D1/15'24\n\
+            T-30.00\n\
+            PGrocer\n\
+            LFood/Weekly\n\
+            ^\n\
+            D12/31/99\n\
+            T1,000.00\n\
+            PEmployer\n\
+            MDecember\n\
+            LSalary\n\
+            ^\n\
+            D2/1/05\n\
+            T-100\n\
+            L[Savings]\n\
+            ^\n\
+            D2024-03-01\n\
+            T-50\n\
+            PStore\n\
+            SFood\n\
+            $-30\n\
+            S[Savings]\n\
+            $-20\n\
+            ^\n";
+        let import = parse_qif(input, account, &resolve);
+        assert!(import.errors.is_empty(), "{:?}", import.errors);
+        assert!(import.unknown_accounts.is_empty());
+        let [groceries, pay, transfer, split] = &import.transactions[..] else {
+            panic!("{:?}", import.transactions);
+        };
+        let food = resolve("Food").unwrap();
+        let savings = resolve("Savings").unwrap();
+
+        assert_eq!(groceries.transaction.date, date!(2024 - 01 - 15));
+        assert_eq!(groceries.transaction.description, "Grocer");
+        assert_eq!(groceries.transaction.amounts[&food], amount(300_000));
+
+        assert_eq!(pay.transaction.date, date!(1999 - 12 - 31));
+        assert_eq!(pay.transaction.description, "Employer - December");
+        assert_eq!(pay.transaction.amounts[&account], amount(10_000_000));
+
+        assert_eq!(transfer.transaction.date, date!(2005 - 02 - 01));
+        assert_eq!(transfer.transaction.amounts[&savings], amount(1_000_000));
+
+        let amounts = &split.transaction.amounts;
+        assert_eq!(amounts.len(), 3);
+        assert_eq!(amounts[&account], amount(-500_000));
+        assert_eq!(amounts[&food], amount(300_000));
+        assert_eq!(amounts[&savings], amount(200_000));
+    }
+
+    #[test]
+    fn years() {
+        let date = |value| parse_date(1, value).unwrap();
+        assert_eq!(date("1/15/24"), date!(2024 - 01 - 15));
+        assert_eq!(date("1/15/70"), date!(1970 - 01 - 15));
+        assert_eq!(date("1/15/69"), date!(2069 - 01 - 15));
+        assert_eq!(date("1/15'70"), date!(2070 - 01 - 15));
+        assert_eq!(date("1/15' 5"), date!(2005 - 01 - 15));
+        assert_eq!(date("1/15/1970"), date!(1970 - 01 - 15));
+        assert!(matches!(
+            parse_date(1, "2/30/24"),
+            Err(ImportError::InvalidDate { line: 1, .. })
+        ));
+    }
+
+    #[test]
+    fn bad_records() {
+        let (account, resolve) = accounts();
+        let input = "\
+            !Type:Bank\n\
+            D13/1/24\n\
+            T-1\n\
+            LFood\n\
+            ^\n\
+            D1/2/24\n\
+            T-5\n\
+            SFood\n\
+            $-2.x\n\
+            SHousehold\n\
+            $-3\n\
+            ^\n\
+            D1/3/24\n\
+            T-5\n\
+            SFood\n\
+            $-2\n\
+            ^\n\
+            D1/4/24\n\
+            T-1\n\
+            LFood\n\
+            ^\n";
+        let import = parse_qif(input, account, resolve);
+        assert!(
+            matches!(
+                &import.errors[..],
+                [
+                    ImportError::InvalidDate { line: 2, .. },
+                    ImportError::InvalidAmount { line: 9, .. },
+                    ImportError::UnbalancedSplits { line: 13 },
+                ]
+            ),
+            "{:?}",
+            import.errors
+        );
+        let [imported] = &import.transactions[..] else {
+            panic!("{:?}", import.transactions);
+        };
+        assert_eq!(imported.transaction.date, date!(2024 - 01 - 04));
+    }
+}
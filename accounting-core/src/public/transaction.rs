@@ -1,10 +1,25 @@
+use std::{cmp::Ordering, collections::BTreeMap};
+
 use serde::{Deserialize, Serialize};
 use time::Date;
 
 use crate::{
-    backend::id::Id,
+    backend::{
+        id::Id,
+        patch::{Patch, Patchable},
+        query::{
+            builder::QueryBuilder,
+            schema::{FieldSchema, Operator, QuerySchema, ValueType},
+            GroupBy, Query, QueryValidationError, Queryable, SimpleQuery, SortField, StringQuery,
+            TextField,
+        },
+        user::ChangeGroup,
+    },
     map::Map,
-    public::{account::Account, amount::Amount},
+    public::{
+        account::Account,
+        amount::{Amount, Currency},
+    },
 };
 
 #[derive(Clone, Debug, Deserialize, Serialize)]
@@ -14,3 +29,344 @@ pub struct Transaction {
     pub description: String,
     pub amounts: Map<Id<Account>, Amount>,
 }
+
+impl Transaction {
+    /// Returns whether the debits and credits of the transaction sum to zero.
+    ///
+    /// A transaction with no amounts is balanced.
+    pub fn is_balanced(&self) -> bool {
+        Amount::sum_is_zero(self.amounts.values().copied())
+    }
+
+    /// Returns whether the debits and credits of the transaction in each currency sum to zero,
+    /// with the currency of each account given by `currency_of`.
+    pub fn is_balanced_by_currency(
+        &self,
+        mut currency_of: impl FnMut(Id<Account>) -> Option<Currency>,
+    ) -> bool {
+        let mut by_currency = BTreeMap::<_, Vec<Amount>>::new();
+        for (&account, &amount) in self.amounts.iter() {
+            by_currency
+                .entry(currency_of(account))
+                .or_default()
+                .push(amount);
+        }
+        by_currency.into_values().all(Amount::sum_is_zero)
+    }
+
+    /// The total of the debit amounts of the transaction, which for a balanced transaction is
+    /// also the total of its credits.
+    ///
+    /// Returns `None` if the total is out of range, which transactions are checked against when
+    /// they are created or updated.
+    pub fn total_debit(&self) -> Option<Amount> {
+        Amount::checked_sum(
+            self.amounts
+                .values()
+                .copied()
+                .filter(|amount| amount.is_debit()),
+        )
+    }
+}
+
+impl ChangeGroup for Transaction {}
+
+impl Patchable for Transaction {
+    type Patch = TransactionPatch;
+}
+
+/// A partial update to a transaction, changing only the fields which are set.
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+pub struct TransactionPatch {
+    #[serde(
+        default,
+        with = "crate::serde::option_date",
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub date: Option<Date>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub amounts: Option<Map<Id<Account>, Amount>>,
+}
+
+impl Patch<Transaction> for TransactionPatch {
+    fn apply(&self, transaction: &mut Transaction) {
+        if let Some(date) = self.date {
+            transaction.date = date;
+        }
+        if let Some(description) = &self.description {
+            transaction.description.clone_from(description);
+        }
+        if let Some(amounts) = &self.amounts {
+            transaction.amounts.clone_from(amounts);
+        }
+    }
+}
+
+impl Queryable for Transaction {
+    type Query = TransactionQuery;
+    type GroupBy = TransactionGroupBy;
+    type SortField = TransactionSortField;
+    type TextField = TransactionTextField;
+
+    fn schema() -> QuerySchema {
+        QuerySchema {
+            fields: vec![
+                FieldSchema {
+                    name: "account",
+                    value_type: ValueType::Id,
+                    operators: Operator::EQUALITY,
+                },
+                FieldSchema {
+                    name: "date",
+                    value_type: ValueType::Date,
+                    operators: Operator::ORDERED,
+                },
+                FieldSchema {
+                    name: "description",
+                    value_type: ValueType::String,
+                    operators: Operator::STRING,
+                },
+                FieldSchema {
+                    name: "total",
+                    value_type: ValueType::Amount,
+                    operators: Operator::ORDERED,
+                },
+                FieldSchema {
+                    name: "account_count",
+                    value_type: ValueType::Integer,
+                    operators: Operator::ORDERED,
+                },
+            ],
+        }
+    }
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(tag = "type", content = "value", rename_all = "snake_case")]
+pub enum TransactionQuery {
+    /// At least one of the accounts involved in the transaction matches the query.
+    Account(SimpleQuery<Id<Account>>),
+    /// Query on the date of the transaction.
+    #[serde(with = "crate::serde::date_query")]
+    Date(SimpleQuery<Date>),
+    /// Query on the description of the transaction.
+    Description(StringQuery),
+    /// Query on the total of the debit amounts of the transaction.
+    Total(SimpleQuery<Amount>),
+    /// Query on the number of accounts involved in the transaction.
+    AccountCount(SimpleQuery<usize>),
+}
+
+/// A builder for queries on transactions.
+pub type TransactionQueryBuilder = QueryBuilder<Transaction>;
+
+impl QueryBuilder<Transaction> {
+    /// Only match transactions dated from `start` to `end`, including both.
+    pub fn date_between(self, start: Date, end: Date) -> Self {
+        self.and_other(TransactionQuery::date_range(start, end))
+    }
+
+    /// Only match transactions involving the account.
+    pub fn involving(self, account: Id<Account>) -> Self {
+        self.and_other(TransactionQuery::Account(SimpleQuery::eq(account)))
+    }
+
+    /// Only match transactions whose description contains `text`.
+    pub fn description_contains(self, text: impl Into<String>) -> Self {
+        self.and_other(TransactionQuery::Description(StringQuery::contains(text)))
+    }
+
+    /// Only match transactions whose total debits match the query.
+    pub fn total(self, query: SimpleQuery<Amount>) -> Self {
+        self.and_other(TransactionQuery::Total(query))
+    }
+}
+
+impl TransactionQuery {
+    /// A query matching transactions dated from `start` to `end`, including both.
+    ///
+    /// To leave out the last day, use [`SimpleQuery::range`] with `start..end` instead.
+    pub fn date_range(start: Date, end: Date) -> Self {
+        Self::Date(SimpleQuery::date_range(start, end))
+    }
+}
+
+impl Query<Transaction> for TransactionQuery {
+    fn matches(&self, transaction: &Transaction) -> bool {
+        match self {
+            Self::Account(query) => transaction.amounts.keys().any(|id| query.matches(id)),
+            Self::Date(query) => query.matches(&transaction.date),
+            Self::Description(query) => query.matches(&transaction.description),
+            Self::Total(query) => transaction
+                .total_debit()
+                .is_some_and(|total| query.matches(&total)),
+            Self::AccountCount(query) => query.matches(&transaction.amounts.len()),
+        }
+    }
+
+    fn validate(&self) -> Result<(), QueryValidationError> {
+        match self {
+            Self::Account(query) => query.validate().map_err(|err| err.within("account")),
+            Self::Date(query) => query.validate().map_err(|err| err.within("date")),
+            Self::Description(query) => query.validate().map_err(|err| err.within("description")),
+            Self::Total(query) => {
+                query.validate().map_err(|err| err.within("total"))?;
+                // the total of the debits can't be negative
+                let negative = query.lt.is_some_and(|total| total <= Amount::ZERO)
+                    || query.le.is_some_and(|total| total < Amount::ZERO)
+                    || query.eq.is_some_and(|total| total < Amount::ZERO);
+                if negative {
+                    return Err(QueryValidationError::new(
+                        "the total of a transaction is never negative, so nothing matches",
+                    )
+                    .within("total"));
+                }
+                Ok(())
+            }
+            Self::AccountCount(query) => {
+                query.validate().map_err(|err| err.within("account_count"))
+            }
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Deserialize, Serialize)]
+pub enum TransactionGroupBy {
+    /// Group by each of the accounts involved in the transaction.
+    Account,
+}
+
+impl GroupBy<Transaction> for TransactionGroupBy {
+    type Key = Id<Account>;
+
+    fn keys(&self, transaction: &Transaction) -> Vec<Self::Key> {
+        match self {
+            Self::Account => transaction.amounts.keys().copied().collect(),
+        }
+    }
+
+    fn amounts(&self, transaction: &Transaction) -> Vec<(Self::Key, Amount)> {
+        match self {
+            Self::Account => transaction
+                .amounts
+                .iter()
+                .map(|(&account, &amount)| (account, amount))
+                .collect(),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Deserialize, Serialize)]
+pub enum TransactionSortField {
+    /// Sort by the date of the transaction.
+    Date,
+    /// Sort by the description of the transaction.
+    Description,
+}
+
+impl SortField<Transaction> for TransactionSortField {
+    fn compare(&self, a: &Transaction, b: &Transaction) -> Ordering {
+        match self {
+            Self::Date => a.date.cmp(&b.date),
+            Self::Description => a.description.cmp(&b.description),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Deserialize, Serialize)]
+pub enum TransactionTextField {
+    /// The description of the transaction.
+    Description,
+}
+
+impl TextField<Transaction> for TransactionTextField {
+    fn get<'a>(&self, transaction: &'a Transaction) -> &'a str {
+        match self {
+            Self::Description => &transaction.description,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use time::macros::date;
+
+    use super::Transaction;
+    use crate::{
+        backend::id::Id,
+        map::Map,
+        public::{
+            account::Account,
+            amount::{Amount, Currency},
+        },
+    };
+
+    /// A transaction with the given amounts, in the currencies of the returned lookup.
+    fn transaction(
+        amounts: &[(Option<&str>, i64)],
+    ) -> (Transaction, impl FnMut(Id<Account>) -> Option<Currency>) {
+        let mut map = Map::default();
+        let mut currencies = Map::default();
+        for &(currency, units) in amounts {
+            let account = Id::new_random();
+            map.insert(account, Amount::from_units(units));
+            currencies.insert(account, currency.map(|code| code.parse().unwrap()));
+        }
+        let transaction = Transaction {
+            date: date!(2024 - 01 - 01),
+            description: "Exchange".into(),
+            amounts: map,
+        };
+        (transaction, move |account| currencies[&account])
+    }
+
+    #[test]
+    fn is_balanced_by_currency() {
+        for (amounts, expected) in [
+            (&[][..], true),
+            (&[(Some("USD"), 10), (Some("USD"), -10)], true),
+            (&[(None, 10), (None, -4), (None, -6)], true),
+            (
+                &[
+                    (Some("USD"), 10),
+                    (Some("EUR"), 9),
+                    (Some("USD"), -10),
+                    (Some("EUR"), -9),
+                ],
+                true,
+            ),
+            // balanced overall, but not in each currency
+            (&[(Some("USD"), 10), (Some("EUR"), -10)], false),
+            (&[(Some("USD"), 10), (None, -10)], false),
+            (&[(Some("USD"), 10), (Some("USD"), -9)], false),
+        ] {
+            let (transaction, currency_of) = transaction(amounts);
+            assert_eq!(
+                transaction.is_balanced_by_currency(currency_of),
+                expected,
+                "{amounts:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn overflowing_amounts() {
+        let max = i64::MAX;
+        // the sum of the amounts wraps around to zero in 64 bits
+        let (wrapping, currency_of) = transaction(&[(None, max), (None, max), (None, 2)]);
+        assert!(!wrapping.is_balanced());
+        assert!(!wrapping.is_balanced_by_currency(currency_of));
+        assert_eq!(wrapping.total_debit(), None);
+
+        let (large, currency_of) =
+            transaction(&[(None, max), (None, max), (None, -max), (None, -max)]);
+        assert!(large.is_balanced());
+        assert!(large.is_balanced_by_currency(currency_of));
+        assert_eq!(large.total_debit(), None);
+
+        let (small, _) = transaction(&[(None, max), (None, -max)]);
+        assert_eq!(small.total_debit(), Some(Amount::from_units(max)));
+    }
+}
@@ -25,3 +25,149 @@ pub mod date {
         }
     }
 }
+
+/// Serialization for optional [`time::Date`]s, in the same format as [`date`].
+///
+/// Fields using this should also be marked `#[serde(default)]`, so that they can be omitted.
+pub mod option_date {
+    use serde::{Deserialize, Deserializer, Serialize, Serializer};
+    use time::Date;
+
+    #[derive(Deserialize, Serialize)]
+    #[serde(transparent)]
+    struct DateWrapper(#[serde(with = "super::date")] Date);
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<Option<Date>, D::Error> {
+        Option::<DateWrapper>::deserialize(deserializer).map(|date| date.map(|date| date.0))
+    }
+
+    pub fn serialize<S: Serializer>(date: &Option<Date>, serializer: S) -> Result<S::Ok, S::Error> {
+        date.map(DateWrapper).serialize(serializer)
+    }
+}
+
+/// Serialization for [`SimpleQuery`]s of [`time::Date`]s, with the dates in the same format as
+/// [`date`].
+pub mod date_query {
+    use serde::{Deserialize, Deserializer, Serialize, Serializer};
+    use time::Date;
+
+    use crate::backend::query::SimpleQuery;
+
+    #[derive(Deserialize, Serialize)]
+    #[serde(transparent)]
+    struct DateWrapper(#[serde(with = "super::date")] Date);
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<SimpleQuery<Date>, D::Error> {
+        SimpleQuery::<DateWrapper>::deserialize(deserializer).map(|query| query.map(|date| date.0))
+    }
+
+    pub fn serialize<S: Serializer>(
+        query: &SimpleQuery<Date>,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        query.clone().map(DateWrapper).serialize(serializer)
+    }
+}
+
+/// Deserialization for doubly optional fields, distinguishing a field which is `null`, as
+/// `Some(None)`, from a field which is missing, as `None`.
+///
+/// Fields using this should also be marked `#[serde(default)]`, so that they can be omitted.
+pub mod double_option {
+    use serde::{Deserialize, Deserializer};
+
+    pub fn deserialize<'de, D, T>(deserializer: D) -> Result<Option<Option<T>>, D::Error>
+    where
+        D: Deserializer<'de>,
+        T: Deserialize<'de>,
+    {
+        Option::<T>::deserialize(deserializer).map(Some)
+    }
+}
+
+/// Serialization for doubly optional [`time::Date`]s, in the same format as [`date`], with `null`
+/// distinguished from a missing field as in [`double_option`].
+///
+/// Fields using this should also be marked `#[serde(default, skip_serializing_if =
+/// "Option::is_none")]`, so that they can be omitted.
+pub mod double_option_date {
+    use serde::{Deserializer, Serializer};
+    use time::Date;
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<Option<Option<Date>>, D::Error> {
+        super::option_date::deserialize(deserializer).map(Some)
+    }
+
+    pub fn serialize<S: Serializer>(
+        date: &Option<Option<Date>>,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        super::option_date::serialize(&date.flatten(), serializer)
+    }
+}
+
+/// Serialization for [`time::OffsetDateTime`] that uses BSON's datetime format for
+/// non-human-readable formats, and RFC 3339 format for human-readable formats.
+///
+/// BSON datetimes have millisecond precision, so any finer precision is lost in non-human-readable
+/// formats.
+pub mod datetime {
+    use bson::DateTime;
+    use serde::{Deserialize, Deserializer, Serialize, Serializer};
+    use time::{serde::rfc3339, OffsetDateTime};
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<OffsetDateTime, D::Error> {
+        if deserializer.is_human_readable() {
+            rfc3339::deserialize(deserializer)
+        } else {
+            DateTime::deserialize(deserializer).map(OffsetDateTime::from)
+        }
+    }
+
+    pub fn serialize<S: Serializer>(
+        datetime: &OffsetDateTime,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        if serializer.is_human_readable() {
+            rfc3339::serialize(datetime, serializer)
+        } else {
+            DateTime::from(*datetime).serialize(serializer)
+        }
+    }
+}
+
+/// Serialization for [`SimpleQuery`]s of [`time::OffsetDateTime`]s, with the datetimes in the same
+/// format as [`datetime`].
+pub mod datetime_query {
+    use serde::{Deserialize, Deserializer, Serialize, Serializer};
+    use time::OffsetDateTime;
+
+    use crate::backend::query::SimpleQuery;
+
+    #[derive(Deserialize, Serialize)]
+    #[serde(transparent)]
+    struct DateTimeWrapper(#[serde(with = "super::datetime")] OffsetDateTime);
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<SimpleQuery<OffsetDateTime>, D::Error> {
+        SimpleQuery::<DateTimeWrapper>::deserialize(deserializer)
+            .map(|query| query.map(|datetime| datetime.0))
+    }
+
+    pub fn serialize<S: Serializer>(
+        query: &SimpleQuery<OffsetDateTime>,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        query.clone().map(DateTimeWrapper).serialize(serializer)
+    }
+}
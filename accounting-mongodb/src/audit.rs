@@ -0,0 +1,72 @@
+//! Storage of the audit log in a MongoDB collection.
+
+use std::sync::Arc;
+
+use accounting_core::{
+    backend::{
+        audit::{AnyResource, AuditEntry, AuditLog, ResourceType},
+        id::Id,
+    },
+    error::{Error, Result},
+};
+use async_trait::async_trait;
+use futures::lock::Mutex;
+use mongodb::{options::FindOptions, ClientSession};
+
+use crate::collection::find_all_in;
+
+/// An audit log stored in a MongoDB collection.
+///
+/// Entries are returned in the order of their `_id`s, which are generated by the driver when they
+/// are inserted, and so increase over time.
+pub struct MongoDbAuditLog {
+    collection: mongodb::Collection<AuditEntry>,
+    session: Option<Arc<Mutex<ClientSession>>>,
+}
+
+impl MongoDbAuditLog {
+    /// Open the collection with the given name in the database.
+    pub fn new(db: &mongodb::Database, name: &str) -> Self {
+        Self {
+            collection: db.collection(name),
+            session: None,
+        }
+    }
+
+    /// Perform all operations within the session.
+    pub(crate) fn with_session(self, session: Arc<Mutex<ClientSession>>) -> Self {
+        Self {
+            session: Some(session),
+            ..self
+        }
+    }
+}
+
+#[async_trait]
+impl AuditLog for MongoDbAuditLog {
+    async fn record(&mut self, entry: AuditEntry) -> Result<()> {
+        match &self.session {
+            Some(session) => {
+                self.collection
+                    .insert_one_with_session(&entry, None, &mut *session.lock().await)
+                    .await
+            }
+            None => self.collection.insert_one(&entry, None).await,
+        }
+        .map_err(Error::backend)?;
+        Ok(())
+    }
+
+    async fn trail(
+        &self,
+        resource_type: ResourceType,
+        resource_id: Id<AnyResource>,
+    ) -> Result<Vec<AuditEntry>> {
+        let filter = bson::doc! {
+            "resource_type": bson::to_bson(&resource_type).map_err(Error::backend)?,
+            "resource_id": resource_id,
+        };
+        let options = FindOptions::builder().sort(bson::doc! { "_id": 1 }).build();
+        find_all_in(&self.collection, self.session.as_ref(), filter, options).await
+    }
+}
@@ -1,85 +1,660 @@
 use accounting_core::{
     backend::{
-        collection::Collection,
+        collection::{ChangeEvent, Collection, CollectionStats, MAX_DISTINCT_VALUES},
         id::Id,
+        patch::{Patch, Patchable},
+        query::{
+            boolean::BooleanExpr, normalize, GroupKey, Pagination, QueryOptions, Queryable, Sort,
+            WithGroupQuery,
+        },
         user::{ChangeGroup, Group, WithGroup},
         version::{Version, Versioned},
     },
     error::{Error, Result},
+    public::amount::Amount,
 };
+use std::{borrow::Cow, collections::BTreeMap, sync::Arc};
+
 use async_trait::async_trait;
-use serde::{de::DeserializeOwned, Serialize};
+use futures::{
+    lock::Mutex,
+    stream::{self, BoxStream},
+    StreamExt, TryStreamExt,
+};
+use mongodb::{
+    change_stream::event::{ChangeStreamEvent, OperationType},
+    error::{CommandError, ErrorKind},
+    options::{
+        AggregateOptions, ChangeStreamOptions, CountOptions, FindOneAndUpdateOptions,
+        FindOneOptions, FindOptions, FullDocumentBeforeChangeType, FullDocumentType, Hint,
+        ReturnDocument,
+    },
+    ClientSession,
+};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use time::OffsetDateTime;
+
+use crate::query::{
+    prefix_fields, query_to_document, sort_to_document, ToDerivedFields, ToDocument, ToGroupStages,
+    ToSortField, ToTextField,
+};
+
+/// Run an operation on a MongoDB collection, within the collection's session if it has one.
+macro_rules! with_session {
+    ($self:ident, $collection:expr, $method:ident, $method_with_session:ident($($arg:expr),* $(,)?) $(,)?) => {
+        match &$self.session {
+            Some(session) => {
+                $collection
+                    .$method_with_session($($arg,)* &mut *session.lock().await)
+                    .await
+            }
+            None => $collection.$method($($arg),*).await,
+        }
+    };
+}
+
+/// Find all documents in a MongoDB collection matching the filter, and collect them, within the
+/// session if there is one.
+pub(crate) async fn find_all_in<D>(
+    collection: &mongodb::Collection<D>,
+    session: Option<&Arc<Mutex<ClientSession>>>,
+    filter: bson::Document,
+    options: impl Into<Option<FindOptions>>,
+) -> Result<Vec<D>>
+where
+    D: DeserializeOwned + Send + Sync + Unpin,
+{
+    match session {
+        Some(session) => {
+            let mut session = session.lock().await;
+            let mut cursor = collection
+                .find_with_session(filter, options, &mut session)
+                .await
+                .map_err(query_error)?;
+            cursor
+                .stream(&mut session)
+                .try_collect()
+                .await
+                .map_err(query_error)
+        }
+        None => {
+            let cursor = collection
+                .find(filter, options)
+                .await
+                .map_err(query_error)?;
+            cursor.try_collect().await.map_err(query_error)
+        }
+    }
+}
 
 pub struct MongoDbCollection<T> {
+    database: mongodb::Database,
     collection: mongodb::Collection<WithGroup<Versioned<T>>>,
+    history: Option<mongodb::Collection<HistoryEntry<T>>>,
+    session: Option<Arc<Mutex<ClientSession>>>,
+}
+
+impl<T> MongoDbCollection<T> {
+    /// Open the collection with the given name in the database.
+    pub fn new(db: &mongodb::Database, name: &str) -> Self {
+        Self {
+            database: db.clone(),
+            collection: db.collection(name),
+            history: None,
+            session: None,
+        }
+    }
+
+    /// Record the previous versions of updated and deleted objects in the collection with the
+    /// given name in the database.
+    pub fn with_history(self, db: &mongodb::Database, name: &str) -> Self {
+        Self {
+            history: Some(db.collection(name)),
+            ..self
+        }
+    }
+
+    /// Perform all operations within the session.
+    ///
+    /// Change streams can't be opened within a transaction, so `watch` ignores the session.
+    pub(crate) fn with_session(self, session: Arc<Mutex<ClientSession>>) -> Self {
+        Self {
+            session: Some(session),
+            ..self
+        }
+    }
+
+    /// Open the collection with the given name in the named database.
+    pub fn from_client(client: &mongodb::Client, db: &str, name: &str) -> Self {
+        Self::new(&client.database(db), name)
+    }
+}
+
+impl<T> MongoDbCollection<T>
+where
+    T: Serialize + DeserializeOwned + Send + Sync + Unpin,
+{
+    /// Find all documents matching the filter, and collect them.
+    ///
+    /// Documents are decoded by the driver's raw deserializer, which is non-human-readable, to
+    /// match how they were inserted.
+    async fn find_all(
+        &self,
+        filter: bson::Document,
+        options: impl Into<Option<FindOptions>>,
+    ) -> Result<Vec<WithGroup<Versioned<T>>>> {
+        self.find_all_in(&self.collection, filter, options).await
+    }
+
+    /// Find all documents in a MongoDB collection matching the filter, and collect them, within
+    /// the session if there is one.
+    async fn find_all_in<D>(
+        &self,
+        collection: &mongodb::Collection<D>,
+        filter: bson::Document,
+        options: impl Into<Option<FindOptions>>,
+    ) -> Result<Vec<D>>
+    where
+        D: DeserializeOwned + Send + Sync + Unpin,
+    {
+        find_all_in(collection, self.session.as_ref(), filter, options).await
+    }
+
+    /// Get the plan MongoDB would use to find the documents matching the filter, without running
+    /// the query.
+    async fn explain_filter(&self, filter: bson::Document) -> Result<bson::Document> {
+        let command = bson::doc! {
+            "explain": { "find": self.collection.name(), "filter": filter },
+            "verbosity": "queryPlanner",
+        };
+        with_session!(
+            self,
+            self.database,
+            run_command,
+            run_command_with_session(command, None),
+        )
+        .map_err(Error::backend)
+    }
+
+    /// Check that finding the documents matching the filter is within the query's `max_cost`.
+    ///
+    /// MongoDB's planner doesn't estimate how many documents a plan examines, so the estimate is
+    /// the sum of the documents examined by each of the plan's [`Scan`]s. Aggregations are checked
+    /// by the plan for their initial `$match`.
+    async fn check_cost(&self, filter: &bson::Document, options: QueryOptions) -> Result<()> {
+        let Some(limit) = options.max_cost else {
+            return Ok(());
+        };
+        let explain = self.explain_filter(filter.clone()).await?;
+        let winning_plan = explain
+            .get_document("queryPlanner")
+            .and_then(|planner| planner.get_document("winningPlan"))
+            .map_err(Error::backend)?;
+        let mut estimated = 0_u64;
+        for scan in plan_scans(winning_plan, filter) {
+            estimated = estimated.saturating_add(self.estimate_scan(scan, limit).await?);
+            if estimated > limit {
+                return Err(Error::QueryTooExpensive { estimated, limit });
+            }
+        }
+        Ok(())
+    }
+
+    /// Estimate the number of documents a scan examines, counting no more than just over `limit`.
+    async fn estimate_scan(&self, scan: Scan, limit: u64) -> Result<u64> {
+        match scan {
+            Scan::Collection => self
+                .collection
+                .estimated_document_count(None)
+                .await
+                .map_err(Error::backend),
+            Scan::Index { name, filter } => {
+                // the filter only has conditions on the index's fields, so counting through the
+                // index only examines the keys within its bounds
+                let options = CountOptions::builder()
+                    .hint(Hint::Name(name))
+                    .limit(limit.saturating_add(1))
+                    .build();
+                self.collection
+                    .count_documents(filter, options)
+                    .await
+                    .map_err(Error::backend)
+            }
+        }
+    }
+
+    /// Run an aggregation pipeline, and collect its output, within the session if there is one.
+    async fn aggregate_all<D>(
+        &self,
+        pipeline: Vec<bson::Document>,
+        options: impl Into<Option<AggregateOptions>>,
+    ) -> Result<Vec<D>>
+    where
+        D: DeserializeOwned + Send + Sync + Unpin,
+    {
+        match &self.session {
+            Some(session) => {
+                let mut session = session.lock().await;
+                let mut cursor = self
+                    .collection
+                    .aggregate_with_session(pipeline, options, &mut session)
+                    .await
+                    .map_err(query_error)?
+                    .with_type::<D>();
+                cursor
+                    .stream(&mut session)
+                    .try_collect()
+                    .await
+                    .map_err(query_error)
+            }
+            None => {
+                let cursor = self
+                    .collection
+                    .aggregate(pipeline, options)
+                    .await
+                    .map_err(query_error)?;
+                cursor
+                    .with_type::<D>()
+                    .try_collect()
+                    .await
+                    .map_err(query_error)
+            }
+        }
+    }
+
+    /// Get the current version of the object with id.
+    async fn find_version(&self, id: Id<T>) -> Result<Option<Version>> {
+        let options = FindOneOptions::builder()
+            .projection(bson::doc! { VERSION_FIELD: 1 })
+            .build();
+        with_session!(
+            self,
+            self.collection.clone_with_type::<VersionOnly>(),
+            find_one,
+            find_one_with_session(query_id(id), options),
+        )
+        .map(|maybe_doc| maybe_doc.map(|doc| doc.version))
+        .map_err(Error::backend)
+    }
+
+    /// Set the fields of the object with id, if it is still at `version`, returning its new
+    /// version.
+    ///
+    /// If there are conflicting edits, this will fail with `Error::ConflictingEdit`
+    async fn set_fields(
+        &self,
+        id: Id<T>,
+        version: Version,
+        fields: bson::Document,
+    ) -> Result<Version> {
+        // cleared fields are removed rather than set to null, since missing values are stored by
+        // omitting the field
+        let (cleared, mut fields): (bson::Document, bson::Document) = fields
+            .into_iter()
+            .partition(|(_, value)| *value == bson::Bson::Null);
+        let new_version = Version::new_random();
+        fields.insert(VERSION_FIELD, new_version);
+        fields.insert(MODIFIED_FIELD, bson::DateTime::now());
+        let mut update = bson::doc! { "$set": fields };
+        if !cleared.is_empty() {
+            let cleared = cleared
+                .into_iter()
+                .map(|(field, _)| (field, bson::Bson::from("")))
+                .collect::<bson::Document>();
+            update.insert("$unset", cleared);
+        }
+        let previous = with_session!(
+            self,
+            self.collection,
+            find_one_and_update,
+            find_one_and_update_with_session(query_id_version(id, version), update, None),
+        )
+        .map_err(Error::backend)?;
+        if let Some(previous) = previous {
+            self.record_history(previous).await?;
+            Ok(new_version)
+        } else if self.find_version(id).await?.is_some() {
+            // if the id exists, its version must have changed, so this is a conflicting edit;
+            // otherwise it's just object not found
+            Err(Error::ConflictingEdit)
+        } else {
+            Err(Error::NotFound)
+        }
+    }
+
+    /// Record a previous version of an object, if history is enabled.
+    async fn record_history(&self, object: WithGroup<Versioned<T>>) -> Result<()> {
+        if let Some(history) = &self.history {
+            // the history entries are separate documents from the objects, so this isn't atomic
+            // with the change that produced them
+            let entry = HistoryEntry { object };
+            with_session!(
+                self,
+                history,
+                insert_one,
+                insert_one_with_session(&entry, None)
+            )
+            .map_err(Error::backend)?;
+        }
+        Ok(())
+    }
+}
+
+impl<T> MongoDbCollection<T>
+where
+    T: Queryable + Serialize + DeserializeOwned + Send + Sync + Unpin,
+    T::Query: ToDocument,
+{
+    /// Get the plan MongoDB would use to find the objects matching all of the queries, without
+    /// running the query, for debugging slow queries.
+    pub async fn explain(&self, query: &[WithGroupQuery<T>]) -> Result<bson::Document> {
+        self.explain_filter(query_live(query)).await
+    }
+}
+
+impl<T> MongoDbCollection<T>
+where
+    T: ToDerivedFields + Serialize + DeserializeOwned + Send + Sync + Unpin,
+{
+    /// Store the derived fields of every object in the collection, including deleted objects,
+    /// returning the number of objects updated.
+    ///
+    /// Objects are given their derived fields whenever they are written, so this is only needed
+    /// for objects written before a derived field was added. Each object is updated separately,
+    /// so objects written in the meantime may be updated again with the same fields.
+    pub async fn store_derived_fields(&self) -> Result<usize> {
+        let objects = self.find_all(bson::doc! {}, None).await?;
+        for object in &objects {
+            let fields = object.object.object.derived_fields();
+            if fields.is_empty() {
+                continue;
+            }
+            let update = bson::doc! { "$set": fields };
+            with_session!(
+                self,
+                self.collection,
+                update_one,
+                update_one_with_session(query_id(object.object.id), update, None),
+            )
+            .map_err(Error::backend)?;
+        }
+        Ok(objects.len())
+    }
 }
 
 #[async_trait]
 impl<T> Collection<T> for MongoDbCollection<T>
 where
-    T: Serialize + DeserializeOwned + Send + Sync + Unpin,
+    T: Queryable + ToDerivedFields + Serialize + DeserializeOwned + Send + Sync + Unpin + 'static,
+    T::Query: ToDocument,
+    T::GroupBy: ToGroupStages,
+    T::SortField: ToSortField,
+    T::TextField: ToTextField,
+    GroupKey<T>: DeserializeOwned + Sync + Unpin,
 {
     async fn create(&mut self, object: WithGroup<T>) -> Result<Id<T>> {
+        let now = OffsetDateTime::now_utc();
         let versioned = Versioned {
             id: Id::new_random(),
             version: Version::new_random(),
+            created_at: now,
+            modified_at: now,
             object,
         }
         .transpose();
+        let mut document = to_document(&versioned)?;
+        document.extend(versioned.object.object.derived_fields());
 
-        self.collection
-            .insert_one(&versioned, None)
-            .await
-            .map_err(Error::backend)?;
+        with_session!(
+            self,
+            self.collection.clone_with_type::<bson::Document>(),
+            insert_one,
+            insert_one_with_session(document, None),
+        )
+        .map_err(Error::backend)?;
 
         Ok(versioned.object.id)
     }
 
     async fn get(&self, id: Id<T>) -> Result<Option<WithGroup<Versioned<T>>>> {
-        self.collection
-            .find_one(Some(query_id(id)), None)
-            .await
-            .map_err(Error::backend)
+        with_session!(
+            self,
+            self.collection,
+            find_one,
+            find_one_with_session(query_id(id), None),
+        )
+        .map_err(Error::backend)
+    }
+
+    async fn exists(&self, id: Id<T>) -> Result<bool> {
+        // ids are unique, but the limit lets the server stop as soon as it finds a match
+        let options = CountOptions::builder().limit(1).build();
+        with_session!(
+            self,
+            self.collection,
+            count_documents,
+            count_documents_with_session(query_id(id), options),
+        )
+        .map(|count| count > 0)
+        .map_err(Error::backend)
     }
 
-    async fn update(&mut self, mut object: Versioned<T>) -> Result<()> {
-        let query = query_id_version(object.id, object.version);
-        object.version = Version::new_random();
-        let ser_options = bson::SerializerOptions::builder()
-            .human_readable(false)
+    async fn get_group(&self, id: Id<T>) -> Result<Option<Id<Group>>> {
+        let options = FindOneOptions::builder()
+            .projection(bson::doc! { GROUP_FIELD: 1 })
             .build();
-        let update_doc = bson::to_document_with_options(&object, ser_options)
-            .map_err(mongodb::error::Error::from)
-            .map_err(Error::backend)?;
-        let update = bson::doc! { "$set": update_doc };
-        let result = self
-            .collection
-            .update_one(query, update, None)
-            .await
-            .map_err(Error::backend)?;
-        if result.matched_count != 1 {
-            // if the id exists, this is a conflicting edit, otherwise it's just object not found
-            if self
-                .collection
-                .find_one(query_id(object.id), None)
+        with_session!(
+            self,
+            self.collection.clone_with_type::<GroupOnly>(),
+            find_one,
+            find_one_with_session(query_id(id), options),
+        )
+        .map(|maybe_doc| maybe_doc.map(|doc| doc.group))
+        .map_err(Error::backend)
+    }
+
+    async fn get_version(&self, id: Id<T>) -> Result<Option<Version>> {
+        self.find_version(id).await
+    }
+
+    async fn get_many(&self, ids: &[Id<T>]) -> Result<Vec<WithGroup<Versioned<T>>>> {
+        if ids.is_empty() {
+            return Ok(Vec::new());
+        }
+        self.find_all(query_ids(ids), None).await
+    }
+
+    async fn update(&mut self, object: Versioned<T>) -> Result<Version> {
+        // only the object's own fields are set, so the creation time is never changed by an
+        // update
+        let mut fields = to_document(&object.object)?;
+        fields.extend(object.object.derived_fields());
+        self.set_fields(object.id, object.version, fields).await
+    }
+
+    async fn patch(&mut self, id: Id<T>, version: Version, patch: T::Patch) -> Result<Version>
+    where
+        T: Patchable,
+    {
+        // patches serialize to exactly the fields they change, but the derived fields may depend on
+        // other fields, so they are computed from the patched object, which is only written if it
+        // is still at the same version
+        let current = self.get(id).await?.ok_or(Error::NotFound)?.object;
+        if current.version != version {
+            return Err(Error::ConflictingEdit);
+        }
+        let mut object = current.object;
+        patch.apply(&mut object);
+        let mut fields = to_document(&patch)?;
+        fields.extend(object.derived_fields());
+        self.set_fields(id, version, fields).await
+    }
+
+    async fn upsert(&mut self, id: Id<T>, object: WithGroup<T>) -> Result<Version> {
+        let now = OffsetDateTime::now_utc();
+        let versioned = Versioned {
+            id: id.transmute(),
+            version: Version::new_random(),
+            created_at: now,
+            modified_at: now,
+            object,
+        }
+        .transpose();
+        let mut document = to_document(&versioned)?;
+        document.extend(versioned.object.object.derived_fields());
+        // the id is set from the filter when inserting, and the creation time is kept when
+        // replacing
+        document.remove(ID_FIELD);
+        document.remove(CREATED_FIELD);
+        let update = bson::doc! {
+            "$set": document,
+            "$setOnInsert": { CREATED_FIELD: bson::DateTime::from(now) },
+            // a deleted object with the same id is replaced, which also restores it
+            "$unset": { DELETED_FIELD: "" },
+        };
+        // the document before the update is returned, to record in the history
+        let options = FindOneAndUpdateOptions::builder()
+            .upsert(true)
+            .return_document(ReturnDocument::Before)
+            .build();
+        let previous = with_session!(
+            self,
+            self.collection,
+            find_one_and_update,
+            find_one_and_update_with_session(bson::doc! { ID_FIELD: id }, update, options),
+        )
+        .map_err(Error::backend)?;
+        if let Some(previous) = previous {
+            self.record_history(previous).await?;
+        }
+        Ok(versioned.object.version)
+    }
+
+    async fn history(&self, id: Id<T>) -> Result<Vec<WithGroup<Versioned<T>>>> {
+        let Some(history) = &self.history else {
+            return Ok(Vec::new());
+        };
+        // history entries have automatically generated object ids, which increase over time
+        let options = FindOptions::builder()
+            .sort(bson::doc! { ID_FIELD: 1 })
+            .build();
+        let entries = self
+            .find_all_in(history, bson::doc! { HISTORY_ID_FIELD: id }, options)
+            .await?;
+        Ok(entries.into_iter().map(|entry| entry.object).collect())
+    }
+
+    async fn revert(&mut self, id: Id<T>, version: Version) -> Result<Version> {
+        let history = self.history.as_ref().ok_or(Error::NotFound)?;
+        let filter = bson::doc! { HISTORY_ID_FIELD: id, HISTORY_VERSION_FIELD: version };
+        let previous = with_session!(self, history, find_one, find_one_with_session(filter, None))
+            .map_err(Error::backend)?
+            .ok_or(Error::NotFound)?
+            .object;
+        match self.get(id).await? {
+            Some(current) => {
+                self.update(Versioned {
+                    object: previous.object.object,
+                    ..current.object
+                })
                 .await
-                .map_err(Error::backend)?
-                .is_some()
-            {
-                Err(Error::ConflictingEdit)
-            } else {
-                Err(Error::NotFound)
             }
+            None => {
+                let object = WithGroup {
+                    group: previous.group,
+                    object: previous.object.object,
+                };
+                self.upsert(id, object).await
+            }
+        }
+    }
+
+    async fn delete(&mut self, id: Id<T>) -> Result<()> {
+        let update_statement = bson::doc! { "$set": { DELETED_FIELD: bson::DateTime::now() } };
+        with_session!(
+            self,
+            self.collection,
+            update_one,
+            update_one_with_session(query_id(id), update_statement, None),
+        )
+        .map_err(Error::backend)?;
+        Ok(())
+    }
+
+    async fn delete_many(&mut self, query: &[WithGroupQuery<T>]) -> Result<usize>
+    where
+        T: Queryable,
+    {
+        let update_statement = bson::doc! { "$set": { DELETED_FIELD: bson::DateTime::now() } };
+        with_session!(
+            self,
+            self.collection,
+            update_many,
+            update_many_with_session(query_live(query), update_statement, None),
+        )
+        .map(|result| result.modified_count as usize)
+        .map_err(Error::backend)
+    }
+
+    async fn get_deleted(&self, id: Id<T>) -> Result<Option<WithGroup<Versioned<T>>>> {
+        with_session!(
+            self,
+            self.collection,
+            find_one,
+            find_one_with_session(query_deleted_id(id), None),
+        )
+        .map_err(Error::backend)
+    }
+
+    async fn query_deleted(
+        &self,
+        query: &[WithGroupQuery<T>],
+    ) -> Result<Vec<WithGroup<Versioned<T>>>>
+    where
+        T: Queryable,
+    {
+        let mut filter = query_to_document(query);
+        filter.insert(DELETED_FIELD, bson::doc! { "$exists": true });
+        self.find_all(filter, None).await
+    }
+
+    async fn restore(&mut self, id: Id<T>) -> Result<()> {
+        let update_statement = bson::doc! { "$unset": { DELETED_FIELD: "" } };
+        let result = with_session!(
+            self,
+            self.collection,
+            update_one,
+            update_one_with_session(query_deleted_id(id), update_statement, None),
+        )
+        .map_err(Error::backend)?;
+        if result.matched_count == 0 {
+            Err(Error::NotFound)
         } else {
             Ok(())
         }
     }
 
-    async fn delete(&mut self, id: Id<T>) -> Result<()> {
-        self.collection
-            .delete_one(query_id(id), None)
-            .await
+    async fn purge(&mut self, id: Id<T>) -> Result<()> {
+        let result = with_session!(
+            self,
+            self.collection,
+            delete_one,
+            delete_one_with_session(query_deleted_id(id), None),
+        )
+        .map_err(Error::backend)?;
+        if result.deleted_count == 0 {
+            return Err(Error::NotFound);
+        }
+        if let Some(history) = &self.history {
+            // as with recording history, this isn't atomic with the deletion of the object
+            with_session!(
+                self,
+                history,
+                delete_many,
+                delete_many_with_session(bson::doc! { HISTORY_ID_FIELD: id }, None),
+            )
             .map_err(Error::backend)?;
+        }
         Ok(())
     }
 
@@ -88,24 +663,765 @@ where
         T: ChangeGroup,
     {
         let update_statement = bson::doc! {
-            "$set": { VERSION_FIELD: Version::new_random(), GROUP_FIELD: new_group},
+            "$set": {
+                VERSION_FIELD: Version::new_random(),
+                MODIFIED_FIELD: bson::DateTime::now(),
+                GROUP_FIELD: new_group,
+            },
         };
-        self.collection
-            .update_one(query_id(id), update_statement, None)
+        with_session!(
+            self,
+            self.collection,
+            update_one,
+            update_one_with_session(query_id(id), update_statement, None),
+        )
+        .map_err(Error::backend)?;
+        Ok(())
+    }
+
+    async fn change_group_many(&mut self, ids: &[Id<T>], new_group: Id<Group>) -> Result<()>
+    where
+        T: ChangeGroup,
+    {
+        if ids.is_empty() {
+            return Ok(());
+        }
+        // versions only need to be unique per object, so all of the objects can share one
+        let update_statement = bson::doc! {
+            "$set": {
+                VERSION_FIELD: Version::new_random(),
+                MODIFIED_FIELD: bson::DateTime::now(),
+                GROUP_FIELD: new_group,
+            },
+        };
+        with_session!(
+            self,
+            self.collection,
+            update_many,
+            update_many_with_session(query_ids(ids), update_statement, None),
+        )
+        .map_err(Error::backend)?;
+        Ok(())
+    }
+
+    async fn query(
+        &self,
+        query: &[WithGroupQuery<T>],
+        options: QueryOptions,
+    ) -> Result<Vec<WithGroup<Versioned<T>>>>
+    where
+        T: Queryable,
+    {
+        let filter = query_live(query);
+        self.check_cost(&filter, options).await?;
+        let options = FindOptions::builder().max_time(options.timeout).build();
+        self.find_all(filter, options).await
+    }
+
+    async fn query_expr(
+        &self,
+        query: &BooleanExpr<WithGroupQuery<T>>,
+        options: QueryOptions,
+    ) -> Result<Vec<WithGroup<Versioned<T>>>>
+    where
+        T: Queryable,
+    {
+        let filter = query_live(std::slice::from_ref(query));
+        self.check_cost(&filter, options).await?;
+        let options = FindOptions::builder().max_time(options.timeout).build();
+        self.find_all(filter, options).await
+    }
+
+    async fn query_ids(
+        &self,
+        query: &[WithGroupQuery<T>],
+        options: QueryOptions,
+    ) -> Result<Vec<Id<T>>>
+    where
+        T: Queryable,
+    {
+        let filter = query_live(query);
+        self.check_cost(&filter, options).await?;
+        let options = FindOptions::builder()
+            .projection(bson::doc! { ID_FIELD: 1 })
+            .max_time(options.timeout)
+            .build();
+        let docs = self
+            .find_all_in(
+                &self.collection.clone_with_type::<IdOnly<T>>(),
+                filter,
+                options,
+            )
+            .await?;
+        Ok(docs.into_iter().map(|doc| doc.id).collect())
+    }
+
+    fn query_stream(
+        &self,
+        query: &[WithGroupQuery<T>],
+        options: QueryOptions,
+    ) -> BoxStream<'_, Result<WithGroup<Versioned<T>>>>
+    where
+        T: Queryable,
+    {
+        let filter = query_live(query);
+        let find_options = FindOptions::builder().max_time(options.timeout).build();
+        if self.session.is_some() {
+            // a session cursor needs the session for every batch, so collect the results up front
+            // rather than holding the session for as long as the stream is alive
+            return stream::once(async move {
+                self.check_cost(&filter, options).await?;
+                self.find_all(filter, find_options).await
+            })
+            .map_ok(|objects| stream::iter(objects.into_iter().map(Ok)))
+            .try_flatten()
+            .boxed();
+        }
+        // dropping the cursor kills it on the server and returns its connection to the pool
+        stream::once(async move {
+            self.check_cost(&filter, options).await?;
+            self.collection
+                .find(filter, find_options)
+                .await
+                .map_err(query_error)
+        })
+        .map_ok(|cursor| cursor.map_err(query_error))
+        .try_flatten()
+        .boxed()
+    }
+
+    async fn watch(
+        &self,
+        query: &[WithGroupQuery<T>],
+    ) -> Result<BoxStream<'static, Result<ChangeEvent<T>>>>
+    where
+        T: Queryable,
+    {
+        let filter = query_to_document(query);
+        // deleted documents can only be matched against their pre-image, which is only available
+        // if pre-images are enabled on the collection
+        let pipeline = [bson::doc! {
+            "$match": {
+                "operationType": { "$in": ["insert", "update", "replace", "delete"] },
+                "$or": [
+                    prefix_fields(&filter, "fullDocument"),
+                    prefix_fields(&filter, "fullDocumentBeforeChange"),
+                ],
+                // purging an object which was already reported as deleted
+                "$nor": [{
+                    "operationType": "delete",
+                    format!("fullDocumentBeforeChange.{DELETED_FIELD}"): { "$exists": true },
+                }],
+            },
+        }];
+        let options = ChangeStreamOptions::builder()
+            .full_document(Some(FullDocumentType::UpdateLookup))
+            .full_document_before_change(Some(FullDocumentBeforeChangeType::WhenAvailable))
+            .build();
+        let stream = self
+            .collection
+            .watch(pipeline, options)
             .await
             .map_err(Error::backend)?;
-        Ok(())
+        Ok(stream
+            .map_err(Error::backend)
+            .try_filter_map(|event| async move { change_event(event) })
+            .boxed())
+    }
+
+    async fn query_sorted(
+        &self,
+        query: &[WithGroupQuery<T>],
+        sort: &[Sort<T>],
+        options: QueryOptions,
+    ) -> Result<Vec<WithGroup<Versioned<T>>>>
+    where
+        T: Queryable,
+    {
+        let filter = query_live(query);
+        self.check_cost(&filter, options).await?;
+        let options = FindOptions::builder()
+            .sort(sort_to_document(sort))
+            .max_time(options.timeout)
+            .build();
+        self.find_all(filter, options).await
+    }
+
+    async fn query_paginated(
+        &self,
+        query: &[WithGroupQuery<T>],
+        sort: &[Sort<T>],
+        pagination: Pagination,
+        options: QueryOptions,
+    ) -> Result<Vec<WithGroup<Versioned<T>>>>
+    where
+        T: Queryable,
+    {
+        // a limit of 0 means no limit to MongoDB
+        if pagination.limit == 0 {
+            return Ok(Vec::new());
+        }
+        let filter = query_live(query);
+        self.check_cost(&filter, options).await?;
+        // the sort ends with the id, so that pages are consistent between calls
+        let options = FindOptions::builder()
+            .sort(sort_to_document(sort))
+            .skip(u64::from(pagination.offset))
+            .limit(i64::from(pagination.limit))
+            .max_time(options.timeout)
+            .build();
+        self.find_all(filter, options).await
+    }
+
+    async fn query_count(&self, query: &[WithGroupQuery<T>], options: QueryOptions) -> Result<usize>
+    where
+        T: Queryable,
+    {
+        let filter = query_live(query);
+        self.check_cost(&filter, options).await?;
+        let options = CountOptions::builder().max_time(options.timeout).build();
+        with_session!(
+            self,
+            self.collection,
+            count_documents,
+            count_documents_with_session(filter, options),
+        )
+        .map(|count| count as usize)
+        .map_err(query_error)
+    }
+
+    async fn distinct_values(
+        &self,
+        field: T::TextField,
+        query: &[WithGroupQuery<T>],
+        options: QueryOptions,
+    ) -> Result<Vec<String>>
+    where
+        T: Queryable,
+    {
+        let filter = query_live(query);
+        self.check_cost(&filter, options).await?;
+        // the `distinct` command can't be limited, so group by the field instead
+        let pipeline = vec![
+            bson::doc! { "$match": filter },
+            bson::doc! { "$group": { ID_FIELD: format!("${}", field.field_name()) } },
+            bson::doc! { "$sort": { ID_FIELD: 1 } },
+            bson::doc! { "$limit": MAX_DISTINCT_VALUES as i64 },
+        ];
+        let options = AggregateOptions::builder()
+            .max_time(options.timeout)
+            .build();
+        let values = self
+            .aggregate_all::<DistinctValue>(pipeline, options)
+            .await?;
+        Ok(values.into_iter().map(|value| value.value).collect())
+    }
+
+    async fn query_count_by(
+        &self,
+        query: &[WithGroupQuery<T>],
+        group_by: T::GroupBy,
+        options: QueryOptions,
+    ) -> Result<Vec<(GroupKey<T>, usize)>>
+    where
+        T: Queryable,
+    {
+        let filter = query_live(query);
+        self.check_cost(&filter, options).await?;
+        let mut pipeline = vec![bson::doc! { "$match": filter }];
+        pipeline.extend(group_by.to_group_stages());
+        let options = AggregateOptions::builder()
+            .max_time(options.timeout)
+            .build();
+        let groups = self
+            .aggregate_all::<KeyCount<GroupKey<T>>>(pipeline, options)
+            .await?;
+        Ok(groups
+            .into_iter()
+            .map(|group| (group.key, group.count as usize))
+            .collect())
+    }
+
+    async fn sum_amounts(
+        &self,
+        query: &[WithGroupQuery<T>],
+        group_by: T::GroupBy,
+        options: QueryOptions,
+    ) -> Result<Vec<(GroupKey<T>, Amount)>>
+    where
+        T: Queryable,
+    {
+        let filter = query_live(query);
+        self.check_cost(&filter, options).await?;
+        let mut pipeline = vec![bson::doc! { "$match": filter }];
+        pipeline.extend(group_by.to_sum_stages());
+        let options = AggregateOptions::builder()
+            .max_time(options.timeout)
+            .build();
+        let groups = self
+            .aggregate_all::<KeyTotal<GroupKey<T>>>(pipeline, options)
+            .await?;
+        Ok(groups
+            .into_iter()
+            .map(|group| (group.key, group.total))
+            .collect())
+    }
+
+    async fn stats(&self) -> Result<CollectionStats> {
+        let count = with_session!(
+            self,
+            self.collection,
+            count_documents,
+            count_documents_with_session(bson::doc! { DELETED_FIELD: { "$exists": false } }, None),
+        )
+        .map_err(Error::backend)?;
+        let deleted_count = with_session!(
+            self,
+            self.collection,
+            count_documents,
+            count_documents_with_session(bson::doc! { DELETED_FIELD: { "$exists": true } }, None),
+        )
+        .map_err(Error::backend)?;
+        let pipeline = vec![bson::doc! {
+            "$group": { ID_FIELD: null, "last_modified": { "$max": format!("${MODIFIED_FIELD}") } },
+        }];
+        let last_modified = self
+            .aggregate_all::<LastModified>(pipeline, None)
+            .await?
+            .into_iter()
+            .next()
+            .and_then(|doc| doc.last_modified)
+            .map(bson::DateTime::to_time_0_3);
+        // `$collStats` can't be run within a transaction, so this is always outside of the
+        // session. On a sharded cluster, there is one output document per shard.
+        let pipeline = [bson::doc! { "$collStats": { "storageStats": {} } }];
+        let shards = self
+            .collection
+            .aggregate(pipeline, None)
+            .await
+            .map_err(Error::backend)?
+            .with_type::<CollStats>()
+            .try_collect::<Vec<_>>()
+            .await
+            .map_err(Error::backend)?;
+        let mut storage_size = 0;
+        let mut index_sizes = BTreeMap::new();
+        for shard in shards {
+            storage_size += shard.storage_stats.size as u64;
+            for (name, size) in shard.storage_stats.index_sizes {
+                *index_sizes.entry(name).or_default() += size as u64;
+            }
+        }
+        Ok(CollectionStats {
+            count: count as usize,
+            deleted_count: deleted_count as usize,
+            storage_size: Some(storage_size),
+            index_sizes,
+            last_modified,
+        })
     }
 }
 
-const ID_FIELD: &str = "_id";
+pub(crate) const ID_FIELD: &str = "_id";
 const VERSION_FIELD: &str = "_version";
-const GROUP_FIELD: &str = "_group";
+pub(crate) const CREATED_FIELD: &str = "_created";
+pub(crate) const MODIFIED_FIELD: &str = "_modified";
+pub(crate) const GROUP_FIELD: &str = "_group";
+/// The time at which an object was deleted, only present on deleted objects
+const DELETED_FIELD: &str = "_deleted";
+const HISTORY_ID_FIELD: &str = "object._id";
+const HISTORY_VERSION_FIELD: &str = "object._version";
+
+/// A previous version of an object.
+#[derive(Deserialize, Serialize)]
+struct HistoryEntry<T> {
+    object: WithGroup<Versioned<T>>,
+}
+
+/// A projection of a document onto only its id.
+#[derive(Deserialize)]
+#[serde(bound = "")]
+struct IdOnly<T> {
+    #[serde(rename = "_id")]
+    id: Id<T>,
+}
+
+/// The output of an aggregation grouping by a single string field.
+#[derive(Deserialize)]
+struct DistinctValue {
+    #[serde(rename = "_id")]
+    value: String,
+}
+
+/// The output of a grouping aggregation.
+#[derive(Deserialize)]
+struct KeyCount<K> {
+    #[serde(rename = "_id")]
+    key: K,
+    count: i64,
+}
+
+/// The output of a summing aggregation.
+#[derive(Deserialize)]
+struct KeyTotal<K> {
+    #[serde(rename = "_id")]
+    key: K,
+    total: Amount,
+}
+
+/// The output of an aggregation finding the latest modification time.
+#[derive(Deserialize)]
+struct LastModified {
+    /// This is null if no documents have a modification time.
+    last_modified: Option<bson::DateTime>,
+}
+
+/// The output of a `$collStats` aggregation stage.
+#[derive(Deserialize)]
+struct CollStats {
+    #[serde(rename = "storageStats")]
+    storage_stats: StorageStats,
+}
+
+/// Storage statistics for a collection.
+///
+/// MongoDB reports these as integers or doubles depending on their magnitude, so they are
+/// deserialized as doubles.
+#[derive(Deserialize)]
+struct StorageStats {
+    /// The uncompressed size of all of the documents.
+    size: f64,
+    #[serde(rename = "indexSizes")]
+    index_sizes: BTreeMap<String, f64>,
+}
+
+/// A projection of a document onto only its version.
+#[derive(Deserialize)]
+struct VersionOnly {
+    #[serde(rename = "_version")]
+    version: Version,
+}
+
+/// A projection of a document onto only its group.
+#[derive(Deserialize)]
+struct GroupOnly {
+    #[serde(rename = "_group")]
+    group: Id<Group>,
+}
+
+/// Serialize a value to a document to be written, using the same non-human-readable
+/// representation that the driver uses when inserting documents.
+///
+/// The strings in the document are normalized, as string queries expect.
+fn to_document<S: Serialize>(value: &S) -> Result<bson::Document> {
+    // `human_readable` is deprecated in favor of per-field wrappers, but the date serialization
+    // in `accounting_core` depends on the serializer as a whole being non-human-readable.
+    #[allow(deprecated)]
+    let options = bson::SerializerOptions::builder()
+        .human_readable(false)
+        .build();
+    let mut document = bson::to_document_with_options(value, options)
+        .map_err(mongodb::error::Error::from)
+        .map_err(Error::backend)?;
+    for (_, value) in document.iter_mut() {
+        normalize_strings(value);
+    }
+    Ok(document)
+}
+
+/// Normalize all of the strings within a value.
+fn normalize_strings(value: &mut bson::Bson) {
+    match value {
+        bson::Bson::String(string) => {
+            if let Cow::Owned(normalized) = normalize(string) {
+                *string = normalized;
+            }
+        }
+        bson::Bson::Array(values) => values.iter_mut().for_each(normalize_strings),
+        bson::Bson::Document(document) => {
+            for (_, value) in document.iter_mut() {
+                normalize_strings(value);
+            }
+        }
+        _ => {}
+    }
+}
+
+/// Convert a change stream event into a `ChangeEvent`, if it is one of the kinds of events that
+/// `ChangeEvent` represents.
+fn change_event<T>(
+    event: ChangeStreamEvent<WithGroup<Versioned<T>>>,
+) -> Result<Option<ChangeEvent<T>>> {
+    Ok(match event.operation_type {
+        OperationType::Insert => event.full_document.map(|document| ChangeEvent::Created {
+            id: document.object.id,
+            version: document.object.version,
+        }),
+        // deleting and restoring objects are updates which set and unset the deleted field
+        OperationType::Update
+            if event
+                .update_description
+                .as_ref()
+                .is_some_and(|update| update.updated_fields.contains_key(DELETED_FIELD)) =>
+        {
+            event.full_document.map(|document| ChangeEvent::Deleted {
+                id: document.object.id,
+            })
+        }
+        OperationType::Update
+            if event.update_description.as_ref().is_some_and(|update| {
+                update
+                    .removed_fields
+                    .iter()
+                    .any(|field| field == DELETED_FIELD)
+            }) =>
+        {
+            event.full_document.map(|document| ChangeEvent::Created {
+                id: document.object.id,
+                version: document.object.version,
+            })
+        }
+        OperationType::Update | OperationType::Replace => {
+            event.full_document.map(|document| ChangeEvent::Updated {
+                id: document.object.id,
+                version: document.object.version,
+            })
+        }
+        OperationType::Delete => match event.document_key {
+            Some(key) => {
+                let IdOnly { id } = bson::from_document(key)
+                    .map_err(mongodb::error::Error::from)
+                    .map_err(Error::backend)?;
+                Some(ChangeEvent::Deleted { id })
+            }
+            None => None,
+        },
+        _ => None,
+    })
+}
+
+/// Convert an error from running a query, distinguishing queries which exceeded their time limit.
+fn query_error(error: mongodb::error::Error) -> Error {
+    /// The server error code for an operation which exceeded its `maxTimeMS`
+    const MAX_TIME_MS_EXPIRED: i32 = 50;
+    match &*error.kind {
+        ErrorKind::Command(CommandError {
+            code: MAX_TIME_MS_EXPIRED,
+            ..
+        }) => Error::Timeout,
+        _ => Error::backend(error),
+    }
+}
 
 fn query_id<T>(id: Id<T>) -> bson::Document {
-    bson::doc! { ID_FIELD: id }
+    bson::doc! { ID_FIELD: id, DELETED_FIELD: { "$exists": false } }
+}
+
+fn query_ids<T>(ids: &[Id<T>]) -> bson::Document {
+    bson::doc! { ID_FIELD: { "$in": ids }, DELETED_FIELD: { "$exists": false } }
 }
 
 fn query_id_version<T>(id: Id<T>, version: Version) -> bson::Document {
-    bson::doc! { ID_FIELD: id, VERSION_FIELD: version }
+    bson::doc! { ID_FIELD: id, VERSION_FIELD: version, DELETED_FIELD: { "$exists": false } }
+}
+
+fn query_deleted_id<T>(id: Id<T>) -> bson::Document {
+    bson::doc! { ID_FIELD: id, DELETED_FIELD: { "$exists": true } }
+}
+
+/// A scan of the documents in a collection, as part of a query plan.
+#[derive(Debug, PartialEq)]
+enum Scan {
+    /// A scan of every document in the collection.
+    Collection,
+    /// A scan of an index, examining the documents matching `filter`.
+    Index {
+        name: String,
+        filter: bson::Document,
+    },
+}
+
+/// Get the scans a query plan for the filter is made up of.
+///
+/// The documents an index scan examines are estimated by the conditions of the filter on the
+/// index's fields. Conditions within `$or`, `$nor` or `$expr` are left out, so an index scan
+/// bounded by one of these is estimated to examine more documents than it does. An index scan
+/// which none of the conditions apply to examines every document.
+fn plan_scans(plan: &bson::Document, filter: &bson::Document) -> Vec<Scan> {
+    fn collect(plan: &bson::Document, filter: &bson::Document, scans: &mut Vec<Scan>) {
+        match plan.get_str("stage") {
+            Ok("COLLSCAN") => scans.push(Scan::Collection),
+            Ok("IXSCAN") => {
+                let name = plan.get_str("indexName");
+                let filter = plan
+                    .get_document("keyPattern")
+                    .map(|fields| index_filter(filter, fields));
+                scans.push(match (name, filter) {
+                    (Ok(name), Ok(filter)) if !filter.is_empty() => Scan::Index {
+                        name: name.to_owned(),
+                        filter,
+                    },
+                    _ => Scan::Collection,
+                });
+            }
+            _ => {}
+        }
+        for value in plan.values() {
+            match value {
+                bson::Bson::Document(plan) => collect(plan, filter, scans),
+                bson::Bson::Array(plans) => {
+                    for plan in plans {
+                        if let bson::Bson::Document(plan) = plan {
+                            collect(plan, filter, scans);
+                        }
+                    }
+                }
+                _ => {}
+            }
+        }
+    }
+
+    let mut scans = Vec::new();
+    collect(plan, filter, &mut scans);
+    scans
+}
+
+/// Get the conditions of the filter, including those within `$and`, on any of the fields.
+fn index_filter(filter: &bson::Document, fields: &bson::Document) -> bson::Document {
+    fn collect(filter: &bson::Document, fields: &bson::Document, conditions: &mut Vec<bson::Bson>) {
+        for (key, value) in filter {
+            match value {
+                bson::Bson::Array(clauses) if key == "$and" => {
+                    for clause in clauses {
+                        if let bson::Bson::Document(clause) = clause {
+                            collect(clause, fields, conditions);
+                        }
+                    }
+                }
+                _ if fields.contains_key(key) => {
+                    conditions.push(bson::doc! { key: value.clone() }.into());
+                }
+                _ => {}
+            }
+        }
+    }
+
+    let mut conditions = Vec::new();
+    collect(filter, fields, &mut conditions);
+    if conditions.is_empty() {
+        bson::Document::new()
+    } else {
+        bson::doc! { "$and": conditions }
+    }
+}
+
+/// Build a query document matching all of the queries, excluding deleted objects.
+fn query_live<Q: ToDocument>(query: &[Q]) -> bson::Document {
+    let mut filter = query_to_document(query);
+    filter.insert(DELETED_FIELD, bson::doc! { "$exists": false });
+    filter
+}
+
+#[cfg(test)]
+mod tests {
+    use bson::doc;
+
+    use super::{plan_scans, to_document, Scan};
+
+    #[test]
+    fn to_document_normalizes_strings() {
+        #[derive(serde::Serialize)]
+        struct Stored {
+            name: &'static str,
+            tags: Vec<&'static str>,
+            nested: Vec<(u32, &'static str)>,
+        }
+        let stored = Stored {
+            name: "Cafe\u{301}",
+            tags: vec!["cre\u{300}me"],
+            nested: vec![(1, "Caf\u{e9}")],
+        };
+        assert_eq!(
+            to_document(&stored).unwrap(),
+            doc! {
+                "name": "Caf\u{e9}",
+                "tags": ["cr\u{e8}me"],
+                "nested": [[1_i64, "Caf\u{e9}"]],
+            }
+        );
+    }
+
+    #[test]
+    fn plan_scans_collection() {
+        let filter = doc! { "description": "Rent", "_deleted": { "$exists": false } };
+        let plan = doc! { "stage": "COLLSCAN", "filter": filter.clone(), "direction": "forward" };
+        assert_eq!(plan_scans(&plan, &filter), [Scan::Collection]);
+    }
+
+    #[test]
+    fn plan_scans_index() {
+        let filter = doc! {
+            "$and": [
+                { "_group": { "$in": [1_i64] } },
+                { "date": { "$gte": "2024-01-01" } },
+                { "description": { "$regex": "fee" } },
+            ],
+            "_deleted": { "$exists": false },
+        };
+        let plan = doc! {
+            "stage": "FETCH",
+            "filter": { "description": { "$regex": "fee" } },
+            "inputStage": {
+                "stage": "IXSCAN",
+                "keyPattern": { "_group": 1, "date": 1 },
+                "indexName": "_group_1_date_1",
+                "indexBounds": {
+                    "_group": ["[1, 1]"],
+                    "date": ["[\"2024-01-01\", {})"],
+                },
+            },
+        };
+        assert_eq!(
+            plan_scans(&plan, &filter),
+            [Scan::Index {
+                name: "_group_1_date_1".into(),
+                filter: doc! {
+                    "$and": [
+                        { "_group": { "$in": [1_i64] } },
+                        { "date": { "$gte": "2024-01-01" } },
+                    ],
+                },
+            }]
+        );
+    }
+
+    #[test]
+    fn plan_scans_or() {
+        // the scans bounded by the branches of an `$or` are estimated to examine every document,
+        // as are scans of indexes on fields without conditions
+        let filter = doc! {
+            "$or": [{ "date": "2024-01-01" }, { "description": "Rent" }],
+            "_deleted": { "$exists": false },
+        };
+        let plan = doc! {
+            "queryPlan": {
+                "stage": "FETCH",
+                "inputStage": {
+                    "stage": "OR",
+                    "inputStages": [
+                        { "stage": "IXSCAN", "keyPattern": { "date": 1 }, "indexName": "date_1" },
+                        {
+                            "stage": "IXSCAN",
+                            "keyPattern": { "description": 1 },
+                            "indexName": "description_1",
+                        },
+                    ],
+                },
+            },
+        };
+        assert_eq!(
+            plan_scans(&plan, &filter),
+            [Scan::Collection, Scan::Collection]
+        );
+    }
 }
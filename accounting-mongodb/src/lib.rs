@@ -1 +1,4 @@
+pub mod audit;
 pub mod collection;
+pub mod query;
+pub mod store;
@@ -0,0 +1,951 @@
+//! Conversion of queries into MongoDB query documents.
+
+use accounting_core::{
+    backend::{
+        id::Id,
+        query::{
+            boolean::{BooleanExpr, Folder},
+            fold_case, like_tokens, LikeToken, NoGroupBy, Queryable, SimpleQuery, Sort,
+            StringQuery, WithGroupQuery,
+        },
+        user::{
+            AccessLevel, Group, GroupQuery, GroupSortField, GroupTextField, Role, User, UserQuery,
+            UserSortField, UserTextField,
+        },
+    },
+    public::{
+        account::{Account, AccountQuery, AccountSortField, AccountTextField, AccountType},
+        amount::Amount,
+        transaction::{
+            Transaction, TransactionGroupBy, TransactionQuery, TransactionSortField,
+            TransactionTextField,
+        },
+    },
+};
+use bson::{doc, Bson, Document};
+use time::{Date, OffsetDateTime};
+
+use crate::collection::{CREATED_FIELD, GROUP_FIELD, ID_FIELD, MODIFIED_FIELD};
+
+/// A value which can be compared against in a query.
+pub trait ToBson {
+    /// Convert the value to BSON, using the same representation as is stored in the database.
+    fn to_bson(&self) -> Bson;
+}
+
+impl ToBson for String {
+    fn to_bson(&self) -> Bson {
+        Bson::String(self.clone())
+    }
+}
+
+impl ToBson for i64 {
+    fn to_bson(&self) -> Bson {
+        Bson::Int64(*self)
+    }
+}
+
+impl<T> ToBson for Id<T> {
+    fn to_bson(&self) -> Bson {
+        (*self).into()
+    }
+}
+
+impl ToBson for Date {
+    fn to_bson(&self) -> Bson {
+        // matches the non-human-readable serialization in `accounting_core::serde::date`
+        bson::DateTime::from(self.midnight().assume_utc()).into()
+    }
+}
+
+impl ToBson for OffsetDateTime {
+    fn to_bson(&self) -> Bson {
+        bson::DateTime::from(*self).into()
+    }
+}
+
+impl ToBson for AccessLevel {
+    fn to_bson(&self) -> Bson {
+        bson::to_bson(self).expect("`AccessLevel` serializes as a string")
+    }
+}
+
+impl ToBson for Role {
+    fn to_bson(&self) -> Bson {
+        bson::to_bson(self).expect("`Role` serializes as a string")
+    }
+}
+
+impl ToBson for AccountType {
+    fn to_bson(&self) -> Bson {
+        bson::to_bson(self).expect("`AccountType` serializes as a string")
+    }
+}
+
+/// The field holding the name of an account with its case folded, as given by [`fold_case`].
+pub(crate) const NAME_KEY_FIELD: &str = "_name_key";
+
+/// The field holding the total of the debit amounts of a transaction, in the units of an
+/// [`Amount`].
+const TOTAL_DEBIT_FIELD: &str = "_total_debit";
+
+/// The field holding the number of accounts in a transaction.
+const ACCOUNT_COUNT_FIELD: &str = "_account_count";
+
+/// An object with fields computed from its own fields, which are stored alongside them, so that
+/// queries can compare against them directly and indexes can cover them.
+pub trait ToDerivedFields {
+    /// The derived fields of the object.
+    fn derived_fields(&self) -> Document;
+}
+
+impl ToDerivedFields for User {
+    fn derived_fields(&self) -> Document {
+        Document::new()
+    }
+}
+
+impl ToDerivedFields for Group {
+    fn derived_fields(&self) -> Document {
+        Document::new()
+    }
+}
+
+impl ToDerivedFields for Account {
+    fn derived_fields(&self) -> Document {
+        doc! { NAME_KEY_FIELD: fold_case(&self.name) }
+    }
+}
+
+impl ToDerivedFields for Transaction {
+    fn derived_fields(&self) -> Document {
+        let mut fields = doc! { ACCOUNT_COUNT_FIELD: account_count(self.amounts.len()) };
+        // the backend rejects transactions whose amounts are too large to total, so the total is
+        // only missing from transactions stored directly in the collection, which no total
+        // matches, as in memory
+        if let Some(total) = self.total_debit() {
+            fields.insert(TOTAL_DEBIT_FIELD, total.to_units());
+        }
+        fields
+    }
+}
+
+/// A query which can be converted into a MongoDB query document.
+pub trait ToDocument {
+    fn to_document(&self) -> Document;
+}
+
+impl<T: ToBson> ToDocument for SimpleQuery<T> {
+    fn to_document(&self) -> Document {
+        let mut document = Document::new();
+        let operators = [
+            ("$eq", &self.eq),
+            ("$ne", &self.ne),
+            ("$gt", &self.gt),
+            ("$lt", &self.lt),
+            ("$gte", &self.ge),
+            ("$lte", &self.le),
+        ];
+        for (operator, value) in operators {
+            if let Some(value) = value {
+                document.insert(operator, value.to_bson());
+            }
+        }
+        for (operator, values) in [("$in", &self.in_), ("$nin", &self.nin)] {
+            if let Some(values) = values {
+                document.insert(operator, values.iter().map(T::to_bson).collect::<Vec<_>>());
+            }
+        }
+        match self.is_null {
+            Some(is_null) => {
+                document.insert("$exists", !is_null);
+            }
+            // unlike the other comparisons, `$ne` and `$nin` match missing fields
+            None if self.ne.is_some() || self.nin.is_some() => {
+                document.insert("$exists", true);
+            }
+            None => {}
+        }
+        document
+    }
+}
+
+impl ToDocument for StringQuery {
+    fn to_document(&self) -> Document {
+        // stored strings are normalized when they are written, so the values are normalized to
+        // match them
+        let query = self.normalized();
+        // regular expressions the value must match, which are matched with `$all`, since `$regex`
+        // may be needed for `like`
+        let mut required = Vec::new();
+        let mut document = if query.ignore_case {
+            let (equality, ordering) = query.equality_and_ordering();
+            let mut document = ordering.to_document();
+            if let Some(value) = &equality.eq {
+                required.push(exact_regex(value));
+            }
+            if let Some(values) = &equality.in_ {
+                document.insert(
+                    "$in",
+                    values
+                        .iter()
+                        .map(|value| exact_regex(value))
+                        .collect::<Vec<_>>(),
+                );
+            }
+            let excluded = equality
+                .ne
+                .iter()
+                .chain(equality.nin.iter().flatten())
+                .map(|value| exact_regex(value))
+                .collect::<Vec<_>>();
+            if !excluded.is_empty() {
+                document.insert("$nin", excluded);
+            }
+            document
+        } else {
+            query.simple.to_document()
+        };
+        if let Some(value) = &query.contains {
+            required.push(literal_regex("", value, "", query.ignore_case));
+        }
+        if let Some(prefix) = &query.starts_with {
+            // a case-sensitive regex anchored at the start can use an index
+            required.push(literal_regex("^", prefix, "", query.ignore_case));
+        }
+        if let Some(regex) = &query.regex {
+            required.push(Bson::RegularExpression(bson::Regex {
+                pattern: regex.as_str().to_owned(),
+                options: if query.ignore_case { "i" } else { "" }.into(),
+            }));
+        }
+        if !required.is_empty() {
+            document.insert("$all", required);
+        }
+        if let Some(pattern) = &query.like {
+            document.insert("$regex", like_to_regex(pattern));
+            // `s` lets `.` match newlines, as wildcards do in `like` patterns
+            document.insert("$options", "is");
+        }
+        document
+    }
+}
+
+/// Convert a `like` pattern into an equivalent regular expression.
+fn like_to_regex(pattern: &str) -> String {
+    let mut regex = String::from("^");
+    for token in like_tokens(pattern) {
+        match token {
+            LikeToken::AnySequence => regex.push_str(".*"),
+            LikeToken::Any => regex.push('.'),
+            LikeToken::Literal(c) => push_escaped(&mut regex, c),
+        }
+    }
+    // `$` would also match before a trailing newline
+    regex.push_str("\\z");
+    regex
+}
+
+/// Build a regular expression matching exactly `value`, ignoring case.
+fn exact_regex(value: &str) -> Bson {
+    literal_regex("^", value, "\\z", true)
+}
+
+/// Build a regular expression matching `value` literally, between `prefix` and `suffix`.
+fn literal_regex(prefix: &str, value: &str, suffix: &str, ignore_case: bool) -> Bson {
+    let mut pattern = String::from(prefix);
+    for c in value.chars() {
+        push_escaped(&mut pattern, c);
+    }
+    pattern.push_str(suffix);
+    let options = if ignore_case { "i" } else { "" };
+    Bson::RegularExpression(bson::Regex {
+        pattern,
+        options: options.into(),
+    })
+}
+
+/// Append a character to a regular expression, escaping it if it is a metacharacter.
+fn push_escaped(regex: &mut String, c: char) {
+    if "\\^$.|?*+()[]{}".contains(c) {
+        regex.push('\\');
+    }
+    regex.push(c);
+}
+
+/// Build a query document matching `field` against `query`.
+fn field_query<Q: ToDocument>(field: &str, query: &Q) -> Document {
+    let document = query.to_document();
+    // an empty operator document would only match fields which are themselves empty documents
+    if document.is_empty() {
+        document
+    } else {
+        doc! { field: document }
+    }
+}
+
+impl<T> ToDocument for WithGroupQuery<T>
+where
+    T: Queryable,
+    T::Query: ToDocument,
+{
+    fn to_document(&self) -> Document {
+        match self {
+            Self::Group(groups) => doc! { GROUP_FIELD: { "$in": groups.clone() } },
+            Self::Ids(ids) => doc! { ID_FIELD: { "$in": ids.clone() } },
+            Self::CreatedAt(query) => field_query(CREATED_FIELD, query),
+            Self::ModifiedAt(query) => field_query(MODIFIED_FIELD, query),
+            Self::Other(query) => query.to_document(),
+            Self::Not(query) => doc! { "$nor": [query.to_document()] },
+        }
+    }
+}
+
+impl ToDocument for AccountQuery {
+    fn to_document(&self) -> Document {
+        match self {
+            Self::Name(query) => field_query("name", query),
+            Self::NameExact(name) => doc! { NAME_KEY_FIELD: fold_case(name) },
+            Self::Description(query) => field_query("description", query),
+            Self::AccountType(query) => field_query("account_type", query),
+        }
+    }
+}
+
+impl ToDocument for TransactionQuery {
+    fn to_document(&self) -> Document {
+        match self {
+            // `amounts` is stored as an array of `[account, amount]` pairs
+            Self::Account(query) => doc! {
+                "amounts": { "$elemMatch": field_query("0", query) },
+            },
+            Self::Date(query) => field_query("date", query),
+            Self::Description(query) => field_query("description", query),
+            Self::Total(query) => {
+                field_query(TOTAL_DEBIT_FIELD, &query.clone().map(Amount::to_units))
+            }
+            Self::AccountCount(query) => {
+                field_query(ACCOUNT_COUNT_FIELD, &query.clone().map(account_count))
+            }
+        }
+    }
+}
+
+/// The number of accounts in a transaction, as stored in [`ACCOUNT_COUNT_FIELD`].
+fn account_count(count: usize) -> i64 {
+    // no transaction has close to `i64::MAX` accounts, so saturating is harmless
+    i64::try_from(count).unwrap_or(i64::MAX)
+}
+
+impl ToDocument for GroupQuery {
+    fn to_document(&self) -> Document {
+        match self {
+            Self::Name(query) => field_query("name", query),
+            // `permissions.users` is stored as an array of `[user, access level]` pairs
+            Self::UserAny(users) => doc! {
+                "permissions.users": { "$elemMatch": { "0": { "$in": users.clone() } } },
+            },
+            Self::UserPerm(user, query) => {
+                let mut pair = doc! { "0": *user };
+                pair.extend(field_query("1", query));
+                doc! { "permissions.users": { "$elemMatch": pair } }
+            }
+            // `permissions.roles` is stored as an array of `[user, role]` pairs
+            Self::UserRole(user, query) => {
+                let mut pair = doc! { "0": *user };
+                pair.extend(field_query("1", query));
+                doc! { "permissions.roles": { "$elemMatch": pair } }
+            }
+            Self::Parent(query) => field_query("parent", query),
+            Self::DefaultAccess(query) => field_query("permissions.default", query),
+            Self::UserCanRead(user, level) => {
+                let levels = AccessLevel::ALL
+                    .into_iter()
+                    .filter(|candidate| candidate >= level)
+                    .map(|level| level.to_bson())
+                    .collect::<Vec<_>>();
+                let roles = Role::ALL
+                    .into_iter()
+                    .filter(|role| role.access_level() >= *level)
+                    .map(|role| role.to_bson())
+                    .collect::<Vec<_>>();
+                doc! {
+                    "$or": [
+                        {
+                            "permissions.users": {
+                                "$elemMatch": { "0": *user, "1": { "$in": levels.clone() } },
+                            },
+                        },
+                        {
+                            "permissions.roles": {
+                                "$elemMatch": { "0": *user, "1": { "$in": roles } },
+                            },
+                        },
+                        // the default only applies to users with neither a role nor an explicit
+                        // access level
+                        {
+                            "$nor": [
+                                { "permissions.users": { "$elemMatch": { "0": *user } } },
+                                { "permissions.roles": { "$elemMatch": { "0": *user } } },
+                            ],
+                            "permissions.default": { "$in": levels },
+                        },
+                    ],
+                }
+            }
+        }
+    }
+}
+
+impl ToDocument for UserQuery {
+    fn to_document(&self) -> Document {
+        match self {
+            Self::Name(query) => field_query("name", query),
+            Self::Superuser(is_superuser) => doc! { "is_superuser": *is_superuser },
+        }
+    }
+}
+
+/// Rewrite a query document so that it matches against the subdocument at `prefix`.
+///
+/// Field paths are prefixed both where they are keys of the query document, and where they are
+/// `$`-prefixed operands of aggregation expressions, in `$expr`.
+pub(crate) fn prefix_fields(document: &Document, prefix: &str) -> Document {
+    document
+        .iter()
+        .map(|(key, value)| match (key.as_str(), value) {
+            ("$and" | "$or" | "$nor", Bson::Array(clauses)) => {
+                let clauses = clauses
+                    .iter()
+                    .map(|clause| match clause {
+                        Bson::Document(clause) => Bson::Document(prefix_fields(clause, prefix)),
+                        clause => clause.clone(),
+                    })
+                    .collect();
+                (key.clone(), Bson::Array(clauses))
+            }
+            ("$expr", expr) => (key.clone(), prefix_expr_fields(expr, prefix)),
+            _ => (format!("{prefix}.{key}"), value.clone()),
+        })
+        .collect()
+}
+
+/// Rewrite an aggregation expression so that its field paths refer to the subdocument at
+/// `prefix`.
+///
+/// Field paths are strings starting with a single `$`; variables, starting with `$$`, and the
+/// contents of `$literal` are left unchanged.
+fn prefix_expr_fields(expr: &Bson, prefix: &str) -> Bson {
+    match expr {
+        Bson::String(path) if path.starts_with('$') && !path.starts_with("$$") => {
+            Bson::String(format!("${prefix}.{}", &path[1..]))
+        }
+        Bson::Array(items) => Bson::Array(
+            items
+                .iter()
+                .map(|item| prefix_expr_fields(item, prefix))
+                .collect(),
+        ),
+        Bson::Document(document) => Bson::Document(
+            document
+                .iter()
+                .map(|(key, value)| match key.as_str() {
+                    "$literal" => (key.clone(), value.clone()),
+                    _ => (key.clone(), prefix_expr_fields(value, prefix)),
+                })
+                .collect(),
+        ),
+        _ => expr.clone(),
+    }
+}
+
+/// Build a query document matching all of the queries.
+pub fn query_to_document<Q: ToDocument>(query: &[Q]) -> Document {
+    if query.is_empty() {
+        Document::new()
+    } else {
+        let clauses = query.iter().map(Q::to_document).collect::<Vec<_>>();
+        doc! { "$and": clauses }
+    }
+}
+
+impl<Q: ToDocument> ToDocument for BooleanExpr<Q> {
+    fn to_document(&self) -> Document {
+        self.fold(&mut DocumentFolder)
+    }
+}
+
+/// Folds a [`BooleanExpr`] into a query document.
+struct DocumentFolder;
+
+impl<Q: ToDocument> Folder<Q, Document> for DocumentFolder {
+    fn query(&mut self, query: &Q) -> Document {
+        query.to_document()
+    }
+
+    fn all(&mut self, clauses: Vec<Document>) -> Document {
+        if clauses.is_empty() {
+            Document::new()
+        } else {
+            doc! { "$and": clauses }
+        }
+    }
+
+    fn any(&mut self, clauses: Vec<Document>) -> Document {
+        // MongoDB rejects an empty `$or`
+        if clauses.is_empty() {
+            doc! { "$expr": false }
+        } else {
+            doc! { "$or": clauses }
+        }
+    }
+
+    fn not(&mut self, clause: Document) -> Document {
+        doc! { "$nor": [clause] }
+    }
+}
+
+/// A field which can be grouped by in a MongoDB aggregation pipeline.
+pub trait ToGroupStages {
+    /// Build the aggregation stages which group documents by the field.
+    ///
+    /// The output documents have the value of the field as `_id`, and the number of documents in
+    /// the group as `count`.
+    fn to_group_stages(&self) -> Vec<Document>;
+
+    /// Build the aggregation stages which sum the amounts of documents grouped by the field.
+    ///
+    /// The output documents have the value of the field as `_id`, and the total amount in the
+    /// group, in the same format as a serialized `Amount`, as `total`.
+    fn to_sum_stages(&self) -> Vec<Document>;
+}
+
+impl ToGroupStages for NoGroupBy {
+    fn to_group_stages(&self) -> Vec<Document> {
+        match *self {}
+    }
+
+    fn to_sum_stages(&self) -> Vec<Document> {
+        match *self {}
+    }
+}
+
+impl ToGroupStages for TransactionGroupBy {
+    fn to_group_stages(&self) -> Vec<Document> {
+        match self {
+            Self::Account => vec![
+                doc! { "$unwind": "$amounts" },
+                doc! {
+                    "$group": {
+                        "_id": { "$arrayElemAt": ["$amounts", 0] },
+                        "count": { "$sum": 1 },
+                    },
+                },
+            ],
+        }
+    }
+
+    fn to_sum_stages(&self) -> Vec<Document> {
+        match self {
+            Self::Account => vec![
+                doc! { "$unwind": "$amounts" },
+                doc! {
+                    "$project": {
+                        "account": { "$arrayElemAt": ["$amounts", 0] },
+                        "amount": { "$arrayElemAt": ["$amounts", 1] },
+                    },
+                },
+                // amounts are stored as decimal strings; sum them as 128-bit decimals and convert
+                // back to strings, so that no precision is lost to floating point
+                doc! {
+                    "$group": {
+                        "_id": "$account",
+                        "total": { "$sum": { "$toDecimal": "$amount.value" } },
+                    },
+                },
+                doc! { "$project": { "total": { "value": { "$toString": "$total" } } } },
+            ],
+        }
+    }
+}
+
+/// A field which can be sorted by.
+pub trait ToSortField {
+    /// The name of the field in the stored document.
+    fn field_name(&self) -> &'static str;
+}
+
+impl ToSortField for AccountSortField {
+    fn field_name(&self) -> &'static str {
+        match self {
+            Self::Name => "name",
+            Self::Description => "description",
+        }
+    }
+}
+
+impl ToSortField for TransactionSortField {
+    fn field_name(&self) -> &'static str {
+        match self {
+            Self::Date => "date",
+            Self::Description => "description",
+        }
+    }
+}
+
+impl ToSortField for GroupSortField {
+    fn field_name(&self) -> &'static str {
+        match self {
+            Self::Name => "name",
+        }
+    }
+}
+
+impl ToSortField for UserSortField {
+    fn field_name(&self) -> &'static str {
+        match self {
+            Self::Name => "name",
+        }
+    }
+}
+
+/// Build a sort document ordering by each of the sorts in turn, and then by id.
+pub fn sort_to_document<T>(sort: &[Sort<T>]) -> Document
+where
+    T: Queryable,
+    T::SortField: ToSortField,
+{
+    let mut document = Document::new();
+    for sort in sort {
+        // a field can only appear once, and later sorts on it would have no effect anyway
+        let field = sort.field.field_name();
+        if !document.contains_key(field) {
+            document.insert(field, if sort.ascending { 1 } else { -1 });
+        }
+    }
+    document.insert(ID_FIELD, 1);
+    document
+}
+
+/// A string-valued field.
+pub trait ToTextField {
+    /// The name of the field in the stored document.
+    fn field_name(&self) -> &'static str;
+}
+
+impl ToTextField for AccountTextField {
+    fn field_name(&self) -> &'static str {
+        match self {
+            Self::Name => "name",
+            Self::Description => "description",
+        }
+    }
+}
+
+impl ToTextField for TransactionTextField {
+    fn field_name(&self) -> &'static str {
+        match self {
+            Self::Description => "description",
+        }
+    }
+}
+
+impl ToTextField for GroupTextField {
+    fn field_name(&self) -> &'static str {
+        match self {
+            Self::Name => "name",
+        }
+    }
+}
+
+impl ToTextField for UserTextField {
+    fn field_name(&self) -> &'static str {
+        match self {
+            Self::Name => "name",
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use accounting_core::{
+        backend::{
+            id::Id,
+            query::{boolean::BooleanExpr, SimpleQuery, StringQuery, WithGroupQuery},
+        },
+        map::Map,
+        public::{
+            account::{Account, AccountQuery, AccountType},
+            amount::Amount,
+            transaction::{Transaction, TransactionQuery},
+        },
+    };
+    use bson::{doc, Bson};
+    use time::{Date, Month};
+
+    use super::{prefix_fields, ToDerivedFields, ToDocument};
+
+    #[test]
+    fn prefix_fields_keys() {
+        let query = doc! {
+            "description": "Rent",
+            "$and": [{ "date": { "$gt": "2024-01-01" } }, { "$nor": [{ "group": 1 }] }],
+        };
+        assert_eq!(
+            prefix_fields(&query, "fullDocument"),
+            doc! {
+                "fullDocument.description": "Rent",
+                "$and": [
+                    { "fullDocument.date": { "$gt": "2024-01-01" } },
+                    { "$nor": [{ "fullDocument.group": 1 }] },
+                ],
+            }
+        );
+    }
+
+    #[test]
+    fn prefix_fields_expr() {
+        let query = doc! {
+            "$expr": {
+                "$and": [
+                    { "$gt": [{ "$size": "$amounts" }, 2] },
+                    { "$eq": [{ "$literal": "$amounts" }, "$$ROOT.amounts"] },
+                ],
+            },
+        };
+        assert_eq!(
+            prefix_fields(&query, "fullDocument"),
+            doc! {
+                "$expr": {
+                    "$and": [
+                        { "$gt": [{ "$size": "$fullDocument.amounts" }, 2] },
+                        { "$eq": [{ "$literal": "$amounts" }, "$$ROOT.amounts"] },
+                    ],
+                },
+            }
+        );
+    }
+
+    #[test]
+    fn prefix_fields_total() {
+        let query = TransactionQuery::Total(SimpleQuery::eq(Amount::ZERO)).to_document();
+        assert_eq!(
+            prefix_fields(&query, "fullDocument"),
+            doc! { "fullDocument._total_debit": { "$eq": 0_i64 } }
+        );
+    }
+
+    #[test]
+    fn total_debit() {
+        let amounts =
+            [15_000, -10_000, -5_000].map(|units| (Id::new_random(), Amount::from_units(units)));
+        let transaction = Transaction {
+            date: Date::from_calendar_date(2024, Month::January, 1).unwrap(),
+            description: "Dinner".into(),
+            amounts: Map(amounts.into_iter().collect()),
+        };
+        assert_eq!(
+            transaction.derived_fields(),
+            doc! { "_account_count": 3_i64, "_total_debit": 15_000_i64 }
+        );
+        let query = SimpleQuery::range(Amount::from_units(10_000)..);
+        assert_eq!(
+            TransactionQuery::Total(query).to_document(),
+            doc! { "_total_debit": { "$gte": 10_000_i64 } }
+        );
+    }
+
+    #[test]
+    fn prefix_fields_account_count() {
+        let query = TransactionQuery::AccountCount(SimpleQuery::eq(1)).to_document();
+        assert_eq!(
+            prefix_fields(&query, "fullDocument"),
+            doc! { "fullDocument._account_count": { "$eq": 1_i64 } }
+        );
+    }
+
+    #[test]
+    fn prefix_fields_empty_any() {
+        let query = BooleanExpr::<WithGroupQuery<Transaction>>::Any(Vec::new()).to_document();
+        assert_eq!(query, doc! { "$expr": false });
+        assert_eq!(prefix_fields(&query, "fullDocument"), query);
+    }
+
+    fn regex(pattern: &str, options: &str) -> Bson {
+        Bson::RegularExpression(bson::Regex {
+            pattern: pattern.into(),
+            options: options.into(),
+        })
+    }
+
+    #[test]
+    fn string_normalized() {
+        // stored strings are normalized, so values written with combining characters are too
+        assert_eq!(
+            StringQuery::eq("Cafe\u{301}").to_document(),
+            doc! { "$eq": "Caf\u{e9}" }
+        );
+        assert_eq!(
+            StringQuery::starts_with("Cafe\u{301}").to_document(),
+            doc! { "$all": [regex("^Caf\u{e9}", "")] }
+        );
+    }
+
+    #[test]
+    fn name_exact() {
+        // names are compared against the stored folded names, in the same form
+        let account = Account {
+            name: "Cafe\u{301} NOIR".into(),
+            description: String::new(),
+            account_type: AccountType::Expense,
+            opening_balance: Amount::ZERO,
+            opening_date: None,
+            currency: None,
+        };
+        let expected = doc! { "_name_key": "caf\u{e9} noir" };
+        assert_eq!(account.derived_fields(), expected);
+        assert_eq!(
+            AccountQuery::NameExact("CAF\u{c9} Noir".into()).to_document(),
+            expected
+        );
+    }
+
+    #[test]
+    fn string_ignore_case() {
+        assert_eq!(
+            StringQuery::eq_ignore_case("Groceries").to_document(),
+            doc! { "$all": [regex("^Groceries\\z", "i")] }
+        );
+        assert_eq!(
+            StringQuery::eq_ignore_case("a.b").to_document(),
+            doc! { "$all": [regex("^a\\.b\\z", "i")] }
+        );
+        let query = StringQuery {
+            ignore_case: true,
+            ..SimpleQuery::default()
+                .and_ne("Rent".into())
+                .and_in(vec!["Food".into()])
+                .into()
+        };
+        assert_eq!(
+            query.to_document(),
+            doc! {
+                "$in": [regex("^Food\\z", "i")],
+                "$nin": [regex("^Rent\\z", "i")],
+            }
+        );
+    }
+
+    #[test]
+    fn string_contains() {
+        assert_eq!(
+            StringQuery::contains("").to_document(),
+            doc! { "$all": [regex("", "")] }
+        );
+        assert_eq!(
+            StringQuery::contains("15% (tip)").to_document(),
+            doc! { "$all": [regex(r"15% \(tip\)", "")] }
+        );
+        let query = StringQuery {
+            ignore_case: true,
+            ..StringQuery::contains(r"a_b.c\d|e")
+        };
+        assert_eq!(
+            query.to_document(),
+            doc! { "$all": [regex(r"a_b\.c\\d\|e", "i")] }
+        );
+    }
+
+    #[test]
+    fn string_like() {
+        assert_eq!(
+            StringQuery::like(r"%15\%").to_document(),
+            doc! { "$regex": r"^.*15%\z", "$options": "is" }
+        );
+        assert_eq!(
+            StringQuery::like(r"a_b.c(d)\_").to_document(),
+            doc! { "$regex": r"^a.b\.c\(d\)_\z", "$options": "is" }
+        );
+    }
+
+    #[test]
+    fn string_regex() {
+        let query = StringQuery::regex(r"^\d+ (rent|fee)$").unwrap();
+        assert_eq!(
+            query.to_document(),
+            doc! { "$all": [regex(r"^\d+ (rent|fee)$", "")] }
+        );
+        let query = StringQuery {
+            ignore_case: true,
+            ..query
+        };
+        assert_eq!(
+            query.to_document(),
+            doc! { "$all": [regex(r"^\d+ (rent|fee)$", "i")] }
+        );
+    }
+
+    #[test]
+    fn simple_query_operators() {
+        let query = SimpleQuery::between("a".to_owned(), "m".to_owned())
+            .and_gt("b".into())
+            .and_lt("l".into())
+            .and_ne("c".into())
+            .and_in(vec!["d".into()])
+            .and_nin(vec!["e".into()]);
+        assert_eq!(
+            query.to_document(),
+            doc! {
+                "$ne": "c",
+                "$gt": "b",
+                "$lt": "l",
+                "$gte": "a",
+                "$lte": "m",
+                "$in": ["d"],
+                "$nin": ["e"],
+                // `$ne` and `$nin` would otherwise match missing fields
+                "$exists": true,
+            }
+        );
+    }
+
+    #[test]
+    fn simple_query_is_null() {
+        let query = SimpleQuery::<String> {
+            is_null: Some(true),
+            ..Default::default()
+        };
+        assert_eq!(query.to_document(), doc! { "$exists": false });
+        let query = SimpleQuery {
+            is_null: Some(false),
+            ..SimpleQuery::eq("a".to_owned())
+        };
+        assert_eq!(query.to_document(), doc! { "$eq": "a", "$exists": true });
+    }
+
+    #[test]
+    fn not() {
+        let account = Id::new_random();
+        let query = !WithGroupQuery::<Transaction>::Other(TransactionQuery::Account(
+            SimpleQuery::eq(account),
+        ));
+        // the negation applies to the whole `$elemMatch`, not to each account
+        assert_eq!(
+            query.to_document(),
+            doc! { "$nor": [{ "amounts": { "$elemMatch": { "0": { "$eq": account } } } }] }
+        );
+    }
+
+    #[test]
+    fn date_range() {
+        let start = Date::from_calendar_date(2024, Month::January, 1).unwrap();
+        let end = Date::from_calendar_date(2024, Month::January, 31).unwrap();
+        let query = TransactionQuery::date_range(start, end);
+        let midnight = |date: Date| bson::DateTime::from(date.midnight().assume_utc());
+        // the end is included, so is compared with `$lte`, rather than `$lt`
+        assert_eq!(
+            query.to_document(),
+            doc! { "date": { "$gte": midnight(start), "$lte": midnight(end) } }
+        );
+    }
+}
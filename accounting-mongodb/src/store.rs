@@ -0,0 +1,195 @@
+//! Storage of all of the collections of a backend in a single MongoDB database.
+
+use std::sync::Arc;
+
+use accounting_core::{
+    backend::store::{Collections, Store, UnitOfWork},
+    error::{Error, Result},
+    public::{account::Account, transaction::Transaction},
+};
+use async_trait::async_trait;
+use futures::lock::Mutex;
+use mongodb::{
+    options::{ReadConcern, TransactionOptions},
+    Client, ClientSession, Database, IndexModel,
+};
+
+use crate::{
+    audit::MongoDbAuditLog,
+    collection::{MongoDbCollection, GROUP_FIELD},
+    query::NAME_KEY_FIELD,
+};
+
+const USERS: &str = "users";
+const GROUPS: &str = "groups";
+const ACCOUNTS: &str = "accounts";
+const TRANSACTIONS: &str = "transactions";
+const AUDIT: &str = "audit";
+
+/// All of the collections of a backend, stored in a single MongoDB database.
+///
+/// Units of work use multi-document transactions, which MongoDB only supports on replica sets
+/// and sharded clusters.
+pub struct MongoDbStore {
+    client: Client,
+    db: Database,
+    history: bool,
+}
+
+impl MongoDbStore {
+    /// Store the collections in the named database.
+    pub fn new(client: Client, db: &str) -> Self {
+        Self {
+            db: client.database(db),
+            client,
+            history: false,
+        }
+    }
+
+    /// Record the previous versions of updated and deleted objects, in a collection named after
+    /// the collection they belong to.
+    pub fn with_history(self) -> Self {
+        Self {
+            history: true,
+            ..self
+        }
+    }
+
+    /// Create the indexes the backend's queries rely on, if they don't already exist.
+    ///
+    /// This indexes each group's accounts by their names with case folded, for looking up an
+    /// account by name.
+    pub async fn create_indexes(&self) -> Result<()> {
+        let index = IndexModel::builder()
+            .keys(bson::doc! { GROUP_FIELD: 1, NAME_KEY_FIELD: 1 })
+            .build();
+        self.db
+            .collection::<bson::Document>(ACCOUNTS)
+            .create_index(index, None)
+            .await
+            .map_err(Error::backend)?;
+        Ok(())
+    }
+
+    /// Store the derived fields of all of the objects, for databases with objects written before
+    /// those fields were added, as with [`MongoDbCollection::store_derived_fields`].
+    pub async fn store_derived_fields(&self) -> Result<()> {
+        MongoDbCollection::<Account>::new(&self.db, ACCOUNTS)
+            .store_derived_fields()
+            .await?;
+        MongoDbCollection::<Transaction>::new(&self.db, TRANSACTIONS)
+            .store_derived_fields()
+            .await?;
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl Store for MongoDbStore {
+    fn collections(&self) -> Collections {
+        open_collections(&self.db, self.history, None)
+    }
+
+    async fn begin(&self) -> Result<Box<dyn UnitOfWork>> {
+        self.start_transaction(None).await
+    }
+
+    async fn snapshot(&self) -> Result<Box<dyn UnitOfWork>> {
+        let options = TransactionOptions::builder()
+            .read_concern(ReadConcern::snapshot())
+            .build();
+        self.start_transaction(options).await
+    }
+}
+
+impl MongoDbStore {
+    async fn start_transaction(
+        &self,
+        options: impl Into<Option<TransactionOptions>>,
+    ) -> Result<Box<dyn UnitOfWork>> {
+        let mut session = self
+            .client
+            .start_session(None)
+            .await
+            .map_err(Error::backend)?;
+        session
+            .start_transaction(options)
+            .await
+            .map_err(Error::backend)?;
+        Ok(Box::new(MongoDbUnitOfWork {
+            db: self.db.clone(),
+            history: self.history,
+            session: Arc::new(Mutex::new(session)),
+        }))
+    }
+}
+
+/// A unit of work, performed within a MongoDB transaction.
+struct MongoDbUnitOfWork {
+    db: Database,
+    history: bool,
+    session: Arc<Mutex<ClientSession>>,
+}
+
+#[async_trait]
+impl UnitOfWork for MongoDbUnitOfWork {
+    fn collections(&self) -> Collections {
+        open_collections(&self.db, self.history, Some(&self.session))
+    }
+
+    async fn commit(self: Box<Self>) -> Result<()> {
+        self.session
+            .lock()
+            .await
+            .commit_transaction()
+            .await
+            .map_err(Error::backend)
+    }
+
+    async fn abort(self: Box<Self>) -> Result<()> {
+        self.session
+            .lock()
+            .await
+            .abort_transaction()
+            .await
+            .map_err(Error::backend)
+    }
+}
+
+fn open_collections(
+    db: &Database,
+    history: bool,
+    session: Option<&Arc<Mutex<ClientSession>>>,
+) -> Collections {
+    Collections {
+        users: Box::new(open_collection(db, USERS, history, session)),
+        groups: Box::new(open_collection(db, GROUPS, history, session)),
+        accounts: Box::new(open_collection(db, ACCOUNTS, history, session)),
+        transactions: Box::new(open_collection(db, TRANSACTIONS, history, session)),
+        audit: Box::new(open_audit_log(db, session)),
+    }
+}
+
+fn open_collection<T>(
+    db: &Database,
+    name: &str,
+    history: bool,
+    session: Option<&Arc<Mutex<ClientSession>>>,
+) -> MongoDbCollection<T> {
+    let mut collection = MongoDbCollection::new(db, name);
+    if history {
+        collection = collection.with_history(db, &format!("{name}_history"));
+    }
+    if let Some(session) = session {
+        collection = collection.with_session(session.clone());
+    }
+    collection
+}
+
+fn open_audit_log(db: &Database, session: Option<&Arc<Mutex<ClientSession>>>) -> MongoDbAuditLog {
+    let audit = MongoDbAuditLog::new(db, AUDIT);
+    match session {
+        Some(session) => audit.with_session(session.clone()),
+        None => audit,
+    }
+}
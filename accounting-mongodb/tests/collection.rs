@@ -0,0 +1,158 @@
+//! Runs the shared [`Collection`](accounting_core::backend::collection::Collection) checks against
+//! a MongoDB database.
+//!
+//! The checks need a running server, given by the `ACCOUNTING_TEST_MONGODB_URI` environment
+//! variable, such as `mongodb://localhost:27017/?directConnection=true`, and are skipped if it
+//! isn't set. The server must be a member of a replica set, even one with a single member, so that
+//! changes can be watched. Each run uses a new database, which is dropped afterwards.
+
+use std::collections::BTreeSet;
+
+use accounting_core::{
+    backend::{
+        collection::Collection,
+        id::Id,
+        query::{Query, QueryOptions, WithGroupQuery},
+        testsuite::run_collection_tests,
+        user::{AccessLevel, Group, GroupQuery, Permissions, Role, WithGroup},
+    },
+    error::Error,
+    map::Map,
+    public::{amount::Amount, transaction::Transaction},
+};
+use accounting_mongodb::collection::MongoDbCollection;
+use bson::oid::ObjectId;
+use mongodb::IndexModel;
+use time::{Date, Month};
+
+/// A new database on the test server, or `None` if there is no test server.
+async fn test_database() -> Option<mongodb::Database> {
+    let Ok(uri) = std::env::var("ACCOUNTING_TEST_MONGODB_URI") else {
+        eprintln!("ACCOUNTING_TEST_MONGODB_URI is not set, skipping MongoDB tests");
+        return None;
+    };
+    let client = mongodb::Client::with_uri_str(uri)
+        .await
+        .expect("test server should be reachable");
+    Some(client.database(&format!("accounting-test-{}", ObjectId::new())))
+}
+
+#[tokio::test]
+async fn collection_tests() {
+    let Some(db) = test_database().await else {
+        return;
+    };
+    let mut collections = 0;
+    run_collection_tests(|| {
+        collections += 1;
+        let collection = MongoDbCollection::new(&db, &format!("transactions{collections}"))
+            .with_history(&db, &format!("history{collections}"));
+        async { collection }
+    })
+    .await;
+    db.drop(None).await.unwrap();
+}
+
+/// Check that `max_cost` catches queries using an index which still examine too many documents.
+#[tokio::test]
+async fn max_cost_index_scan() {
+    let Some(db) = test_database().await else {
+        return;
+    };
+    let mut collection = MongoDbCollection::<Transaction>::new(&db, "transactions");
+    let index = IndexModel::builder()
+        .keys(bson::doc! { "_group": 1 })
+        .build();
+    db.collection::<bson::Document>("transactions")
+        .create_index(index, None)
+        .await
+        .unwrap();
+    let (large, small) = (Id::new_random(), Id::new_random());
+    for (group, count) in [(large, 10), (small, 2)] {
+        for _ in 0..count {
+            let mut amounts = Map::default();
+            amounts.insert(Id::new_random(), Amount::ZERO);
+            let object = Transaction {
+                date: Date::from_calendar_date(2024, Month::January, 1).unwrap(),
+                description: "Rent".into(),
+                amounts,
+            };
+            collection
+                .create(WithGroup { group, object })
+                .await
+                .unwrap();
+        }
+    }
+
+    let options = QueryOptions {
+        max_cost: Some(5),
+        ..QueryOptions::default()
+    };
+    let query = [WithGroupQuery::Group(vec![small])];
+    assert_eq!(collection.query(&query, options).await.unwrap().len(), 2);
+    let query = [WithGroupQuery::Group(vec![large])];
+    assert!(matches!(
+        collection.query(&query, options).await,
+        Err(Error::QueryTooExpensive { .. })
+    ));
+    db.drop(None).await.unwrap();
+}
+
+/// Check that the query for the groups a user can access agrees with the permissions in memory.
+#[tokio::test]
+async fn user_can_read() {
+    let Some(db) = test_database().await else {
+        return;
+    };
+    let mut collection = MongoDbCollection::<Group>::new(&db, "groups");
+    let [none, read, write] = AccessLevel::ALL;
+    let (user, other) = (Id::new_random(), Id::new_random());
+    let permissions = [
+        (vec![], vec![], none),
+        (vec![], vec![], read),
+        (vec![(other, write)], vec![], write),
+        (vec![(user, write)], vec![], none),
+        (vec![(user, none)], vec![], write),
+        (vec![(user, read)], vec![], write),
+        (vec![], vec![(user, Role::Viewer)], write),
+        (vec![], vec![(user, Role::Member)], none),
+        (vec![(user, none)], vec![(user, Role::Viewer)], write),
+    ];
+    let mut ids = Vec::new();
+    for (users, roles, default) in permissions {
+        let object = Group {
+            name: "Books".into(),
+            permissions: Permissions {
+                users: Map(users.into_iter().collect()),
+                roles: Map(roles.into_iter().collect()),
+                default,
+            },
+            parent: None,
+        };
+        let group = Id::new_random();
+        ids.push(
+            collection
+                .create(WithGroup { group, object })
+                .await
+                .unwrap(),
+        );
+    }
+    let objects = collection.get_many(&ids).await.unwrap();
+
+    for level in AccessLevel::ALL {
+        let query = [WithGroupQuery::Other(GroupQuery::UserCanRead(user, level))];
+        let expected = objects
+            .iter()
+            .filter(|object| query[0].matches(object))
+            .map(|object| object.object.id)
+            .collect::<BTreeSet<_>>();
+        let actual = collection
+            .query_ids(&query, QueryOptions::default())
+            .await
+            .unwrap()
+            .into_iter()
+            .collect::<BTreeSet<_>>();
+        assert_eq!(actual, expected, "{level:?}");
+    }
+    db.drop(None).await.unwrap();
+}